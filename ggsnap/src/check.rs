@@ -0,0 +1,339 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+use std::fs;
+use chrono::prelude::*;
+use serde_json::{ self, Value };
+use ggsnap_utils::Config;
+use metrics;
+use preflight;
+use stats::{ self, SnapStat };
+
+/// Monitoring plugin states
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl State {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            State::Ok => 0,
+            State::Warning => 1,
+            State::Critical => 2,
+            State::Unknown => 3,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Ok => "OK",
+            State::Warning => "WARNING",
+            State::Critical => "CRITICAL",
+            State::Unknown => "UNKNOWN",
+        }
+    }
+
+    /// Order when results are combined, the
+    /// worst state is the result of the check.
+    fn rank(&self) -> u8 {
+        match *self {
+            State::Ok => 0,
+            State::Warning => 1,
+            State::Unknown => 2,
+            State::Critical => 3,
+        }
+    }
+}
+
+/// Warning and critical thresholds, a value at
+/// or above a threshold has that state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub warning: f64,
+    pub critical: f64,
+}
+
+impl Thresholds {
+    pub fn state(&self, value: f64) -> State {
+        if value >= self.critical {
+            State::Critical
+        }
+        else if value >= self.warning {
+            State::Warning
+        }
+        else {
+            State::Ok
+        }
+    }
+
+    /// Error text if warning threshold is above critical
+    pub fn validate(&self, name: &str) -> Result<(), String> {
+        if self.warning > self.critical {
+            Err(format!("warning {} threshold is above critical", name))
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    fn perfdata(&self, label: &str, value: f64, unit: &str) -> String {
+        format!("{}={}{};{};{};0", label, value, unit, self.warning, self.critical)
+    }
+}
+
+/// Result of one check with text and perfdata
+#[derive(Debug, PartialEq)]
+pub struct CheckResult {
+    pub state: State,
+    pub text: String,
+    pub perfdata: Vec<String>,
+}
+
+/// Age in seconds of newest snapshot on side
+pub fn check_age(side: &str, newest: Option<DateTime<Utc>>, now: DateTime<Utc>, th: &Thresholds) -> CheckResult {
+    match newest {
+        Some(t) => {
+            let age = now.signed_duration_since(t).num_seconds().max(0) as f64;
+            CheckResult {
+                state: th.state(age),
+                text: format!("newest {} snapshot {:.1}h old", side, age / 3600.0),
+                perfdata: vec![th.perfdata(&format!("{}_newest_age", side), age, "s")],
+            }
+        },
+        None => CheckResult {
+            state: State::Critical,
+            text: format!("no snapshots on {}", side),
+            perfdata: Vec::new(),
+        },
+    }
+}
+
+/// Number of snapshots that differ between master and slave
+pub fn check_drift(master: &SnapStat, slave: &SnapStat, th: &Thresholds) -> CheckResult {
+    let diff = master.number_diff(slave) as f64;
+
+    CheckResult {
+        state: th.state(diff),
+        text: format!("{} snapshots differ", diff),
+        perfdata: vec![th.perfdata("differing", diff, ""),
+                       format!("master_snapshots={};;;0", master.len()),
+                       format!("slave_snapshots={};;;0", slave.len())],
+    }
+}
+
+/// Geo-replication status per master node, Paused
+/// is a warning, other than Active and Passive critical.
+pub fn check_geo_replication(nodes: Result<Vec<(String, String)>, String>) -> CheckResult {
+    let nodes = match nodes {
+        Ok(n) => n,
+        Err(e) => return CheckResult { state: State::Critical, text: e, perfdata: Vec::new() },
+    };
+
+    let mut state = State::Ok;
+    let mut bad: Vec<String> = Vec::new();
+
+    for &(ref n, ref s) in nodes.iter() {
+        let s_state = match s.as_str() {
+            "Active" | "Passive" => State::Ok,
+            "Paused" => State::Warning,
+            _ => State::Critical,
+        };
+
+        if s_state != State::Ok {
+            bad.push(format!("{}: {}", n, s));
+        }
+        state = worst(state, s_state);
+    }
+
+    let text = if bad.len() > 0 {
+        format!("geo-replication {}", bad.join(", "))
+    }
+    else {
+        String::from("geo-replication Active/Passive")
+    };
+
+    CheckResult { state: state, text: text, perfdata: Vec::new() }
+}
+
+/// Result of last run, success and time of run
+pub fn check_last_run(last: Option<(bool, String)>) -> CheckResult {
+    match last {
+        Some((true, t)) => CheckResult { state: State::Ok, text: format!("last run {} OK", t), perfdata: Vec::new() },
+        Some((false, t)) => CheckResult { state: State::Critical, text: format!("last run {} failed", t), perfdata: Vec::new() },
+        None => CheckResult { state: State::Unknown, text: String::from("no result of last run found"), perfdata: Vec::new() },
+    }
+}
+
+fn worst(a: State, b: State) -> State {
+    if b.rank() > a.rank() { b } else { a }
+}
+
+/// Last run of session from last line
+/// of the session in JSON report
+pub fn last_run_from_report(report: &str, session: &str) -> Option<(bool, String)> {
+    let v = match report.lines()
+        .filter_map(|l| serde_json::from_str::<Value>(l).ok())
+        .filter(|v| v["session"].as_str() == Some(session))
+        .last() {
+        Some(v) => v,
+        None => return None,
+    };
+
+    match (v["success"].as_bool(), v["timestamp"].as_str()) {
+        (Some(s), Some(t)) => Some((s, t.to_string())),
+        _ => None,
+    }
+}
+
+/// Last run of session from Prometheus textfile
+pub fn last_run_from_metrics(text: &str, session: &str) -> Option<(bool, String)> {
+    let m = metrics::parse(text);
+    let labels = format!("{{session=\"{}\"}}", metrics::escape(session));
+    let success = m.get(&format!("ggsnap_last_run_success{}", labels)).map(|v| *v == 1.0);
+    let time = m.get(&format!("ggsnap_last_run_timestamp_seconds{}", labels)).map(|v| *v as i64);
+
+    match (success, time.and_then(|t| Utc.timestamp_opt(t, 0).single())) {
+        (Some(s), Some(t)) => Some((s, t.to_rfc3339())),
+        _ => None,
+    }
+}
+
+/// Combines results to plugin output line and state
+pub fn output(results: &[CheckResult]) -> (State, String) {
+    let mut state = State::Ok;
+    let mut texts: Vec<String> = Vec::new();
+    let mut perf: Vec<String> = Vec::new();
+
+    for r in results {
+        state = worst(state, r.state);
+        texts.push(r.text.clone());
+        perf.extend(r.perfdata.iter().cloned());
+    }
+
+    if perf.len() > 0 {
+        (state, format!("GGSNAP {} - {} | {}", state.name(), texts.join(", "), perf.join(" ")))
+    }
+    else {
+        (state, format!("GGSNAP {} - {}", state.name(), texts.join(", ")))
+    }
+}
+
+/// Plugin output line for errors that stop the
+/// check before it runs, like config errors.
+/// Error text on several lines is joined to one line.
+pub fn unknown(text: &str) -> (State, String) {
+    let lines: Vec<String> = text.lines()
+        .map(|l| l.replace("Master: ", "").trim().to_string())
+        .filter(|l| l.len() > 0)
+        .collect();
+
+    output(&[CheckResult { state: State::Unknown, text: lines.join(", "), perfdata: Vec::new() }])
+}
+
+/// Runs all checks for ggsnap check and returns
+/// plugin output line and state. report_path is
+/// the path of report_file if it is set.
+pub fn run(config: &Config, age: &Thresholds, drift: &Thresholds, report_path: Option<String>) -> (State, String) {
+    let now = Utc::now();
+    let mut results: Vec<CheckResult> = Vec::new();
+
+    match (stats::get_statistics(&config), stats::get_slave_statistics(&config)) {
+        (Ok(m), Ok(s)) => {
+            results.push(check_age("master", m.newest_time(), now, age));
+            results.push(check_age("slave", s.newest_time(), now, age));
+            results.push(check_drift(&m, &s, drift));
+        },
+        (Err(e), _) | (_, Err(e)) => results.push(CheckResult {
+            state: State::Unknown,
+            text: e.replace("Master: ", "").replace('\n', " ").trim().to_string(),
+            perfdata: Vec::new(),
+        }),
+    }
+
+    results.push(check_geo_replication(preflight::geo_replication_nodes(&config)));
+
+    // Last run is only checked if a report or metrics file is written
+    let metrics_file = config.general.metrics_file.clone().unwrap_or(String::new());
+    let session = ggsnap_utils::session_name(&config);
+    match report_path {
+        Some(p) => results.push(check_last_run(fs::read_to_string(p).ok().and_then(|r| last_run_from_report(&r, &session)))),
+        None if metrics_file.len() > 0 => {
+            results.push(check_last_run(fs::read_to_string(metrics_file).ok().and_then(|m| last_run_from_metrics(&m, &session))))
+        },
+        None => (),
+    }
+
+    output(&results)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn check_results() {
+        let now = Utc::now();
+        let age = Thresholds { warning: 26.0 * 3600.0, critical: 50.0 * 3600.0 };
+        assert_eq!(check_age("master", Some(now - Duration::hours(2)), now, &age).state, State::Ok);
+        let r = check_age("slave", Some(now - Duration::hours(30)), now, &age);
+        assert_eq!(r.state, State::Warning);
+        assert_eq!(r.perfdata, vec![String::from("slave_newest_age=108000s;93600;180000;0")]);
+        assert_eq!(check_age("slave", None, now, &age).state, State::Critical);
+
+        let geo = check_geo_replication(Ok(vec![(String::from("n1"), String::from("Active")),
+                                                (String::from("n2"), String::from("Faulty"))]));
+        assert_eq!(geo.state, State::Critical);
+        assert_eq!(geo.text, "geo-replication n2: Faulty");
+
+        let (state, line) = output(&[r, check_last_run(None)]);
+        assert_eq!(state, State::Unknown);
+        assert_eq!(state.exit_code(), 3);
+        assert_eq!(line, "GGSNAP UNKNOWN - newest slave snapshot 30.0h old, no result of last run found | slave_newest_age=108000s;93600;180000;0");
+
+        assert_eq!(unknown("Master: ConfigValueErr: Parameter error in config file\n    Error in parameter: lock_policy\n"),
+                   (State::Unknown, String::from("GGSNAP UNKNOWN - ConfigValueErr: Parameter error in config file, Error in parameter: lock_policy")));
+        assert!(age.validate("age").is_ok());
+        assert_eq!(Thresholds { warning: 3.0, critical: 2.0 }.validate("drift"),
+                   Err(String::from("warning drift threshold is above critical")));
+    }
+
+    #[test]
+    fn last_run_of_session() {
+        let report = "{\"timestamp\":\"2018-02-16T12:04:38+00:00\",\"session\":\"vol1 root@slave::svol1\",\"success\":true}\n\
+                      {\"timestamp\":\"2018-02-16T13:04:38+00:00\",\"session\":\"vol2 root@slave::svol2\",\"success\":true}\n\
+                      {\"timestamp\":\"2018-02-17T12:04:38+00:00\",\"session\":\"vol1 root@slave::svol1\",\"success\":false}\n\
+                      {\"timestamp\":\"2018-02-17T13:04:38+00:00\",\"session\":\"vol2 root@slave::svol2\",\"success\":true}\n";
+        assert_eq!(last_run_from_report(report, "vol1 root@slave::svol1"), Some((false, String::from("2018-02-17T12:04:38+00:00"))));
+        assert_eq!(last_run_from_report(report, "vol2 root@slave::svol2"), Some((true, String::from("2018-02-17T13:04:38+00:00"))));
+        assert_eq!(last_run_from_report(report, "vol3 root@slave::svol3"), None);
+
+        let metrics = "ggsnap_last_run_success{session=\"s1\"} 1\nggsnap_last_run_timestamp_seconds{session=\"s1\"} 0\n\
+                       ggsnap_last_run_success{session=\"s2\"} 0\nggsnap_last_run_timestamp_seconds{session=\"s2\"} 60\n";
+        assert_eq!(last_run_from_metrics(metrics, "s1"), Some((true, String::from("1970-01-01T00:00:00+00:00"))));
+        assert_eq!(last_run_from_metrics(metrics, "s2"), Some((false, String::from("1970-01-01T00:01:00+00:00"))));
+        assert_eq!(last_run_from_metrics(metrics, "s3"), None);
+    }
+}
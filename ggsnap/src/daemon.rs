@@ -0,0 +1,245 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::{ thread, time };
+use chrono::prelude::*;
+use clap::ArgMatches;
+use libc;
+use ggsnap_utils::{ systemd, Config };
+use ggsnap_utils::cron::JobSchedule;
+use ggsnap_utils::state::{ self, DaemonState };
+use ggsnap_utils::timezone::config_timezone;
+use http;
+use status;
+
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// Default state file for daemon mode
+static DEFAULT_STATE_FILE: &'static str = "ggsnap_daemon_state.toml";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum JobKind {
+    Snapshot,
+    Prune,
+}
+
+/// A scheduled job and when it runs next
+struct Job {
+    kind: JobKind,
+    schedule: JobSchedule,
+    next: Option<DateTime<Utc>>,
+}
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if signal == libc::SIGHUP {
+        RELOAD.store(true, Ordering::SeqCst);
+    }
+    else {
+        TERMINATE.store(true, Ordering::SeqCst);
+    }
+}
+
+fn install_signal_handlers() {
+    unsafe {
+        libc::signal(libc::SIGTERM, handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGINT, handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGHUP, handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+fn state_file(config: &Config) -> String {
+    match config.schedule {
+        Some(ref s) => s.state_file.clone().unwrap_or(String::from(DEFAULT_STATE_FILE)),
+        None => String::from(DEFAULT_STATE_FILE),
+    }
+}
+
+fn last_run(state: &DaemonState, kind: JobKind) -> Option<DateTime<Utc>> {
+    match kind {
+        JobKind::Snapshot => DaemonState::last_run(&state.last_snapshot),
+        JobKind::Prune => DaemonState::last_run(&state.last_prune),
+    }
+}
+
+/// Creates jobs from [schedule] in config and calculates
+/// next run. If a run was missed while daemon was not
+/// running and catch_up is true the job runs at once,
+/// missed runs are only caught up once.
+fn jobs(config: &Config, state: &DaemonState) -> Result<Vec<Job>, String> {
+    let schedule = match config.schedule {
+        Some(ref s) => s,
+        None => return Err(String::from("Master: Error: Missing [schedule] in config file")),
+    };
+    let tz = config_timezone(config);
+    let now = Utc::now();
+    let mut jobs: Vec<Job> = Vec::new();
+
+    let snapshot = JobSchedule::from_config(&schedule.snapshot_cron, schedule.snapshot_interval, "snapshot");
+    let prune = JobSchedule::from_config(&schedule.prune_cron, schedule.prune_interval, "prune");
+
+    for (kind, sched) in vec![(JobKind::Snapshot, snapshot), (JobKind::Prune, prune)] {
+        let sched = match sched {
+            Ok(Some(s)) => s,
+            Ok(None) => continue,
+            Err(e) => return Err(format!("Master: Error: {}", e)),
+        };
+
+        let next = match last_run(state, kind).and_then(|l| sched.next_after(l, &tz)) {
+            Some(n) if n <= now && schedule.catch_up.unwrap_or(true) => Some(now),
+            Some(n) if n > now => Some(n),
+            _ => sched.next_after(now, &tz),
+        };
+
+        jobs.push(Job { kind: kind, schedule: sched, next: next });
+    }
+
+    if jobs.iter().all(|j| j.kind != JobKind::Snapshot) {
+        return Err(String::from("Master: Error: Missing snapshot_cron or snapshot_interval in [schedule]"));
+    }
+
+    Ok(jobs)
+}
+
+fn print_next(job: &Job) {
+    match job.next {
+        Some(n) => println!("Master: Daemon: Next {:?} run: {}", job.kind,
+                            n.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")),
+        None => println!("Master: Daemon: {:?} schedule never runs", job.kind),
+    }
+}
+
+/// Runs ggsnap as a daemon, snapshots and pruning run
+/// according to [schedule] in config file.
+/// SIGTERM and SIGINT stops daemon after a running job
+/// is finished and SIGHUP reloads config file.
+pub fn run(matches: &ArgMatches, mut config: Config) -> Result<(), ()> {
+    install_signal_handlers();
+
+    let mut state = match state::read_daemon_state(&state_file(&config)) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("Master: {}", e);
+            return Err(());
+        }
+    };
+
+    let mut jobs = match jobs(&config, &state) {
+        Ok(j) => j,
+        Err(e) => {
+            println!("{}", e);
+            return Err(());
+        }
+    };
+
+    if let Some(address) = config.schedule.as_ref().and_then(|s| s.http_listen.clone()) {
+        if address.len() > 0 {
+            status::keep_metrics();
+            if let Err(e) = http::serve(&address) {
+                println!("{}", e);
+                return Err(());
+            }
+            println!("Master: Daemon: Serving /metrics and /status on: {}", address);
+        }
+    }
+
+    println!("Master: Daemon started");
+    systemd::ready();
+    systemd::status("Waiting for next job");
+    for j in jobs.iter() {
+        print_next(j);
+    }
+
+    loop {
+        if TERMINATE.load(Ordering::SeqCst) {
+            systemd::stopping();
+            println!("Master: Daemon stopped");
+            return Ok(());
+        }
+
+        if RELOAD.swap(false, Ordering::SeqCst) {
+            systemd::reloading();
+            println!("Master: Daemon: Reloading config file");
+            let reloaded = match super::load_config(matches) {
+                Ok((c, config_file_exist)) => {
+                    if !config_file_exist {
+                        println!("{}", super::CONFIG_NOT_FOUND);
+                    }
+
+                    match super::missing_config_values(&c) {
+                        Some(e) => Err(e),
+                        None => match self::jobs(&c, &state) {
+                            Ok(j) => Ok((c, j)),
+                            Err(e) => Err(e),
+                        },
+                    }
+                },
+                Err(e) => Err(e),
+            };
+
+            match reloaded {
+                Ok((c, j)) => {
+                    config = c;
+                    jobs = j;
+                    for j in jobs.iter() {
+                        print_next(j);
+                    }
+                },
+                Err(e) => println!("{}\nMaster: Daemon: Keeping previous config", e),
+            }
+            systemd::ready();
+        }
+
+        let now = Utc::now();
+        let tz = config_timezone(&config);
+
+        for j in jobs.iter_mut() {
+            match j.next {
+                Some(n) if n <= now => (),
+                _ => continue,
+            }
+
+            let start = Utc::now();
+            let _ = match j.kind {
+                JobKind::Snapshot => {
+                    state.last_snapshot = DaemonState::run_time(start);
+                    super::create_snapshot(&config, None)
+                },
+                JobKind::Prune => {
+                    state.last_prune = DaemonState::run_time(start);
+                    super::prune_snapshots(&config)
+                },
+            };
+
+            if let Err(e) = state::write_daemon_state(&state_file(&config), &state) {
+                println!("Master: Daemon: {}", e);
+            }
+
+            j.next = j.schedule.next_after(Utc::now(), &tz);
+            print_next(j);
+            systemd::status("Waiting for next job");
+        }
+
+        systemd::watchdog();
+        thread::sleep(time::Duration::from_secs(1));
+    }
+}
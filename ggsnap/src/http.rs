@@ -0,0 +1,127 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+use std::io::{ Read, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::thread;
+use std::time::Duration;
+use status;
+
+/// Largest request that is read
+const MAX_REQUEST: usize = 8192;
+
+/// Path of request from request line,
+/// ex: GET /metrics HTTP/1.1
+fn request_path(request: &str) -> Result<&str, u16> {
+    let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(p)) | (Some("HEAD"), Some(p)) => Ok(p.split('?').next().unwrap_or(p)),
+        (Some(_), Some(_)) => Err(405),
+        _ => Err(400),
+    }
+}
+
+/// Status code, content type and body for request
+fn response(request: &str) -> (u16, &'static str, String) {
+    match request_path(request) {
+        Ok("/metrics") => (200, "text/plain; version=0.0.4", status::metrics_text()),
+        Ok("/status") => (200, "application/json", status::status_json()),
+        Ok(_) => (404, "text/plain", String::from("Not found, use /metrics or /status\n")),
+        Err(405) => (405, "text/plain", String::from("Method not allowed\n")),
+        Err(c) => (c, "text/plain", String::from("Bad request\n")),
+    }
+}
+
+fn reason(code: u16) -> &'static str {
+    match code {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Bad Request",
+    }
+}
+
+fn handle(mut stream: TcpStream) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+
+    let mut request: Vec<u8> = Vec::new();
+    let mut buf = [0; 1024];
+
+    while request.len() < MAX_REQUEST {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+
+        if request.windows(4).any(|w| w == b"\r\n\r\n") {
+            break;
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request).to_string();
+    let (code, content_type, body) = response(&request);
+    let head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                       code, reason(code), content_type, body.len());
+
+    let _ = stream.write_all(head.as_bytes());
+    if !request.starts_with("HEAD") {
+        let _ = stream.write_all(body.as_bytes());
+    }
+}
+
+/// Starts HTTP server on address, ex: 127.0.0.1:9464,
+/// serving /metrics and /status in a background thread.
+pub fn serve(address: &str) -> Result<(), String> {
+    let listener = match TcpListener::bind(address) {
+        Ok(l) => l,
+        Err(e) => return Err(format!("Master: Error listening on: {}: {}", address, e)),
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Ok(s) = stream {
+                handle(s);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_requests() {
+        assert_eq!(request_path("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n"), Ok("/metrics"));
+        assert_eq!(request_path("GET /status?x=1 HTTP/1.0\r\n\r\n"), Ok("/status"));
+        assert_eq!(request_path("POST /status HTTP/1.1\r\n\r\n"), Err(405));
+        assert_eq!(request_path(""), Err(400));
+
+        assert_eq!(response("GET / HTTP/1.1\r\n\r\n").0, 404);
+        let (code, content_type, body) = response("GET /status HTTP/1.1\r\n\r\n");
+        assert_eq!((code, content_type), (200, "application/json"));
+        assert!(body.contains("\"geo_replication_paused\":false"));
+    }
+}
@@ -0,0 +1,288 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+
+use chrono::prelude::*;
+use serde_json::{ self, Value };
+use ggsnap_utils::{ Config, HostType };
+use ggsnap_utils::tiers::{ self, Period, Tier };
+use stats::{ SnapStat, Snapshot };
+
+/// Output formats for --info
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Snapshots and retention tiers on one cluster
+struct Side<'a> {
+    name: &'static str,
+    stats: &'a SnapStat,
+    periods: Vec<Period>,
+    only_here: Vec<String>,
+}
+
+impl<'a> Side<'a> {
+    /// Periods in tier with at least one snapshot
+    /// and total number of periods in tier
+    fn tier_count(&self, tier: Tier) -> (usize, usize) {
+        let periods: Vec<&Period> = self.periods.iter().filter(|p| p.tier == tier).collect();
+        (periods.iter().filter(|p| !p.snapshots.is_empty()).count(), periods.len())
+    }
+
+    fn gaps(&self, tier: Tier) -> Vec<&Period> {
+        self.periods.iter().filter(|p| p.tier == tier && p.snapshots.is_empty()).collect()
+    }
+}
+
+static TIERS: [Tier; 3] = [Tier::Daily, Tier::Weekly, Tier::Monthly];
+
+fn period_text(p: &Period) -> String {
+    if p.start == p.end {
+        p.start.format("%Y-%m-%d").to_string()
+    }
+    else {
+        format!("{} - {}", p.start.format("%Y-%m-%d"), p.end.format("%Y-%m-%d"))
+    }
+}
+
+fn snap_text(snap: Option<&Snapshot>) -> &str {
+    match snap {
+        Some(s) => &s.name,
+        None => "None",
+    }
+}
+
+fn list_text(names: &[String]) -> String {
+    if names.is_empty() {
+        String::from(" None")
+    }
+    else {
+        names.iter().map(|n| format!("\n    {}", n)).collect::<Vec<String>>().concat()
+    }
+}
+
+fn side_text(side: &Side) -> String {
+    let mut text = format!("Total number of snapshots on {0} cluster: {1}
+Newest snapshot on {0} cluster: {2}
+Oldest snapshot on {0} cluster: {3}
+Retention tiers on {0} cluster (periods with snapshot / target):",
+                           side.name, side.stats.len(), snap_text(side.stats.newest_snap()),
+                           snap_text(side.stats.oldest_snap()));
+
+    for t in TIERS.iter() {
+        let (count, target) = side.tier_count(*t);
+        text.push_str(&format!("\n    {:<8} {} / {}", format!("{}:", t.name()), count, target));
+    }
+
+    text.push_str(&format!("\nGaps on {} cluster:", side.name));
+    let mut gaps = false;
+    for t in TIERS.iter() {
+        for p in side.gaps(*t) {
+            text.push_str(&format!("\n    {:<8} {}", format!("{}:", t.name()), period_text(p)));
+            gaps = true;
+        }
+    }
+    if !gaps {
+        text.push_str(" None");
+    }
+
+    text.push_str(&format!("\nSnapshots only on {} cluster:{}", side.name, list_text(&side.only_here)));
+    text.push_str(&format!("\nSnapshots on {} cluster not created by ggsnap:{}",
+                           side.name, list_text(side.stats.others())));
+    text
+}
+
+fn format_text(master: &Side, slave: &Side) -> String {
+    format!("==================================================================================
+=               Snapshot statistics (Snapshots created by ggsnap)                =
+==================================================================================
+{}
+
+{}
+
+Number of snapshots that differ between master and slave: {}
+==================================================================================
+",
+            side_text(master), side_text(slave), master.only_here.len() + slave.only_here.len())
+}
+
+fn side_json(side: &Side) -> Value {
+    let mut tiers = serde_json::Map::new();
+
+    for t in TIERS.iter() {
+        let (count, target) = side.tier_count(*t);
+        let periods: Vec<Value> = side.periods.iter().filter(|p| p.tier == *t).map(|p| json!({
+            "start": p.start.format("%Y-%m-%d").to_string(),
+            "end": p.end.format("%Y-%m-%d").to_string(),
+            "snapshots": p.snapshots,
+        })).collect();
+        let gaps: Vec<String> = side.gaps(*t).iter().map(|p| period_text(p)).collect();
+
+        tiers.insert(t.name().to_string(), json!({
+            "count": count,
+            "target": target,
+            "gaps": gaps,
+            "periods": periods,
+        }));
+    }
+
+    json!({
+        "total": side.stats.len(),
+        "newest": side.stats.newest_snap().map(|s| s.name.clone()),
+        "oldest": side.stats.oldest_snap().map(|s| s.name.clone()),
+        "tiers": tiers,
+        "only_here": side.only_here,
+        "not_created_by_ggsnap": side.stats.others(),
+    })
+}
+
+fn format_json(master: &Side, slave: &Side) -> String {
+    let info = json!({
+        "master": side_json(master),
+        "slave": side_json(slave),
+        "differing": master.only_here.len() + slave.only_here.len(),
+    });
+
+    format!("{}\n", serde_json::to_string_pretty(&info).unwrap_or(String::new()))
+}
+
+/// Quotes a csv field if needed
+fn csv_field(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace("\"", "\"\""))
+    }
+    else {
+        field.to_string()
+    }
+}
+
+fn csv_row(fields: &[&str]) -> String {
+    let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    format!("{}\n", row.join(","))
+}
+
+/// One row per period, snapshot only on one side
+/// and snapshot not created by ggsnap. Periods
+/// without a snapshot are gaps.
+fn format_csv(master: &Side, slave: &Side) -> String {
+    let mut csv = csv_row(&["side", "category", "period_start", "period_end", "count", "snapshot"]);
+
+    for side in [master, slave].iter() {
+        csv.push_str(&csv_row(&[side.name, "total", "", "", &side.stats.len().to_string(), ""]));
+
+        for p in side.periods.iter() {
+            let newest = p.snapshots.first().cloned().unwrap_or(String::new());
+            csv.push_str(&csv_row(&[side.name, p.tier.name(),
+                                    &p.start.format("%Y-%m-%d").to_string(),
+                                    &p.end.format("%Y-%m-%d").to_string(),
+                                    &p.snapshots.len().to_string(), &newest]));
+        }
+
+        for s in side.only_here.iter() {
+            csv.push_str(&csv_row(&[side.name, "only_here", "", "", "", s]));
+        }
+
+        for s in side.stats.others().iter() {
+            csv.push_str(&csv_row(&[side.name, "not_created_by_ggsnap", "", "", "", s]));
+        }
+    }
+
+    csv
+}
+
+/// Statistics on snapshots on master and slave with
+/// retention tiers from today in config timezone.
+pub fn format(config: &Config, master: &SnapStat, slave: &SnapStat, today: NaiveDate, format: Format) -> String {
+    let master_side = Side {
+        name: "master",
+        stats: master,
+        periods: tiers::snapshot_periods(config, &master.names(), &HostType::Master, today),
+        only_here: master.missing_in(slave),
+    };
+    let slave_side = Side {
+        name: "slave",
+        stats: slave,
+        periods: tiers::snapshot_periods(config, &slave.names(), &HostType::Slave, today),
+        only_here: slave.missing_in(master),
+    };
+
+    match format {
+        Format::Text => format_text(&master_side, &slave_side),
+        Format::Json => format_json(&master_side, &slave_side),
+        Format::Csv => format_csv(&master_side, &slave_side),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info_formats() {
+        let mut config = Config::default_config();
+        config.snapshot.snapshot_name_prefix = Some(String::from("snap"));
+        config.snapshot.master_volume = Some(String::from("vol"));
+        config.snapshot.slave_volume = Some(String::from("vol"));
+        config.snapshot.number_days_every_day = 2;
+        config.snapshot.number_weeks_with_one = 1;
+        config.snapshot.number_months_total = 0;
+
+        let master = SnapStat::new(String::from("snap_vol_20180216_120438
+snap_vol_20180212_101635
+before_upgrade"), &config, HostType::Master);
+        let slave = SnapStat::new(String::from("snap_vol_20180216_120438"), &config, HostType::Slave);
+        let today = NaiveDate::from_ymd_opt(2018, 2, 16).unwrap();
+
+        let text = format(&config, &master, &slave, today, Format::Text);
+        assert!(text.contains("    daily:   1 / 2\n    weekly:  1 / 1\n    monthly: 0 / 0\nGaps on master cluster:\n    daily:   2018-02-15\n"));
+        assert!(text.contains("Snapshots only on master cluster:\n    snap_vol_20180212_101635\n"));
+        assert!(text.contains("Snapshots only on slave cluster: None\n"));
+        assert!(text.contains("Snapshots on master cluster not created by ggsnap:\n    before_upgrade\n"));
+        assert!(text.contains("differ between master and slave: 1\n"));
+
+        let json: Value = serde_json::from_str(&format(&config, &master, &slave, today, Format::Json)).unwrap();
+        assert_eq!(json["master"]["tiers"]["daily"]["gaps"], json!(["2018-02-15"]));
+        assert_eq!(json["slave"]["tiers"]["weekly"]["gaps"], json!(["2018-02-08 - 2018-02-14"]));
+        assert_eq!(json["master"]["not_created_by_ggsnap"], json!(["before_upgrade"]));
+        assert_eq!(json["differing"], json!(1));
+
+        let csv = format(&config, &master, &slave, today, Format::Csv);
+        assert!(csv.starts_with("side,category,period_start,period_end,count,snapshot\nmaster,total,,,2,\n"));
+        assert!(csv.contains("master,weekly,2018-02-08,2018-02-14,1,snap_vol_20180212_101635\n"));
+        assert!(csv.contains("slave,daily,2018-02-15,2018-02-15,0,\n"));
+        assert!(csv.contains("master,not_created_by_ggsnap,,,,before_upgrade\n"));
+    }
+}
@@ -22,345 +22,661 @@ extern crate chrono;
 extern crate clap;
 extern crate ggsnap_utils;
 extern crate lettre;
-
+extern crate libc;
+extern crate native_tls;
+#[macro_use]
+extern crate serde_json;
+
+mod check;
+mod daemon;
+mod http;
+mod info;
+mod metrics;
+mod preflight;
+mod smtp;
 mod stats;
+mod status;
+mod webhook;
 
 use chrono::prelude::*;
-use clap::{Arg, ArgMatches, App};
+use clap::{Arg, ArgMatches, App, SubCommand};
 use std::process::Command;
-use ggsnap_utils::{get_config, Config, ConfigReadErr };
-use std::{ thread, time };
+use ggsnap_utils::{get_config, hook, lock, logfile, mail, notify, pin, systemd, timezone, Config, ConfigReadErr, HostType };
+use ggsnap_utils::event::{ self, Event, EventLog, Level };
+use ggsnap_utils::report::RunReport;
+use ggsnap_utils::syslog::{ self, Syslog };
 use std::path::Path;
 use std::fs::OpenOptions;
 use std::io::{ Write, BufWriter };
-use lettre::smtp::authentication::{ Credentials, Mechanism };
-use lettre::{SimpleSendableEmail, EmailTransport, EmailAddress, SmtpTransport};
 
 /// Parses command line arguments and
 /// checks that configuration is correct
 fn main() {
     let matches = arg_matches();
-    
-    if matches.is_present("VOLUME")   || matches.is_present("SLAVE") ||
-       matches.is_present("USER")     || matches.is_present("SLAVE_HOST") ||
-       matches.is_present("SNAPSHOT") || matches.is_present("INFO") {
 
-        let mut _config: Config = Config::default_config();
-        let mut config_file_exist = true;
-        let mut config_err = false;
-        let mut config_err_text: String = String::new();
-        _config = match get_config() {
-            Ok(c) => c,
-            Err((e, e_str)) => {
-                if e == ConfigReadErr::ConfigNotFound {
-                    println!("Master: {:?}: Config file not found, using default values", e);
-                    config_file_exist = false;
-                    Config::default_config()
-                }
-                else if e == ConfigReadErr::ConfigValueErr {
-                    println!("Master: {:?}: Parameter error in config file\n{}", e, e_str);
-                    std::process::exit(1);
-                }
-                else {
-                    println!("Master: {:?}: Error reading config file\n{}", e, e_str);
-                    std::process::exit(1);
-                }
-            },
+    if let Some(pin_matches) = matches.subcommand_matches("pin") {
+        let (config, _) = read_config(&matches);
+
+        let res = if pin_matches.is_present("LIST") {
+            list_pinned_snapshots(&config)
+        }
+        else {
+            pin_snapshot(&config, pin_matches.value_of("NAME").unwrap(), pin_matches.value_of("UNTIL"))
         };
 
-        match matches.value_of("VOLUME") {
-            Some(v) => _config.snapshot.master_volume = Some(String::from(v)),
-            None => (),
+        if res.is_err() {
+            std::process::exit(1);
         }
+    }
+    else if let Some(unpin_matches) = matches.subcommand_matches("unpin") {
+        let (config, _) = read_config(&matches);
 
-        match matches.value_of("SLAVE") {
-            Some(v) => _config.snapshot.slave_volume = Some(String::from(v)),
-            None => (),
+        if unpin_snapshot(&config, unpin_matches.value_of("NAME").unwrap()).is_err() {
+            std::process::exit(1);
         }
+    }
+    else if matches.subcommand_matches("daemon").is_some() {
+        let (config, config_file_exist) = read_config(&matches);
 
-        match matches.value_of("USER") {
-            Some(v) => _config.snapshot.slave_user = Some(String::from(v)),
-            None => (),
+        if let Some(e) = missing_config_values(&config) {
+            exit_missing_config_values(&e, config_file_exist);
         }
 
-        match matches.value_of("SLAVE_HOST") {
-            Some(v) => _config.snapshot.slave_hostname = Some(String::from(v)),
-            None => (),
+        if daemon::run(&matches, config).is_err() {
+            std::process::exit(1);
         }
+    }
+    else if let Some(check_matches) = matches.subcommand_matches("check") {
+        std::process::exit(run_check(&matches, &check_matches));
+    }
+    else if let Some(snapshot_matches) = matches.subcommand_matches("snapshot") {
+        let (config, config_file_exist) = read_config(&matches);
 
-        if _config.snapshot.slave_volume.is_none() {
-            _config.snapshot.slave_volume = _config.snapshot.master_volume.clone();
+        if let Some(e) = missing_config_values(&config) {
+            exit_missing_config_values(&e, config_file_exist);
         }
 
-        if _config.snapshot.snapshot_name_prefix.is_none() {
-            let c = Config::default_config();
-            _config.snapshot.snapshot_name_prefix = c.snapshot.snapshot_name_prefix.clone();
+        if create_snapshot(&config, snapshot_matches.value_of("LABEL")).is_err() {
+            std::process::exit(1);
         }
+    }
+    else if matches.is_present("VOLUME")   || matches.is_present("SLAVE") ||
+       matches.is_present("USER")     || matches.is_present("SLAVE_HOST") ||
+       matches.is_present("SNAPSHOT") || matches.is_present("INFO") {
 
-	if _config.snapshot.delay_after_pause_before_snapshot.is_none() {
-            let c = Config::default_config();
-	    _config.snapshot.delay_after_pause_before_snapshot = c.snapshot.delay_after_pause_before_snapshot.clone();
-	}
+        let (config, config_file_exist) = read_config(&matches);
+        let config_err_text = missing_config_values(&config);
 
-	if _config.snapshot.delay_resume_geo_replication.is_none() {
-            let c = Config::default_config();
-	    _config.snapshot.delay_resume_geo_replication = c.snapshot.delay_resume_geo_replication.clone();
-	}
+        if matches.is_present("INFO") && 
+           config.snapshot.slave_hostname.is_some() {
+            let format = info::Format::from_name(matches.value_of("FORMAT").unwrap_or("text")).unwrap();
+            let success = print_statistics(&config, format);
 
-        if _config.snapshot.master_volume.is_none() {
-            config_err_text = String::from("Error: Missing config value master volume name: master_volume");
-            config_err = true;
+            if success.is_err() {
+                std::process::exit(1);
+            }
+        }
+        else if let Some(e) = config_err_text {
+            exit_missing_config_values(&e, config_file_exist);
         }
 
-        if _config.snapshot.slave_volume.is_none() {
-            if config_err_text.len() == 0 {
-                config_err_text = String::from("Error: Missing config value slave volume name: slave_volume");
-            }
-            else {
-                config_err_text = format!("{}\nError: Missing config value slave volume name: slave_volume", config_err_text);
+        if !matches.is_present("INFO") {
+            let res = create_snapshot(&config, None);
+
+            if res.is_err() {
+                std::process::exit(1);
             }
-            config_err = true;
         }
+    }
+}
 
-        if _config.snapshot.slave_user.is_none() {
-            if config_err_text.len() == 0 {
-                config_err_text = String::from("Error: Missing config value slave user name: slave_user");
-            }
-            else {
-                config_err_text = format!("{}\nError: Missing config value slave user name: slave_user", config_err_text);
-            }
-            config_err = true;
+/// Checks that all values needed to create
+/// snapshots are set, returns error text
+/// describing missing values.
+fn missing_config_values(config: &Config) -> Option<String> {
+    let mut config_err_text: String = String::new();
+
+    if config.snapshot.master_volume.is_none() {
+        config_err_text = String::from("Error: Missing config value master volume name: master_volume");
+    }
+
+    if config.snapshot.slave_volume.is_none() {
+        if config_err_text.len() == 0 {
+            config_err_text = String::from("Error: Missing config value slave volume name: slave_volume");
         }
+        else {
+            config_err_text = format!("{}\nError: Missing config value slave volume name: slave_volume", config_err_text);
+        }
+    }
 
-        if _config.snapshot.slave_hostname.is_none() {
-            if config_err_text.len() == 0 {
-                config_err_text = String::from("Error: Missing config value slave hostname name: slave_hostname");
-            }
-            else {
-                config_err_text = format!("{}\nError: Missing config value slave hostname name: slave_hostname", config_err_text);
-            }
-            config_err = true;
+    if config.snapshot.slave_user.is_none() {
+        if config_err_text.len() == 0 {
+            config_err_text = String::from("Error: Missing config value slave user name: slave_user");
         }
+        else {
+            config_err_text = format!("{}\nError: Missing config value slave user name: slave_user", config_err_text);
+        }
+    }
 
-        let config = _config;
+    if config.snapshot.slave_hostname.is_none() {
+        if config_err_text.len() == 0 {
+            config_err_text = String::from("Error: Missing config value slave hostname name: slave_hostname");
+        }
+        else {
+            config_err_text = format!("{}\nError: Missing config value slave hostname name: slave_hostname", config_err_text);
+        }
+    }
 
-        if matches.is_present("INFO") && 
-           config.snapshot.slave_hostname.is_some() {
-            let success = print_statistics(&config);
+    if config_err_text.len() == 0 {
+        None
+    }
+    else {
+        Some(config_err_text)
+    }
+}
 
-            if success.is_err() {
-                std::process::exit(1);
+/// Prints missing config values and exits
+fn exit_missing_config_values(config_err_text: &str, config_file_exist: bool) {
+    println!("{}", config_err_text);
+    if config_file_exist {
+        println!("Add missing arguments or update config file and try again");
+    }
+    else {
+        println!("Add missing arguments or create a config file and try again");
+    }
+
+    println!("");
+    println!("Use -h or --help for help");
+    std::process::exit(1);
+}
+
+/// Reads config file and overrides values
+/// with command line arguments, exits on error.
+/// Returns config and if config file exists.
+fn read_config(matches: &ArgMatches) -> (Config, bool) {
+    match load_config(matches) {
+        Ok((c, config_file_exist)) => {
+            if !config_file_exist {
+                println!("{}", CONFIG_NOT_FOUND);
             }
+            (c, config_file_exist)
+        },
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
         }
-        else if config_err {
-            println!("{}", config_err_text);
-            if config_file_exist {
-                println!("Add missing arguments or update config file and try again");
+    }
+}
+
+/// Printed when config file does not exist
+const CONFIG_NOT_FOUND: &str = "Master: ConfigNotFound: Config file not found, using default values";
+
+/// Reads config file and overrides values
+/// with command line arguments.
+/// Optional values missing in config file
+/// are set to default values, nothing is printed.
+/// Returns config and if config file exists.
+fn load_config(matches: &ArgMatches) -> Result<(Config, bool), String> {
+    let mut _config: Config = Config::default_config();
+    let mut config_file_exist = true;
+    _config = match get_config() {
+        Ok(c) => c,
+        Err((e, e_str)) => {
+            if e == ConfigReadErr::ConfigNotFound {
+                config_file_exist = false;
+                Config::default_config()
+            }
+            else if e == ConfigReadErr::ConfigValueErr {
+                return Err(format!("Master: {:?}: Parameter error in config file\n{}", e, e_str));
             }
             else {
-                println!("Add missing arguments or create a config file and try again");
+                return Err(format!("Master: {:?}: Error reading config file\n{}", e, e_str));
             }
+        },
+    };
 
-            println!("");
-            println!("Use -h or --help for help");
-            std::process::exit(1);
-        }
+    match matches.value_of("VOLUME") {
+        Some(v) => _config.snapshot.master_volume = Some(String::from(v)),
+        None => (),
+    }
 
+    match matches.value_of("SLAVE") {
+        Some(v) => _config.snapshot.slave_volume = Some(String::from(v)),
+        None => (),
+    }
 
+    match matches.value_of("USER") {
+        Some(v) => _config.snapshot.slave_user = Some(String::from(v)),
+        None => (),
+    }
 
-        if !matches.is_present("INFO") {
-            let res = create_snapshot(&config);
+    match matches.value_of("SLAVE_HOST") {
+        Some(v) => _config.snapshot.slave_hostname = Some(String::from(v)),
+        None => (),
+    }
 
-            if res.is_err() {
-                std::process::exit(1);
-            }
+    if _config.snapshot.slave_volume.is_none() {
+        _config.snapshot.slave_volume = _config.snapshot.master_volume.clone();
+    }
+
+    if _config.general.pin_file.is_none() {
+        let c = Config::default_config();
+        _config.general.pin_file = c.general.pin_file.clone();
+    }
+
+    if _config.general.lvs_bin.is_none() {
+        let c = Config::default_config();
+        _config.general.lvs_bin = c.general.lvs_bin.clone();
+    }
+
+    if _config.general.lock_dir.is_none() {
+        let c = Config::default_config();
+        _config.general.lock_dir = c.general.lock_dir.clone();
+    }
+
+    if _config.general.lock_policy.is_none() {
+        let c = Config::default_config();
+        _config.general.lock_policy = c.general.lock_policy.clone();
+    }
+
+    if _config.general.lock_wait_timeout.is_none() {
+        let c = Config::default_config();
+        _config.general.lock_wait_timeout = c.general.lock_wait_timeout.clone();
+    }
+
+    if _config.general.lock_stale_seconds.is_none() {
+        let c = Config::default_config();
+        _config.general.lock_stale_seconds = c.general.lock_stale_seconds.clone();
+    }
+
+    if _config.general.log_target.is_none() {
+        let c = Config::default_config();
+        _config.general.log_target = c.general.log_target.clone();
+    }
+
+    if _config.general.log_format.is_none() {
+        let c = Config::default_config();
+        _config.general.log_format = c.general.log_format.clone();
+    }
+
+    if _config.general.report_file.is_none() {
+        let c = Config::default_config();
+        _config.general.report_file = c.general.report_file.clone();
+    }
+
+    if _config.general.log_dir.is_none() {
+        let c = Config::default_config();
+        _config.general.log_dir = c.general.log_dir.clone();
+    }
+
+    if _config.general.log_rotate_size_mb.is_none() {
+        let c = Config::default_config();
+        _config.general.log_rotate_size_mb = c.general.log_rotate_size_mb.clone();
+    }
+
+    if _config.general.log_rotate_days.is_none() {
+        let c = Config::default_config();
+        _config.general.log_rotate_days = c.general.log_rotate_days.clone();
+    }
+
+    if _config.general.log_keep_files.is_none() {
+        let c = Config::default_config();
+        _config.general.log_keep_files = c.general.log_keep_files.clone();
+    }
+
+    if _config.general.log_compress.is_none() {
+        let c = Config::default_config();
+        _config.general.log_compress = c.general.log_compress.clone();
+    }
+
+    if _config.general.syslog_facility.is_none() {
+        let c = Config::default_config();
+        _config.general.syslog_facility = c.general.syslog_facility.clone();
+    }
+
+    if _config.general.syslog_ident.is_none() {
+        let c = Config::default_config();
+        _config.general.syslog_ident = c.general.syslog_ident.clone();
+    }
+
+    if _config.general.metrics_file.is_none() {
+        let c = Config::default_config();
+        _config.general.metrics_file = c.general.metrics_file.clone();
+    }
+
+    if _config.snapshot.snapshot_name_prefix.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.snapshot_name_prefix = c.snapshot.snapshot_name_prefix.clone();
+    }
+
+    if _config.snapshot.delay_after_pause_before_snapshot.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.delay_after_pause_before_snapshot = c.snapshot.delay_after_pause_before_snapshot.clone();
+    }
+
+    if _config.snapshot.delay_resume_geo_replication.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.delay_resume_geo_replication = c.snapshot.delay_resume_geo_replication.clone();
+    }
+
+    if _config.snapshot.manual_snapshot_expire_days.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.manual_snapshot_expire_days = c.snapshot.manual_snapshot_expire_days.clone();
+    }
+
+    if _config.snapshot.snapshot_name_template.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.snapshot_name_template = c.snapshot.snapshot_name_template.clone();
+    }
+
+    if _config.snapshot.manual_snapshot_name_template.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.manual_snapshot_name_template = c.snapshot.manual_snapshot_name_template.clone();
+    }
+
+    if _config.snapshot.timezone.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.timezone = c.snapshot.timezone.clone();
+    }
+
+    if _config.snapshot.delete_oldest_at_limit.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.delete_oldest_at_limit = c.snapshot.delete_oldest_at_limit.clone();
+    }
+
+    if _config.snapshot.thin_pool_max_data_percent.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.thin_pool_max_data_percent = c.snapshot.thin_pool_max_data_percent.clone();
+    }
+
+    if _config.snapshot.thin_pool_max_metadata_percent.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.thin_pool_max_metadata_percent = c.snapshot.thin_pool_max_metadata_percent.clone();
+    }
+
+    if _config.snapshot.thin_pool_full_action.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.thin_pool_full_action = c.snapshot.thin_pool_full_action.clone();
+    }
+
+    if _config.snapshot.capacity_target_percent.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.capacity_target_percent = c.snapshot.capacity_target_percent.clone();
+    }
+
+    if _config.snapshot.capacity_keep_newest.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.capacity_keep_newest = c.snapshot.capacity_keep_newest.clone();
+    }
+
+    if _config.snapshot.preflight_checks.is_none() {
+        let c = Config::default_config();
+        _config.snapshot.preflight_checks = c.snapshot.preflight_checks.clone();
+    }
+
+    Ok((_config, config_file_exist))
+}
+
+/// Takes run lock for session, lock messages are added to log.
+fn take_run_lock(config: &Config, log: &mut EventLog) -> Result<lock::RunLock, lock::LockErr> {
+    let session = format!("{}_{}@{}::{}", config.snapshot.master_volume.clone().unwrap(),
+                          config.snapshot.slave_user.clone().unwrap(),
+                          config.snapshot.slave_hostname.clone().unwrap(),
+                          config.snapshot.slave_volume.clone().unwrap());
+
+    match lock::acquire(config, &session, &HostType::Master) {
+        Ok((l, events)) => {
+            log.extend(events);
+            Ok(l)
+        },
+        Err((e, events)) => {
+            log.extend(events);
+            Err(e)
+        },
+    }
+}
+
+/// Runs command on master node and adds output
+/// to log, returns exit status and output or None
+/// if command could not be run.
+fn run_logged(log: &mut EventLog, cmd: &mut Command, cmd_text: &str) -> Option<(bool, String)> {
+    match cmd.output() {
+        Ok(o) => {
+            let o_str = format!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
+            let level = if o.status.success() { Level::Info } else { Level::Error };
+            log.command(level, HostType::Master, cmd_text, o.status.code(), &o_str);
+            Some((o.status.success(), o_str))
+        },
+        Err(e) => {
+            log.command(Level::Error, HostType::Master, cmd_text, None, &format!("Error running command: {}\nError: {}", cmd_text, e.to_string()));
+            None
         }
     }
 }
 
+/// Geo-replication session as used by gluster
+/// commands: user@slave_hostname::slave_volume
+fn slave_url(config: &Config) -> String {
+    format!("{}@{}::{}", config.snapshot.slave_user.clone().unwrap(),
+            config.snapshot.slave_hostname.clone().unwrap(),
+            config.snapshot.slave_volume.clone().unwrap())
+}
+
+/// Takes run lock for session so runs do not overlap.
 /// Pause geo-replication, if already paused it will continue.
 /// Creates snapshot on both master and slave node.
 /// Resumes geo-replication
-fn create_snapshot(config: &Config) -> Result<(), String> {
+/// If label is given a manual snapshot is created
+/// and old snapshots are not removed.
+fn create_snapshot(config: &Config, label: Option<&str>) -> Result<(), String> {
     let date = Local::now();
-    let mut log = String::new();
-//    log = format!("===================\n{}", date.format("%Y-%m-%d %H:%M:%S"));
-    log = format!("{}\nMaster: Pausing geo-replication", log);
+    let mut log = EventLog::new(&ggsnap_utils::session_name(&config));
+
+    log.set_phase("lock");
+    let _lock = match take_run_lock(&config, &mut log) {
+        Ok(l) => l,
+        Err(e) => {
+            let skipped = e == lock::LockErr::Skip;
+            print_log(&log, date, &config, skipped, None);
+            return if skipped { Ok(()) } else { Err(String::from("Error")) }
+        },
+    };
+
+    report_phase(&config, &mut log, "preflight", None);
+    if config.snapshot.preflight_checks.unwrap_or(true) {
+        match preflight::run_checks(&config) {
+            Ok(l) => log.extend(l),
+            Err(l) => {
+                log.extend(l);
+                print_log(&log, date, &config, false, None);
+                return Err(String::from("Error"))
+            }
+        }
+    }
 
+    match ggsnap_utils::limits::check_snapshot_limit(&config, &HostType::Master) {
+        Ok(l) => log.extend(l),
+        Err(l) => {
+            log.extend(l);
+            print_log(&log, date, &config, false, None);
+            return Err(String::from("Error"))
+        }
+    }
+
+    match ggsnap_utils::capacity::check_thin_pools(&config, &HostType::Master) {
+        Ok(l) => log.extend(l),
+        Err(l) => {
+            log.extend(l);
+            print_log(&log, date, &config, false, None);
+            return Err(String::from("Error"))
+        }
+    }
+
+    let snap_name = match label {
+        Some(l) => ggsnap_utils::manual_snapshot_name(&config, &config.snapshot.master_volume.clone().unwrap(), l, date),
+        None => ggsnap_utils::snapshot_name(&config, &config.snapshot.master_volume.clone().unwrap(), date),
+    };
+
+    report_phase(&config, &mut log, "pause", Some(&snap_name));
+    if !run_hook(&config, &mut log, hook::Phase::PrePause, &snap_name) {
+        print_log(&log, date, &config, false, Some(&snap_name));
+        return Err(String::from("Error"))
+    }
+
+    log.info(HostType::Master, "Pausing geo-replication");
+
+    let cmd_text = format!("gluster volume geo-replication {} {} pause",
+                           config.snapshot.master_volume.clone().unwrap(), slave_url(&config));
     let cmd_out = Command::new(&config.general.gluster_bin)
                           .arg("volume")
                           .arg("geo-replication")
                           .arg(&config.snapshot.master_volume.clone().unwrap())
-                          .arg(format!("{}@{}::{}", config.snapshot.slave_user.clone().unwrap(),
-                                       config.snapshot.slave_hostname.clone().unwrap(),
-                                       config.snapshot.slave_volume.clone().unwrap()))
+                          .arg(slave_url(&config))
                           .arg("pause")
                           .output();
     match cmd_out {
         Ok(o) => {
             let o_str = format!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
             if !o.status.success() && o_str.contains("already Paused") {
-                log = format!("{}\nMaster: {}\nMaster: Continue as geo-replication is already paused", log, o_str);
+                status::set_paused(true);
+                log.command(Level::Warning, HostType::Master, &cmd_text, o.status.code(), &o_str);
+                log.info(HostType::Master, "Continue as geo-replication is already paused");
             }
             else if !o.status.success() {
-                log = format!("{}\nMaster: {}", log, o_str);
-
-                match resume_geo_replication(&config, &log) {
-                    Ok(l) => {
-                        log = l;
-                    }
-                    Err(l) => {
-                        log = l;
-                    }
-                }
-
-                print_log(&log, date, &config, false);
+                log.command(Level::Error, HostType::Master, &cmd_text, o.status.code(), &o_str);
+                resume_geo_replication(&config, &mut log);
+                print_log(&log, date, &config, false, Some(&snap_name));
                 return Err(String::from("Error"))
             }
             else {
-                log = format!("{}\nMaster: {}", log, o_str);
+                status::set_paused(true);
+                log.command(Level::Info, HostType::Master, &cmd_text, o.status.code(), &o_str);
             }
         }
         Err(e) => {
-            log = format!("{}\nMaster: Error running command: gluster volume geo-replication {} {}@::{} pause",
-                          log, config.snapshot.slave_user.clone().unwrap(),
-                          config.snapshot.slave_hostname.clone().unwrap(),
-                          config.snapshot.slave_volume.clone().unwrap());
-            log = format!("{}\nMaster: Error: {}", log, e.to_string());
-            print_log(&log, date, &config, false);
+            log.command(Level::Error, HostType::Master, &cmd_text, None,
+                        &format!("Error running command: {}\nError: {}", cmd_text, e.to_string()));
+            print_log(&log, date, &config, false, Some(&snap_name));
             return Err(String::from("Error"))
         }
     }
 
-    thread::sleep(time::Duration::from_secs(config.snapshot.delay_after_pause_before_snapshot.unwrap()));
-    log = format!("{}\nMaster: Delaying before creating snapshot with {} seconds", log, 
-                 config.snapshot.delay_after_pause_before_snapshot.unwrap());
-
-    let snap_name = format!("{}_{}_{}", config.snapshot.snapshot_name_prefix.clone().unwrap(),
-                            config.snapshot.master_volume.clone().unwrap(), date.format("%Y%m%d_%H%M%S"));
+    systemd::sleep(config.snapshot.delay_after_pause_before_snapshot.unwrap());
+    log.info(HostType::Master, &format!("Delaying before creating snapshot with {} seconds",
+                                        config.snapshot.delay_after_pause_before_snapshot.unwrap()));
 
-    let mut slave_snap_success = true;
-    match create_slave_snapshot(&config, &snap_name) {
-        Ok(m) => log = format!("{}\n{}", log, m),
-        Err(e) => {
-            log = format!("{}\n{}", log, e);
-            slave_snap_success = false;
-        }
+    report_phase(&config, &mut log, "snapshot", Some(&snap_name));
+    if !run_hook(&config, &mut log, hook::Phase::PreSnapshot, &snap_name) {
+        resume_geo_replication(&config, &mut log);
+        print_log(&log, date, &config, false, Some(&snap_name));
+        return Err(String::from("Error"))
     }
 
-    let slave_snap_success = slave_snap_success;
-
-    log = format!("{}\nMaster: Creating snapshot: {} on volume: {}", 
-                  log, snap_name, config.snapshot.master_volume.clone().unwrap());
-
-    let cmd_out = Command::new(&config.general.gluster_bin)
-                          .arg("snapshot")
-                          .arg("create")
-                          .arg(&snap_name)
-                          .arg(&config.snapshot.master_volume.clone().unwrap())
-                          .arg("no-timestamp")
-                          .output();
-
-    match cmd_out {
-        Ok(o) => {
-            log = format!("{}\nMaster: {}{}", log, String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
-            if !o.status.success() {
-                match resume_geo_replication(&config, &log) {
-                    Ok(l) => {
-                        print_log(&l, date, &config, false);
-                    }
-                    Err(l) => {
-                        print_log(&l, date, &config, false);
-                    }
-                }
-
-                return Err(String::from("Error"))
-            }
-        }
-        Err(e) => {
-            log = format!("{}\nMaster: Error running command: gluster snapshot create {} {} no-timestamp",
-                          log, snap_name, config.snapshot.master_volume.clone().unwrap());
-            log = format!("{}\nMaster: Error: {}", log, e.to_string());
-            print_log(&log, date, &config, false);
+    let slave_snap_success = create_slave_snapshot(&config, &mut log, &snap_name);
+
+    log.info(HostType::Master, &format!("Creating snapshot: {} on volume: {}",
+                                        snap_name, config.snapshot.master_volume.clone().unwrap()));
+
+    let cmd_text = format!("gluster snapshot create {} {} no-timestamp",
+                           snap_name, config.snapshot.master_volume.clone().unwrap());
+    let res = run_logged(&mut log, Command::new(&config.general.gluster_bin)
+                                           .arg("snapshot")
+                                           .arg("create")
+                                           .arg(&snap_name)
+                                           .arg(&config.snapshot.master_volume.clone().unwrap())
+                                           .arg("no-timestamp"), &cmd_text);
+
+    match res {
+        Some((true, _)) => (),
+        Some((false, _)) => {
+            resume_geo_replication(&config, &mut log);
+            print_log(&log, date, &config, false, Some(&snap_name));
             return Err(String::from("Error"))
-        }
+        },
+        None => {
+            print_log(&log, date, &config, false, Some(&snap_name));
+            return Err(String::from("Error"))
+        },
     }
 
+    let mut hook_success = run_hook(&config, &mut log, hook::Phase::PostSnapshot, &snap_name);
+
     let mut old_snap_success = true;
-    match remove_old_snapshots(&config) {
-        Ok(s) => log = format!("{}\n{}", log, s),
-        Err(e) => {
-            log = format!("{}\n{}", log, e);
-            old_snap_success = false;
-        }
+    if label.is_some() {
+        log.info(HostType::Master, "Manual snapshot, old snapshots are not removed");
     }
-
-    match remove_old_slave_snapshots(&config) {
-        Ok(s) => log = format!("{}\n{}", log, s),
-        Err(e) => {
-            log = format!("{}\n{}", log, e);
-            old_snap_success = false;
-        }
+    else {
+        report_phase(&config, &mut log, "remove", Some(&snap_name));
+        old_snap_success = remove_old_snapshots(&config, &mut log);
+        old_snap_success = remove_old_slave_snapshots(&config, &mut log) && old_snap_success;
     }
 
-    match resume_geo_replication(&config, &log) {
-        Ok(l) => {
-            print_log(&l, date, &config, slave_snap_success && old_snap_success);
-        }
-        Err(l) => {
-            print_log(&l, date, &config, false);
-            return Err(String::from("Error"))
-        }
+    report_phase(&config, &mut log, "resume", Some(&snap_name));
+    if !resume_geo_replication(&config, &mut log) {
+        print_log(&log, date, &config, false, Some(&snap_name));
+        return Err(String::from("Error"))
     }
 
+    hook_success = run_hook(&config, &mut log, hook::Phase::PostResume, &snap_name) && hook_success;
+
+    print_log(&log, date, &config, slave_snap_success && old_snap_success && hook_success, Some(&snap_name));
+
 
 
     Ok(())
 }
 
-/// Resuming of geo-replication
-fn resume_geo_replication(config: &Config, log: &String) -> Result<String, String> {
-    let mut _l: String = String::new();
-    _l = format!("{}\nMaster: Delaying resuming geo-replication with {} seconds", log, 
-                 config.snapshot.delay_resume_geo_replication.unwrap());
-    thread::sleep(time::Duration::from_secs(config.snapshot.delay_resume_geo_replication.unwrap()));
-
-    _l = format!("{}\nMaster: Resuming geo-replication", _l);
-    let cmd_out = Command::new(&config.general.gluster_bin)
-                          .arg("volume")
-                          .arg("geo-replication")
-                          .arg(&config.snapshot.master_volume.clone().unwrap())
-                          .arg(format!("{}@{}::{}", config.snapshot.slave_user.clone().unwrap(),
-                                       config.snapshot.slave_hostname.clone().unwrap(),
-                                       config.snapshot.slave_volume.clone().unwrap()))
-                          .arg("resume")
-                          .output();
+/// Runs hook on master node if configured and adds
+/// result to log, returns false if hook failed
+/// with on_failure abort.
+fn run_hook(config: &Config, log: &mut EventLog, phase: hook::Phase, snap_name: &str) -> bool {
+    match hook::run_hook(&config, phase, snap_name, &HostType::Master) {
+        Some(Ok(l)) => {
+            log.extend(l);
+            true
+        },
+        Some(Err(l)) => {
+            log.extend(l);
+            false
+        },
+        None => true,
+    }
+}
 
-    match cmd_out {
-        Ok(o) => {
-            _l = format!("{}\nMaster: {}{}", _l, String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
-            if !o.status.success() {
-                return Err(_l)
-            }
-        }
-        Err(e) => {
-            _l = format!("{}\nMaster: Error running command: gluster volume geo-replication {} {}@{}::{} resume", 
-                        _l, config.snapshot.master_volume.clone().unwrap(),
-                        config.snapshot.slave_user.clone().unwrap(),
-                        config.snapshot.slave_hostname.clone().unwrap(),
-                        config.snapshot.slave_volume.clone().unwrap());
-            _l = format!("{}\nMaster: Error:{}", _l, e.to_string());
-            return Err(_l)
-        }
+/// Resuming of geo-replication
+fn resume_geo_replication(config: &Config, log: &mut EventLog) -> bool {
+    log.info(HostType::Master, &format!("Delaying resuming geo-replication with {} seconds",
+                                        config.snapshot.delay_resume_geo_replication.unwrap()));
+    systemd::sleep(config.snapshot.delay_resume_geo_replication.unwrap());
+
+    log.info(HostType::Master, "Resuming geo-replication");
+    let cmd_text = format!("gluster volume geo-replication {} {} resume",
+                           config.snapshot.master_volume.clone().unwrap(), slave_url(&config));
+    let res = run_logged(log, Command::new(&config.general.gluster_bin)
+                                      .arg("volume")
+                                      .arg("geo-replication")
+                                      .arg(&config.snapshot.master_volume.clone().unwrap())
+                                      .arg(slave_url(&config))
+                                      .arg("resume"), &cmd_text);
+
+    match res {
+        Some((true, _)) => {
+            status::set_paused(false);
+            true
+        },
+        _ => false,
     }
- 
-    Ok(_l)
 }
 
 /// Connects to main slave node over ssh
 /// and runs ggsnap_slave to create a
 /// snapshot
-fn create_slave_snapshot(config: &Config, snap_name: &String) -> Result<String, String>{
+fn create_slave_snapshot(config: &Config, log: &mut EventLog, snap_name: &String) -> bool {
+    let cmd_text = format!("ssh {} {} --volume {} --snapshot-name {}",
+                           config.snapshot.slave_hostname.clone().unwrap(),
+                           config.general.ggsnap_slave_bin,
+                           config.snapshot.slave_volume.clone().unwrap(),
+                           snap_name);
     let cmd_out = Command::new("ssh")
                           .arg(&config.snapshot.slave_hostname.clone().unwrap())
                           .arg(&config.general.ggsnap_slave_bin)
@@ -372,22 +688,21 @@ fn create_slave_snapshot(config: &Config, snap_name: &String) -> Result<String,
 
     match cmd_out {
         Ok(o) => {
-            let l = format!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
             if o.status.success() {
-                Ok(l)
+                log.extend(event::parse_json(Level::Info, HostType::Slave, &String::from_utf8_lossy(&o.stdout)));
+                log.extend(Event::lines(Level::Info, HostType::Slave, &String::from_utf8_lossy(&o.stderr)));
+                true
             }
             else {
-                Err(format!("Slave error creating snapshot:\n{}", l))
+                log.error(HostType::Slave, "Slave error creating snapshot:");
+                log.extend(slave_error_events(&cmd_text, o.status.code(), &o.stdout, &o.stderr));
+                false
             }
         },
         Err(e) => {
-            let mut l: String = format!("Master: Error running command: ssh {} {} --volume {} --snapshot-name {}",
-                                         config.snapshot.slave_hostname.clone().unwrap(),
-                                         config.general.ggsnap_slave_bin,
-                                         config.snapshot.slave_volume.clone().unwrap(),
-                                         snap_name);
-            l = format!("{}\nMaster: Error: {}", l, e.to_string());
-            Err(l)
+            log.command(Level::Error, HostType::Master, &cmd_text, None,
+                        &format!("Error running command: {}\nError: {}", cmd_text, e.to_string()));
+            false
         }           
     }
                           
@@ -395,21 +710,31 @@ fn create_slave_snapshot(config: &Config, snap_name: &String) -> Result<String,
 
 /// Removes old snapshot from master node according 
 /// to settings in config file.
-fn remove_old_snapshots(config: &Config) -> Result<String, String> {
-    let mut log = String::from("Master: Removing old snapshots");
+fn remove_old_snapshots(config: &Config, log: &mut EventLog) -> bool {
+    log.info(HostType::Master, "Removing old snapshots");
 
-    match ggsnap_utils::remove_old_snapshots(config, ggsnap_utils::HostType::Master) {
+    match ggsnap_utils::remove_old_snapshots(config, HostType::Master) {
         Ok(s) => {
-            log = format!("{}\nMaster: The following snapshots has been removed:\n{}", log, s);
-            Ok(format!("{}\nMaster: End of removing snapshots", log))
+            log.info(HostType::Master, "The following snapshots has been removed:");
+            log.extend(s);
+            log.info(HostType::Master, "End of removing snapshots");
+            true
+        },
+        Err(e) => {
+            log.error(HostType::Master, "Error removing old snapshots:");
+            log.extend(e);
+            false
         },
-        Err(e) => Err(format!("{}\nMaster: Error removing old snapshots:\n{}", log, e.to_string())),
     }
 }
 
 /// Removes old snapshot from slave node according 
 /// to settings in config file.
-fn remove_old_slave_snapshots(config: &Config) -> Result<String, String> {
+fn remove_old_slave_snapshots(config: &Config, log: &mut EventLog) -> bool {
+    let cmd_text = format!("ssh {} {} --remove-snapshots --volume {}",
+                           config.snapshot.slave_hostname.clone().unwrap(),
+                           config.general.ggsnap_slave_bin,
+                           config.snapshot.slave_volume.clone().unwrap());
     let cmd_out = Command::new("/bin/ssh")
                           .arg(&config.snapshot.slave_hostname.clone().unwrap())
                           .arg(&config.general.ggsnap_slave_bin)
@@ -421,76 +746,311 @@ fn remove_old_slave_snapshots(config: &Config) -> Result<String, String> {
     match cmd_out {
         Ok(o) => {
             if o.status.success() {
-                Ok(format!("{}", String::from_utf8_lossy(&o.stdout)))
+                log.extend(event::parse_json(Level::Info, HostType::Slave, &String::from_utf8_lossy(&o.stdout)));
+                true
             }
             else {
-                Err(format!("Slave: Error running command: {} --remove-snapshots --volume {}\n{}{}",
-                            config.general.ggsnap_slave_bin,
-                            config.snapshot.slave_volume.clone().unwrap(),
-                            String::from_utf8_lossy(&o.stdout), 
-                            String::from_utf8_lossy(&o.stderr)))
+                log.extend(slave_error_events(&cmd_text, o.status.code(), &o.stdout, &o.stderr));
+                false
             }
         },
         Err(e) => {
-            Err(format!("Master: Error running command: ssh {} {} --remove-snapshots --volume {}\n{}",
-                        config.snapshot.slave_hostname.clone().unwrap(), 
-                        config.general.ggsnap_slave_bin, 
-                        config.snapshot.slave_volume.clone().unwrap(), e.to_string()))
+            log.command(Level::Error, HostType::Master, &cmd_text, None,
+                        &format!("Error running command: {}\nError: {}", cmd_text, e.to_string()));
+            false
         }
     }
 }
 
-/// Print statistics for both master snapshots
-/// and slave snapshots.
-/// Prints number of snapshots that differs between
-/// master and slave.
-/// Only snapshots with names that matches the
-/// format ggsnap uses.
-fn print_statistics(config: &Config) -> Result<(),()>{
-    let cmd_out = Command::new("/bin/ssh")
-                          .arg(&config.snapshot.slave_hostname.clone().unwrap())
-                          .arg(&config.general.ggsnap_slave_bin)
-                          .arg("--list")
-                          .output();
+/// Events printed by a failed ggsnap_slave, lines that
+/// are not events and stderr, like errors from ssh,
+/// are added as errors of command.
+fn slave_error_events(cmd_text: &str, exit_code: Option<i32>, stdout: &[u8], stderr: &[u8]) -> Vec<Event> {
+    let mut events = event::parse_json(Level::Error, HostType::Slave, &String::from_utf8_lossy(stdout));
+    events.extend(Event::command(Level::Error, HostType::Slave, cmd_text, exit_code, &String::from_utf8_lossy(stderr)));
+    events
+}
 
-    let mut _slave_gluster_out: String = String::from("");
-    match cmd_out {
+/// Removes old snapshots on master and slave
+/// node without creating a new snapshot, used
+/// by daemon mode for prune schedule.
+fn prune_snapshots(config: &Config) -> Result<(), String> {
+    let date = Local::now();
+    let mut log = EventLog::new(&ggsnap_utils::session_name(&config));
+
+    log.set_phase("lock");
+    let _lock = match take_run_lock(&config, &mut log) {
+        Ok(l) => l,
+        Err(e) => {
+            let skipped = e == lock::LockErr::Skip;
+            print_log(&log, date, &config, skipped, None);
+            return if skipped { Ok(()) } else { Err(String::from("Error")) }
+        },
+    };
+
+    report_phase(&config, &mut log, "remove", None);
+    let mut success = remove_old_snapshots(&config, &mut log);
+    success = remove_old_slave_snapshots(&config, &mut log) && success;
+
+    print_log(&log, date, &config, success, None);
+
+    if success {
+        Ok(())
+    }
+    else {
+        Err(String::from("Error"))
+    }
+}
+
+/// True if snapshot is in gluster snapshot list on master
+fn master_snapshot_exists(config: &Config, snap_name: &str) -> Result<bool, String> {
+    match Command::new(&config.general.gluster_bin).arg("snapshot").arg("list").output() {
         Ok(o) => {
             if o.status.success() {
-                _slave_gluster_out = String::from_utf8_lossy(&o.stdout).to_string();
+                Ok(String::from_utf8_lossy(&o.stdout).lines().any(|l| l.trim() == snap_name))
             }
             else {
-                println!("Master: Error running ggsnap_slave: {}{}", 
-                         String::from_utf8_lossy(&o.stdout), 
-                         String::from_utf8_lossy(&o.stderr));
+                Err(format!("Master: Error executing command: gluster snapshot list; {}",
+                            String::from_utf8_lossy(&o.stderr).trim()))
+            }
+        },
+        Err(e) => Err(format!("Master: Error executing command: gluster snapshot list; {}", e)),
+    }
+}
+
+/// Pins snapshot on both master and slave node,
+/// a pinned snapshot is never removed when old
+/// snapshots are removed. If until is given the
+/// snapshot is pinned to and including that date.
+/// Snapshot must exist on master and if pinning
+/// on slave fails the pin on master is rolled back.
+fn pin_snapshot(config: &Config, snap_name: &str, until: Option<&str>) -> Result<(), ()> {
+    let mut until_date = None;
+    if let Some(u) = until {
+        match pin::parse_until(u) {
+            Ok(d) => until_date = Some(d),
+            Err(e) => {
+                println!("Master: {}", e);
                 return Err(())
+            },
+        }
+    }
+
+    if config.snapshot.slave_hostname.is_none() {
+        println!("Error: Missing config value slave hostname name: slave_hostname");
+        return Err(())
+    }
+
+    match master_snapshot_exists(config, snap_name) {
+        Ok(true) => (),
+        Ok(false) => {
+            println!("Master: Snapshot does not exist: {}", snap_name);
+            return Err(())
+        },
+        Err(e) => {
+            println!("{}", e);
+            return Err(())
+        },
+    }
+
+    let previous = match pin::read_pins(config) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("Master: {}", e);
+            return Err(())
+        },
+    };
+
+    let mut pins = previous.clone();
+    pins.remove_expired(timezone::config_timezone(config).today());
+    pins.add(snap_name, until_date);
+
+    if let Err(e) = pin::write_pins(config, &pins) {
+        println!("Master: {}", e);
+        return Err(())
+    }
+
+    let mut cmd = Command::new("ssh");
+    cmd.arg(&config.snapshot.slave_hostname.clone().unwrap())
+       .arg(&config.general.ggsnap_slave_bin)
+       .arg("--pin")
+       .arg(snap_name);
+
+    if let Some(u) = until {
+        cmd.arg("--until").arg(u);
+    }
+
+    if run_slave_pin_command(config, cmd).is_err() {
+        match pin::write_pins(config, &previous) {
+            Ok(_) => println!("Master: Error pinning snapshot on slave, pin on master is rolled back"),
+            Err(e) => println!("Master: Error pinning snapshot on slave, pin on master could not be rolled back\nMaster: {}", e),
+        }
+        return Err(())
+    }
+
+    match until {
+        Some(u) => println!("Master: Pinned snapshot: {} until: {}", snap_name, u),
+        None => println!("Master: Pinned snapshot: {}", snap_name),
+    }
+
+    Ok(())
+}
+
+/// Removes pin from snapshot on both
+/// master and slave node.
+fn unpin_snapshot(config: &Config, snap_name: &str) -> Result<(), ()> {
+    if config.snapshot.slave_hostname.is_none() {
+        println!("Error: Missing config value slave hostname name: slave_hostname");
+        return Err(())
+    }
+
+    let mut pins = match pin::read_pins(config) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("Master: {}", e);
+            return Err(())
+        },
+    };
+
+    pins.remove_expired(timezone::config_timezone(config).today());
+
+    if pins.remove(snap_name) {
+        println!("Master: Unpinned snapshot: {}", snap_name);
+    }
+    else {
+        println!("Master: Snapshot is not pinned: {}", snap_name);
+    }
+
+    if let Err(e) = pin::write_pins(config, &pins) {
+        println!("Master: {}", e);
+        return Err(())
+    }
+
+    let mut cmd = Command::new("ssh");
+    cmd.arg(&config.snapshot.slave_hostname.clone().unwrap())
+       .arg(&config.general.ggsnap_slave_bin)
+       .arg("--unpin")
+       .arg(snap_name);
+
+    if run_slave_pin_command(config, cmd).is_err() {
+        println!("Master: Error unpinning snapshot on slave, pin file on master is updated");
+        return Err(())
+    }
+
+    Ok(())
+}
+
+/// Runs pin or unpin with ggsnap_slave
+/// on slave node and prints the result.
+fn run_slave_pin_command(config: &Config, mut cmd: Command) -> Result<(), ()> {
+    match cmd.output() {
+        Ok(o) => {
+            print!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
+            if o.status.success() {
+                Ok(())
+            }
+            else {
+                Err(())
             }
         },
         Err(e) => {
-            println!("Master: Error running command: ssh {} /root/ggsnap_slave --list; {}", 
-                     config.snapshot.slave_hostname.clone().unwrap(),e);
+            println!("Master: Error running command: ssh {} {}; {}",
+                     config.snapshot.slave_hostname.clone().unwrap(),
+                     config.general.ggsnap_slave_bin, e);
+            Err(())
+        },
+    }
+}
+
+/// Prints all snapshots pinned on
+/// master node.
+fn list_pinned_snapshots(config: &Config) -> Result<(), ()> {
+    let pins = match pin::read_pins(config) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("Master: {}", e);
             return Err(())
         },
+    };
+
+    let today = timezone::config_timezone(config).today();
+
+    for p in pins.pin.iter() {
+        let expired = if p.is_expired(today) { " (expired)" } else { "" };
+
+        match p.until {
+            Some(ref u) => println!("{} until: {}{}", p.name, u, expired),
+            None => println!("{}", p.name),
+        }
     }
 
-    
-    let slave_stats = stats::SnapStat::new(_slave_gluster_out, &config.snapshot.slave_volume.clone().unwrap());
-    let stats = stats::get_statistics(&config);
-
-    println!("==================================================================================");
-    println!("=               Snapshot statistics (Snapshots created by ggsnap)                =");
-    println!("==================================================================================");
-    println!("Total number of snapshots on master cluster: {}", stats.len());
-    println!("Newest snapshot on master cluster: {}", stats.newest_snap());
-    println!("Oldest snapshot on master cluster: {}", stats.oldest_snap());
-    println!("");
-    println!("Total number of snapshots on slave cluster: {}", slave_stats.len());
-    println!("Newest snapshot on slave cluster: {}", slave_stats.newest_snap());
-    println!("Oldest snapshot on slave cluster: {}", slave_stats.oldest_snap());
-    println!("");
-    println!("Number of snapshots that differ between master and slave: {}", 
-             stats.number_diff(&slave_stats));
-    println!("==================================================================================");
+    Ok(())
+}
+
+/// Runs monitoring plugin checks, prints
+/// result and returns plugin exit code.
+fn run_check(matches: &ArgMatches, check_matches: &ArgMatches) -> i32 {
+    let hours = |name: &str| check_matches.value_of(name).unwrap().parse::<f64>().unwrap() * 3600.0;
+    let count = |name: &str| check_matches.value_of(name).unwrap().parse::<f64>().unwrap();
+
+    let age = check::Thresholds { warning: hours("WARNING_AGE"), critical: hours("CRITICAL_AGE") };
+    let drift = check::Thresholds { warning: count("WARNING_DRIFT"), critical: count("CRITICAL_DRIFT") };
+
+    let config = match age.validate("age").and(drift.validate("drift"))
+        .and(load_config(matches)) {
+        Ok((c, _)) => match missing_config_values(&c) {
+            Some(e) => Err(e),
+            None => Ok(c),
+        },
+        Err(e) => Err(e),
+    };
+
+    let config = match config {
+        Ok(c) => c,
+        Err(e) => {
+            let (state, line) = check::unknown(&e);
+            println!("{}", line);
+            return state.exit_code();
+        },
+    };
+
+    let report_file = config.general.report_file.clone().unwrap_or(String::new());
+    let report_path = if report_file.len() > 0 && report_file != "-" {
+        log_file_path(&report_file, &config).ok()
+    }
+    else {
+        None
+    };
+
+    let (state, line) = check::run(&config, &age, &drift, report_path);
+    println!("{}", line);
+    state.exit_code()
+}
+
+/// Print statistics for both master snapshots
+/// and slave snapshots.
+/// Prints number of snapshots that differs between
+/// master and slave, retention tiers with gaps and
+/// snapshots not created by ggsnap in text, json or csv.
+fn print_statistics(config: &Config, format: info::Format) -> Result<(),()>{
+    let slave_stats = match stats::get_slave_statistics(&config) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("{}", e);
+            return Err(())
+        },
+    };
+
+    let stats = match stats::get_statistics(&config) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("{}", e);
+            return Err(())
+        },
+    };
+
+    let today = timezone::config_timezone(&config).today();
+    print!("{}", info::format(&config, &stats, &slave_stats, today, format));
 
     Ok(())
 }
@@ -498,10 +1058,58 @@ fn print_statistics(config: &Config) -> Result<(),()>{
 /// Prints result to log file in specified
 /// log file in config file
 /// If mail is active, mail will be sent
-/// with result.
-fn print_log(log: &String, date: DateTime<Local>, config: &Config, success: bool) {
-    //TODO
-    // Send mail and and append to log in same dir as ggsnap
+/// with result and webhooks in [notify_webhook]
+/// are called.
+fn print_log(log: &EventLog, date: DateTime<Local>, config: &Config, success: bool, snap_name: Option<&str>) {
+    let mut log = log.clone();
+
+    let report = RunReport::from_log(&log, snap_name, success, Utc::now());
+    let res_mail = send_log_mail(&report, &format_log_text(&log.events, date, success), date, &config);
+    log.set_phase("mail");
+    match res_mail {
+        Ok(m) => log.info(HostType::Master, &m),
+        Err(e) => log.error(HostType::Master, &e),
+    }
+
+    if let Some(ref w) = config.notify_webhook {
+        if w.notifies(success) {
+            // Report again so payload has mail status.
+            let report = RunReport::from_log(&log, snap_name, success, Utc::now());
+            log.set_phase("webhook");
+            log.extend(webhook::notify(w, &report));
+        }
+    }
+
+    let res = if config.general.log_target.clone().unwrap_or(String::from("file")) == "journald" {
+        write_to_journal(&log, config, success, snap_name)
+    }
+    else if config.general.log_target.clone().unwrap_or(String::from("file")) == "syslog" {
+        write_to_syslog(&log, config, success, snap_name)
+    }
+    else if config.general.log_format.clone().unwrap_or(String::from("text")) == "json" {
+        write_to_log_file(&event::format_json(&log.events), &config)
+    }
+    else {
+        write_to_log_file(&format_log_text(&log.events, date, success), &config)
+    };
+
+    match res {
+        Ok(_) => (),
+        Err(e) => println!("{}", e),
+    }
+
+    if let Err(e) = write_report(&log, &config, success, snap_name) {
+        println!("{}", e);
+    }
+
+    if let Err(e) = write_metrics(&log, &config, success, snap_name) {
+        println!("{}", e);
+    }
+}
+
+/// Formats events as text log with
+/// header with date and tail with result.
+fn format_log_text(events: &[Event], date: DateTime<Local>, success: bool) -> String {
     let mut header: String = format!("\n\n====================================================================================\n");
     header = format!("{}=                 gluster-geo-snapshot date: {}                   =",
                      header, date.format("%Y-%m-%d %H:%M:%S"));
@@ -517,184 +1125,304 @@ fn print_log(log: &String, date: DateTime<Local>, config: &Config, success: bool
     }
     tail = format!("{}====================================================================================\n", tail);
 
-    let mut _log_msg: String = String::new();
-    let mut _log_msg_no_tail: String = String::new();
-    let mut clean_log: Vec<String> = Vec::new();
-    let mut prev_l = "";
+    format!("{}{}\n{}", header, event::format_text(events), tail)
+}
 
-    for l in log.split("\n") {
-        if l.len() > 0 {
-            if l.contains("Do you still want to continue? (y/n)") {
-                let s: Vec<&str> = l.split("Do you still want to continue? (y/n)").collect();
-                clean_log.push(s[0].to_string());
+/// Reports phase of a run to systemd and daemon status,
+/// events added after this belong to phase.
+fn report_phase(config: &Config, log: &mut EventLog, phase: &str, snap_name: Option<&str>) {
+    log.set_phase(phase);
+    status::set_phase(&log.session, phase);
+    systemd::status(&format!("{}: {} {}", phase, ggsnap_utils::session_name(&config), snap_name.unwrap_or("")));
+    systemd::watchdog();
+}
 
-                if prev_l.starts_with("Master") {
-                    clean_log.push(format!("Master: {}", s[1]));
-                }
-                else {
-                    clean_log.push(format!("Slave: {}", s[1]));
-                }
-            }
-            else {
-                if l.starts_with("Master") || l.starts_with("Slave") {
-                    clean_log.push(l.to_string());
-                }
-                else {
-                    if prev_l.starts_with("Master") {
-                        clean_log.push(format!("Master: {}", l));
-                    }
-                    else {
-                        clean_log.push(format!("Slave: {}", l));
-                    }
-                }
-            }
-            prev_l = l;
+/// Write events to journald, one entry per event
+/// with fields for session, host, phase and snapshot
+/// and a last entry with the result of the run.
+fn write_to_journal(log: &EventLog, config: &Config, success: bool, snap_name: Option<&str>) -> Result<(), String> {
+    let snap = snap_name.unwrap_or("");
+
+    for e in log.events.iter() {
+        let exit_code = e.exit_code.map(|c| c.to_string()).unwrap_or(String::new());
+        let mut fields: Vec<(&str, &str)> = vec![("MESSAGE", &e.message),
+                                                 ("PRIORITY", systemd::priority(&e.level)),
+                                                 ("GGSNAP_SESSION", &e.session),
+                                                 ("GGSNAP_HOST", if e.host == HostType::Master { "master" } else { "slave" }),
+                                                 ("GGSNAP_PHASE", &e.phase),
+                                                 ("GGSNAP_SNAPSHOT", snap)];
+        if let Some(ref c) = e.command {
+            fields.push(("GGSNAP_COMMAND", c));
+            fields.push(("GGSNAP_EXIT_CODE", &exit_code));
         }
-    }
 
-    _log_msg = format!("{}", header);
-    for l in clean_log {
-        _log_msg = format!("{}\n{}", _log_msg, l);
+        match systemd::journal_send(&fields) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        }
     }
-    _log_msg_no_tail = _log_msg.clone();
-    _log_msg = format!("{}\n{}", _log_msg, tail);
 
-    let res_mail = send_log_mail(&_log_msg, &config, success);
-    _log_msg = format!("{}\n{}\n{}", _log_msg_no_tail, res_mail, tail);
+    let (msg, prio, result) = if success {
+        ("Snapshots updated successfully", "6", "success")
+    }
+    else {
+        ("Error updating snapshots", "3", "failed")
+    };
+
+    systemd::journal_send(&[("MESSAGE", msg),
+                            ("PRIORITY", prio),
+                            ("GGSNAP_SESSION", &ggsnap_utils::session_name(&config)),
+                            ("GGSNAP_HOST", "master"),
+                            ("GGSNAP_PHASE", "done"),
+                            ("GGSNAP_SNAPSHOT", snap),
+                            ("GGSNAP_RESULT", result)])
+}
 
-    match write_to_log_file(&_log_msg, &config) {
-        Ok(_) => (),
-        Err(e) => println!("{}", e),
+/// Write events to syslog, severity is taken from
+/// event level and last message with result of run
+/// is notice on success and err on failure.
+fn write_to_syslog(log: &EventLog, config: &Config, success: bool, snap_name: Option<&str>) -> Result<(), String> {
+    let sys = match Syslog::connect(&config) {
+        Ok(s) => s,
+        Err(e) => return Err(e),
+    };
+
+    for e in log.events.iter() {
+        let msg = format!("{}: {} (session: {}, phase: {})", if e.host == HostType::Master { "Master" } else { "Slave" },
+                          e.message, e.session, e.phase);
+        match sys.send(syslog::Severity::from_level(&e.level), &msg) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        }
     }
 
+    let (severity, result) = if success {
+        (syslog::Severity::Notice, "Snapshots updated successfully")
+    }
+    else {
+        (syslog::Severity::Err, "Error updating snapshots")
+    };
 
+    sys.send(severity, &format!("Master: {} (session: {}, snapshot: {})", result,
+                                 ggsnap_utils::session_name(&config), snap_name.unwrap_or("")))
 }
 
 /// Write log to log file 
 /// as specified in config file 
 fn write_to_log_file(log: &String, config: &Config) -> Result<(), String> {
-    let mut _rel_path: String = String::new();
-    let mut log_path = Path::new(&config.general.log_file);
-  
     if config.general.log_file.len() > 0 {
-        if log_path.is_relative() {
-            let mut exe_path = match std::env::current_exe() {
-                Ok(p)  => p, 
-                Err(e) => return Err(format!("Error getting exe path, can not write to log.\nError: {}", e.to_string())),
-            };
+        append_to_file(log, &config.general.log_file, &config)
+    }
+    else {
+        println!("{}", log);
+        Ok(())
+    }
+}
 
-            exe_path.pop();
-            let l_path = match exe_path.to_str() {
-                Some(p) => p,
-                None    => return Err(String::from("Path is not valid unicode, can not write to log.")),
-            };
+/// Writes JSON line report of run to report_file,
+/// - writes to stdout.
+fn write_report(log: &EventLog, config: &Config, success: bool, snap_name: Option<&str>) -> Result<(), String> {
+    let report_file = config.general.report_file.clone().unwrap_or(String::new());
+    if report_file.len() == 0 {
+        return Ok(())
+    }
+
+    let json = match RunReport::from_log(&log, snap_name, success, Utc::now()).to_json() {
+        Ok(j) => j,
+        Err(e) => return Err(e),
+    };
 
-            _rel_path = format!("{}/{}", l_path, config.general.log_file);
-            log_path = Path::new(&_rel_path);
-        } 
+    if report_file == "-" {
+        print!("{}", json);
+        Ok(())
+    }
+    else {
+        append_to_file(&json, &report_file, &config)
+    }
+}
 
-        let mut open_opt: OpenOptions = OpenOptions::new();
+/// Writes Prometheus metrics of run to metrics_file
+/// for node_exporter textfile collector and keeps them
+/// for HTTP endpoint in daemon mode. Counters continue
+/// from last run.
+fn write_metrics(log: &EventLog, config: &Config, success: bool, snap_name: Option<&str>) -> Result<(), String> {
+    let metrics_file = config.general.metrics_file.clone().unwrap_or(String::new());
+    let report = RunReport::from_log(&log, snap_name, success, Utc::now());
+    let report_json = match serde_json::to_value(&report) {
+        Ok(v) => v,
+        Err(_) => serde_json::Value::Null,
+    };
+
+    if metrics_file.len() == 0 && !status::keeps_metrics() {
+        status::finish(&log.session, report_json, None);
+        return Ok(())
+    }
 
-        if log_path.exists() {
-            open_opt.append(true);
+    let path = Path::new(&metrics_file);
+    let previous = match status::last_metrics() {
+        Some(m) => m,
+        None if metrics_file.len() > 0 => metrics::read_previous(&path),
+        None => metrics::Series::new(),
+    };
+
+    let master = stats::get_statistics(&config);
+    let slave = stats::get_slave_statistics(&config);
+    let snap_stats = match (&master, &slave) {
+        (&Ok(ref m), &Ok(ref s)) => Some((m, s)),
+        _ => None,
+    };
+
+    let m = metrics::run_metrics(&log, &report, Utc::now(), snap_stats, &previous);
+    status::finish(&log.session, report_json, Some(m.clone()));
+
+    if metrics_file.len() > 0 {
+        metrics::write_textfile(&path, &m)
+    }
+    else {
+        Ok(())
+    }
+}
+
+/// Path of log or report file, relative path is
+/// relative to log_dir or binary if log_dir is not set.
+fn log_file_path(file_name: &str, config: &Config) -> Result<String, String> {
+    let log_dir = config.general.log_dir.clone().unwrap_or(String::new());
+
+    if Path::new(file_name).is_absolute() {
+        Ok(file_name.to_string())
+    }
+    else if log_dir.len() > 0 {
+        Ok(format!("{}/{}", log_dir.trim_end_matches('/'), file_name))
+    }
+    else {
+        let mut exe_path = match std::env::current_exe() {
+            Ok(p)  => p, 
+            Err(e) => return Err(format!("Error getting exe path, can not write to file.\nError: {}", e.to_string())),
+        };
+
+        exe_path.pop();
+        match exe_path.to_str() {
+            Some(p) => Ok(format!("{}/{}", p, file_name)),
+            None    => Err(String::from("Path is not valid unicode, can not write to file.")),
         }
-        else {
-            open_opt.create(true).write(true);
+    }
+}
+
+/// Appends text to file, path as in log_file_path.
+/// File is rotated first if needed.
+fn append_to_file(log: &String, file_name: &str, config: &Config) -> Result<(), String> {
+    let log_dir = config.general.log_dir.clone().unwrap_or(String::new());
+
+    if Path::new(file_name).is_relative() && log_dir.len() > 0 {
+        if let Err(e) = std::fs::create_dir_all(&log_dir) {
+            return Err(format!("Error creating log directory: {}\nError: {}", log_dir, e.to_string()));
         }
+    }
 
+    let path = match log_file_path(file_name, config) {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+    let log_path = Path::new(&path);
 
-        let f = match open_opt.open(log_path) {
-            Ok(f) => f,
-            Err(e) => return Err(format!("Error opening log file: {}\nError: {}", log_path.to_str().unwrap(), e.to_string())),
-        };
+    match logfile::rotate_if_needed(log_path, &logfile::Rotation::from_config(&config)) {
+        Ok(Some(m)) => println!("Master: {}", m),
+        Ok(None) => (),
+        Err(e) => return Err(e),
+    }
 
-        let mut f = BufWriter::new(f);
+    let mut open_opt: OpenOptions = OpenOptions::new();
 
-        return match f.write_all(log.as_bytes()) {
-            Ok(_)  => Ok(()),
-            Err(e) => Err(format!("Error writing to log file: {}\nError: {}", log_path.to_str().unwrap(), e.to_string())),
-        };
+    if log_path.exists() {
+        open_opt.append(true);
     }
     else {
-        println!("{}", log);
+        open_opt.create(true).write(true);
     }
 
-    Ok(())
+
+    let f = match open_opt.open(log_path) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("Error opening file: {}\nError: {}", log_path.to_str().unwrap(), e.to_string())),
+    };
+
+    let mut f = BufWriter::new(f);
+
+    match f.write_all(log.as_bytes()) {
+        Ok(_)  => Ok(()),
+        Err(e) => Err(format!("Error writing to file: {}\nError: {}", log_path.to_str().unwrap(), e.to_string())),
+    }
 }
 
 /// If mail is enabled in config file
 /// Mail will be sent according to
 /// config file settings.
-fn send_log_mail(log: &String, config: &Config, success: bool) -> String {
+/// Mail has a summary of report as text and html
+/// with the full log attached. Config setting notify
+/// decides if mail is sent for this run, sent as a
+/// digest later or not sent.
+fn send_log_mail(report: &RunReport, log: &String, date: DateTime<Local>, config: &Config) -> Result<String, String> {
     if let Some(ref mail_conf) = config.mail_from_master {
         if mail_conf.enable {
-            let mut msg: String = String::from("To: ");
-            let mut to = mail_conf.to_addresses.clone();
-            let mut auth: Mechanism = Mechanism::Plain;
-            let mut mailer_build;
-
-            let first_to = to.pop();
-            if first_to.is_some() {
-                msg = format!("{}{}", msg, first_to.unwrap());                
-            }
-
-            for m in to {
-                msg = format!("{},{}", msg, m);
-            }
-
-            if success {
-                msg = format!("{}\nSubject: {}: OK\n", msg, mail_conf.subject.clone());
+            let policy = notify::Policy::from_name(&mail_conf.notify.clone().unwrap_or(String::from("always")))
+                                        .unwrap_or(notify::Policy::Always);
+            let state_file = mail_conf.notify_state_file.clone()
+                                      .unwrap_or(String::from(notify::DEFAULT_NOTIFY_STATE_FILE));
+
+            // State is saved after mail is sent, if sending
+            // fails previous state is kept with this run added
+            let (notification, states) = if policy == notify::Policy::Always {
+                (notify::Notification::Run, None)
             }
             else {
-                msg = format!("{}\nSubject: {}: Error\n", msg, mail_conf.subject.clone());
-            }
-
-            msg = format!("{}{}", msg, log);
-
-            to = mail_conf.to_addresses.clone();
-            let mut mail_to: Vec<EmailAddress> = Vec::new();
-
-            for m in to {
-                mail_to.push(EmailAddress::new(m));
-            }
-
-            if mail_conf.authentication_mechanism == "login".to_string() {
-                auth = Mechanism::Login;
-            }
-            else if mail_conf.authentication_mechanism == "crammd5".to_string() {
-                auth = Mechanism::CramMd5;
-            }
-
+                let previous = match notify::read_notify_state(&state_file) {
+                    Ok(s) => s,
+                    Err(e) => return Err(format!("Error sending mail: {}", e)),
+                };
+                let mut state = previous.clone();
+                let n = notify::notification(policy, &mut state, report, timezone::config_timezone(config).today());
+                (n, Some((previous, state)))
+            };
 
+            let res = match notification {
+                notify::Notification::Run => match mail::compose(mail_conf, report, log, date) {
+                    Ok(m) => smtp::send(mail_conf, &m),
+                    Err(e) => Err(format!("Error sending mail: {}", e)),
+                },
+                notify::Notification::Digest(runs) => {
+                    smtp::send(mail_conf, &mail::compose_digest(mail_conf, &runs, date))
+                        .map(|m| format!("{}, digest of {} runs", m, runs.len()))
+                },
+                notify::Notification::Skip => Ok(format!("Email not sent, notify is: {}", policy.name())),
+            };
 
-            // Use TLS certificates
-            if mail_conf.tls_domain.is_some() {
-                mailer_build = SmtpTransport::simple_builder(mail_conf.tls_domain.clone().unwrap()).unwrap();
-            }
-            else {
-                mailer_build = SmtpTransport::builder_unencrypted_localhost().unwrap();
-        
+            if let Some((previous, state)) = states {
+                let state = match res {
+                    Ok(_) => state,
+                    Err(_) => notify::unsent(policy, &previous, report),
+                };
+
+                if let Err(e) = notify::write_notify_state(&state_file, &state) {
+                    return match res {
+                        Ok(m) => Err(format!("{}\n{}", m, e)),
+                        Err(m) => Err(format!("{}\n{}", m, e)),
+                    };
+                }
             }
 
-            let email = SimpleSendableEmail::new(
-                EmailAddress::new(mail_conf.from_sender_address.to_string()),
-                mail_to,
-                "message_id".to_string(),
-                msg,
-            );
-
-            let mut mailer = mailer_build.credentials(Credentials::new(mail_conf.username.clone(), mail_conf.password.clone()))
-                .smtp_utf8(true)
-                .authentication_mechanism(auth).build();
-
-            match mailer.send(&email) {
-                Ok(_) => return "Master: Email sent OK".to_string(),
-                Err(e) => return format!("Master: Error sending mail: {}", e.to_string()),
-            }
+            return res;
         }
     }
     
-    "Master: Email is not enabled".to_string()
+    Ok("Email is not enabled".to_string())
+}
+
+/// Validator for numeric arguments
+fn is_number(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(v) if v >= 0.0 => Ok(()),
+        _ => Err(format!("Not a valid number: {}", value)),
+    }
 }
 
 /// Build argument parsing and help text
@@ -750,6 +1478,13 @@ if not specified in config file."))
 for both master and slave cluster.
 Option SLAVE_HOST is required
 if not specified in config file."))
+       .arg(Arg::with_name("FORMAT")
+            .long("format")
+            .takes_value(true)
+            .possible_values(&["text", "json", "csv"])
+            .requires("INFO")
+            .help("Output format of INFO, text (default),
+json or csv."))
        .arg(Arg::with_name("SNAPSHOT")
             .short("c")
             .long("create-snapshots")
@@ -758,6 +1493,85 @@ if not specified in config file."))
 master and slave cluster.
 Takes information about SLAVE_HOST, VOLUME,
 USER and SLAVE from config file."))
+       .subcommand(SubCommand::with_name("snapshot")
+            .about("Creates a manual snapshot on both master and slave cluster.
+Manual snapshots are named according to config setting
+manual_snapshot_name_template and are only removed
+according to config setting manual_snapshot_expire_days.")
+            .arg(Arg::with_name("LABEL")
+                 .short("l")
+                 .long("label")
+                 .takes_value(true)
+                 .required(true)
+                 .validator(|l| if ggsnap_utils::is_valid_label(&l) {
+                     Ok(())
+                 }
+                 else {
+                     Err(String::from("LABEL may only contain letters, digits and -"))
+                 })
+                 .help("Label of snapshot, may only contain
+letters, digits and -")))
+       .subcommand(SubCommand::with_name("check")
+            .about("Monitoring plugin for Nagios and Icinga, checks age of
+newest snapshot on master and slave, number of snapshots
+that differ, geo-replication status and result of last run
+(from report_file or metrics_file). Exit code is 0 OK,
+1 WARNING, 2 CRITICAL or 3 UNKNOWN, output has perfdata.")
+            .arg(Arg::with_name("WARNING_AGE")
+                 .long("warning-age")
+                 .takes_value(true)
+                 .value_name("HOURS")
+                 .default_value("26")
+                 .validator(is_number)
+                 .help("Warning if newest snapshot is older"))
+            .arg(Arg::with_name("CRITICAL_AGE")
+                 .long("critical-age")
+                 .takes_value(true)
+                 .value_name("HOURS")
+                 .default_value("50")
+                 .validator(is_number)
+                 .help("Critical if newest snapshot is older"))
+            .arg(Arg::with_name("WARNING_DRIFT")
+                 .long("warning-drift")
+                 .takes_value(true)
+                 .value_name("COUNT")
+                 .default_value("1")
+                 .validator(is_number)
+                 .help("Warning if this many snapshots differ"))
+            .arg(Arg::with_name("CRITICAL_DRIFT")
+                 .long("critical-drift")
+                 .takes_value(true)
+                 .value_name("COUNT")
+                 .default_value("3")
+                 .validator(is_number)
+                 .help("Critical if this many snapshots differ")))
+       .subcommand(SubCommand::with_name("daemon")
+            .about("Runs in foreground and creates snapshots according to
+[schedule] in config file instead of using cron.
+SIGTERM stops daemon and SIGHUP reloads config file."))
+       .subcommand(SubCommand::with_name("pin")
+            .about("Pins a snapshot on both master and slave cluster,
+a pinned snapshot is never removed by ggsnap.")
+            .arg(Arg::with_name("NAME")
+                 .required_unless("LIST")
+                 .help("Name of snapshot to pin"))
+            .arg(Arg::with_name("UNTIL")
+                 .long("until")
+                 .takes_value(true)
+                 .value_name("DATE")
+                 .conflicts_with("LIST")
+                 .help("Snapshot is pinned until and including
+DATE, format: YYYY-MM-DD"))
+            .arg(Arg::with_name("LIST")
+                 .short("l")
+                 .long("list")
+                 .conflicts_with("NAME")
+                 .help("Lists all pinned snapshots on master cluster")))
+       .subcommand(SubCommand::with_name("unpin")
+            .about("Removes pin from a snapshot on both master and slave cluster")
+            .arg(Arg::with_name("NAME")
+                 .required(true)
+                 .help("Name of snapshot to unpin")))
        .after_help("Important! This program must run on master node
 
 To create snapshots two alternatives are available:
@@ -0,0 +1,277 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use chrono::prelude::*;
+use ggsnap_utils::HostType;
+use ggsnap_utils::event::EventLog;
+use ggsnap_utils::report::RunReport;
+use stats::SnapStat;
+
+/// Counters and values kept from previous runs,
+/// read back from the textfile written last time.
+static KEPT_METRICS: &'static [&'static str] = &["ggsnap_last_success_timestamp_seconds",
+                                                  "ggsnap_runs_total",
+                                                  "ggsnap_failures_total",
+                                                  "ggsnap_deleted_snapshots_total"];
+
+/// Metrics as series, ex: ggsnap_runs_total{session="...",result="success"},
+/// with value. Sorted so output is stable.
+pub type Series = BTreeMap<String, f64>;
+
+/// Help text and type for each metric
+fn metric_help(name: &str) -> (&'static str, &'static str) {
+    match name {
+        "ggsnap_last_run_timestamp_seconds" => ("Time of last run", "gauge"),
+        "ggsnap_last_run_success" => ("1 if last run was successful", "gauge"),
+        "ggsnap_last_success_timestamp_seconds" => ("Time of last successful run", "gauge"),
+        "ggsnap_run_duration_seconds" => ("Duration of last run", "gauge"),
+        "ggsnap_pause_duration_seconds" => ("Time geo-replication was paused in last run", "gauge"),
+        "ggsnap_snapshots" => ("Number of snapshots created by ggsnap", "gauge"),
+        "ggsnap_snapshots_differing" => ("Number of snapshots that differ between master and slave", "gauge"),
+        "ggsnap_deleted_snapshots" => ("Snapshots deleted in last run", "gauge"),
+        "ggsnap_deleted_snapshots_total" => ("Snapshots deleted", "counter"),
+        "ggsnap_runs_total" => ("Runs by result", "counter"),
+        "ggsnap_failures_total" => ("Failed runs by phase", "counter"),
+        "ggsnap_geo_replication_paused" => ("1 if geo-replication is paused by ggsnap", "gauge"),
+        _ => ("", "untyped"),
+    }
+}
+
+pub fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn series(name: &str, session: &str, labels: &[(&str, &str)]) -> String {
+    let mut s = format!("{}{{session=\"{}\"", name, escape(session));
+    for &(k, v) in labels {
+        s = format!("{},{}=\"{}\"", s, k, escape(v));
+    }
+    format!("{}}}", s)
+}
+
+fn side(host: &HostType) -> &'static str {
+    if *host == HostType::Master { "master" } else { "slave" }
+}
+
+/// Parses metrics in Prometheus text format,
+/// comments and invalid lines are skipped.
+pub fn parse(text: &str) -> Series {
+    let mut metrics = Series::new();
+
+    for l in text.lines() {
+        if l.starts_with('#') {
+            continue;
+        }
+
+        if let Some(i) = l.rfind(' ') {
+            if let Ok(v) = l[i + 1..].parse::<f64>() {
+                metrics.insert(l[..i].to_string(), v);
+            }
+        }
+    }
+
+    metrics
+}
+
+/// Reads metrics written by last run, empty if
+/// file does not exist.
+pub fn read_previous(path: &Path) -> Series {
+    match fs::read_to_string(path) {
+        Ok(t) => parse(&t),
+        Err(_) => Series::new(),
+    }
+}
+
+/// Time geo-replication was paused, from start of phase
+/// pause to end of phase resume.
+fn pause_duration(log: &EventLog, end: DateTime<Utc>) -> Option<f64> {
+    let start = match log.phases.iter().position(|p| p.name == "pause") {
+        Some(i) => i,
+        None => return None,
+    };
+
+    let resume = match log.phases.iter().position(|p| p.name == "resume") {
+        Some(i) => i,
+        None => log.phases.len() - 1,
+    };
+
+    let stop = match log.phases.get(resume + 1) {
+        Some(p) => p.start,
+        None => end,
+    };
+
+    match stop.signed_duration_since(log.phases[start].start).num_milliseconds() {
+        ms if ms >= 0 => Some(ms as f64 / 1000.0),
+        _ => None,
+    }
+}
+
+/// Metrics of a run, counters continue from previous.
+/// Series of other sessions are kept unchanged since
+/// all sessions share the same metrics file.
+/// Snapshot counts are added if statistics of master
+/// and slave could be read.
+pub fn run_metrics(log: &EventLog, report: &RunReport, end: DateTime<Utc>,
+                   stats: Option<(&SnapStat, &SnapStat)>, previous: &Series) -> Series {
+    let session = log.session.as_str();
+    let session_label = format!("{{session=\"{}\"", escape(session));
+    let mut metrics = Series::new();
+
+    for (k, v) in previous.iter() {
+        if !k.contains(&session_label) ||
+           KEPT_METRICS.iter().any(|m| k.starts_with(&format!("{}{{", m))) {
+            metrics.insert(k.clone(), *v);
+        }
+    }
+
+    let now = end.timestamp() as f64;
+    metrics.insert(series("ggsnap_last_run_timestamp_seconds", session, &[]), now);
+    metrics.insert(series("ggsnap_last_run_success", session, &[]), if report.success { 1.0 } else { 0.0 });
+    metrics.insert(series("ggsnap_run_duration_seconds", session, &[]), report.duration_seconds);
+
+    if report.success {
+        metrics.insert(series("ggsnap_last_success_timestamp_seconds", session, &[]), now);
+    }
+
+    if let Some(p) = pause_duration(log, end) {
+        metrics.insert(series("ggsnap_pause_duration_seconds", session, &[]), p);
+    }
+
+    let result = if report.success { "success" } else { "failure" };
+    *metrics.entry(series("ggsnap_runs_total", session, &[("result", result)])).or_insert(0.0) += 1.0;
+
+    if !report.success {
+        for s in report.steps.iter().filter(|s| s.status == "failed") {
+            *metrics.entry(series("ggsnap_failures_total", session, &[("phase", &s.name)])).or_insert(0.0) += 1.0;
+        }
+    }
+
+    for host in &[HostType::Master, HostType::Slave] {
+        let deleted = report.deleted.iter().filter(|d| d.host == *host).count() as f64;
+        metrics.insert(series("ggsnap_deleted_snapshots", session, &[("side", side(host))]), deleted);
+        *metrics.entry(series("ggsnap_deleted_snapshots_total", session, &[("side", side(host))])).or_insert(0.0) += deleted;
+    }
+
+    if let Some((master, slave)) = stats {
+        metrics.insert(series("ggsnap_snapshots", session, &[("side", "master")]), master.len() as f64);
+        metrics.insert(series("ggsnap_snapshots", session, &[("side", "slave")]), slave.len() as f64);
+        metrics.insert(series("ggsnap_snapshots_differing", session, &[]), master.number_diff(slave) as f64);
+    }
+
+    metrics
+}
+
+/// Formats metrics in Prometheus text format
+/// with HELP and TYPE for each metric.
+pub fn format(metrics: &Series) -> String {
+    let mut text = String::new();
+    let mut last_name = String::new();
+
+    for (k, v) in metrics.iter() {
+        let name = match k.find('{') {
+            Some(i) => &k[..i],
+            None => k.as_str(),
+        };
+
+        if name != last_name {
+            let (help, kind) = metric_help(name);
+            text = format!("{}# HELP {} {}\n# TYPE {} {}\n", text, name, help, name, kind);
+            last_name = name.to_string();
+        }
+
+        text = format!("{}{} {}\n", text, k, v);
+    }
+
+    text
+}
+
+/// Writes metrics to textfile, written to a temporary
+/// file first so node_exporter never reads a partial file.
+pub fn write_textfile(path: &Path, metrics: &Series) -> Result<(), String> {
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".tmp");
+
+    if let Err(e) = fs::write(&tmp, format(metrics)) {
+        return Err(format!("Master: Error writing metrics file: {}\nMaster: Error: {}", path.display(), e));
+    }
+
+    match fs::rename(&tmp, path) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Master: Error writing metrics file: {}\nMaster: Error: {}", path.display(), e)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggsnap_utils::event::Event;
+    use ggsnap_utils::Config;
+    use chrono::Duration;
+
+    #[test]
+    fn textfile_metrics() {
+        let mut log = EventLog::new("vol root@slave::svol");
+        log.set_phase("pause");
+        log.set_phase("remove");
+        log.extend(vec![Event::deleted(HostType::Master, "ggsnap_vol_20180214_095616")]);
+        log.set_phase("resume");
+        log.error(HostType::Master, "Error resuming geo-replication");
+        let end = log.phases[3].start + Duration::seconds(3);
+        let report = RunReport::from_log(&log, None, false, end);
+
+        let mut config = Config::default_config();
+        config.snapshot.snapshot_name_prefix = Some(String::from("ggsnap"));
+        config.snapshot.master_volume = Some(String::from("vol"));
+        config.snapshot.slave_volume = Some(String::from("vol"));
+        let master = SnapStat::new(String::from("ggsnap_vol_20180216_114403\nggsnap_vol_20180216_115150"), &config, HostType::Master);
+        let slave = SnapStat::new(String::from("ggsnap_vol_20180216_114403"), &config, HostType::Slave);
+
+        let previous = parse("# HELP ggsnap_runs_total Runs by result\n\
+                              ggsnap_runs_total{session=\"vol root@slave::svol\",result=\"failure\"} 2\n\
+                              ggsnap_last_success_timestamp_seconds{session=\"vol root@slave::svol\"} 1518000000\n\
+                              ggsnap_runs_total{session=\"other\",result=\"failure\"} 5\n\
+                              ggsnap_last_run_success{session=\"other\"} 1\n\
+                              ggsnap_run_duration_seconds{session=\"vol root@slave::svol\"} 10\n\
+                              ggsnap_last_run_success{session=\"vol root@slave::svol\"} 1\n");
+        let metrics = run_metrics(&log, &report, end, Some((&master, &slave)), &previous);
+
+        let s = "{session=\"vol root@slave::svol\"";
+        assert_eq!(metrics[&format!("ggsnap_runs_total{},result=\"failure\"}}", s)], 3.0);
+        assert_eq!(metrics[&format!("ggsnap_last_success_timestamp_seconds{}}}", s)], 1518000000.0);
+        assert_eq!(metrics[&format!("ggsnap_last_run_success{}}}", s)], 0.0);
+        assert_eq!(metrics[&format!("ggsnap_failures_total{},phase=\"resume\"}}", s)], 1.0);
+        assert_eq!(metrics[&format!("ggsnap_deleted_snapshots_total{},side=\"master\"}}", s)], 1.0);
+        assert_eq!(metrics[&format!("ggsnap_snapshots{},side=\"master\"}}", s)], 2.0);
+        assert_eq!(metrics[&format!("ggsnap_snapshots_differing{}}}", s)], 1.0);
+        assert!(metrics[&format!("ggsnap_pause_duration_seconds{}}}", s)] >= 3.0);
+        assert_eq!(metrics["ggsnap_runs_total{session=\"other\",result=\"failure\"}"], 5.0);
+        assert_eq!(metrics["ggsnap_last_run_success{session=\"other\"}"], 1.0);
+        assert!(metrics[&format!("ggsnap_run_duration_seconds{}}}", s)] < 10.0);
+
+        let text = format(&metrics);
+        assert!(text.contains("# TYPE ggsnap_runs_total counter\nggsnap_runs_total{session=\"other\",result=\"failure\"} 5\n\
+                               ggsnap_runs_total{session=\"vol root@slave::svol\",result=\"failure\"} 3\n"));
+        assert_eq!(parse(&text), metrics);
+    }
+}
@@ -0,0 +1,267 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::process::Command;
+use ggsnap_utils::{ Config, HostType };
+use ggsnap_utils::event::Event;
+
+/// Seconds before ssh gives up connecting to slave
+static SSH_CONNECT_TIMEOUT: &'static str = "ConnectTimeout=10";
+
+/// Parses output from gluster volume status VOLUME detail
+/// and returns all bricks that are not online.
+pub fn offline_bricks(status_detail: &str) -> Vec<String> {
+    let mut offline: Vec<String> = Vec::new();
+    let mut brick = String::new();
+
+    for l in status_detail.lines() {
+        let mut kv = l.splitn(2, ':');
+        let key = kv.next().unwrap_or("").trim();
+        let value = kv.next().unwrap_or("").trim();
+
+        if key == "Brick" {
+            brick = value.split_whitespace().last().unwrap_or("").to_string();
+        }
+        else if key == "Online" && value != "Y" {
+            offline.push(brick.clone());
+        }
+    }
+
+    offline
+}
+
+/// Parses output from gluster volume geo-replication
+/// MASTER SLAVE status and returns master node and
+/// status for every brick in session.
+pub fn geo_replication_status(status: &str) -> Vec<(String, String)> {
+    let mut nodes: Vec<(String, String)> = Vec::new();
+    let mut status_col: Option<usize> = None;
+
+    for l in status.lines() {
+        if status_col.is_none() {
+            if l.contains("MASTER NODE") {
+                status_col = l.find(" STATUS ").map(|i| i + 1);
+            }
+            continue;
+        }
+
+        if l.starts_with("---") || l.trim().len() == 0 {
+            continue;
+        }
+
+        let node = l.split_whitespace().next().unwrap_or("").to_string();
+        let state = match l.get(status_col.unwrap()..) {
+            Some(s) => s.split_whitespace().next().unwrap_or("").to_string(),
+            None => String::new(),
+        };
+
+        nodes.push((node, state));
+    }
+
+    nodes
+}
+
+/// Runs command and returns output if command succeeded
+fn run(cmd: &mut Command, desc: &str) -> Result<String, String> {
+    match cmd.output() {
+        Ok(o) => {
+            let o_str = format!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
+            if o.status.success() {
+                Ok(o_str)
+            }
+            else {
+                Err(format!("{} failed: {}", desc, o_str.trim()))
+            }
+        },
+        Err(e) => Err(format!("Error running command: {}; {}", desc, e)),
+    }
+}
+
+/// All bricks of master volume are online
+fn check_bricks(config: &Config) -> Result<String, String> {
+    let volume = config.snapshot.master_volume.clone().unwrap();
+    let out = match run(Command::new(&config.general.gluster_bin)
+                                .arg("volume")
+                                .arg("status")
+                                .arg(&volume)
+                                .arg("detail"),
+                        &format!("gluster volume status {} detail", volume)) {
+        Ok(o) => o,
+        Err(e) => return Err(e),
+    };
+
+    let offline = offline_bricks(&out);
+    if offline.len() > 0 {
+        Err(format!("Bricks not online: {}", offline.join(", ")))
+    }
+    else {
+        Ok(String::from("All bricks online"))
+    }
+}
+
+/// Runs gluster volume geo-replication status for
+/// session and returns master nodes with status
+pub fn geo_replication_nodes(config: &Config) -> Result<Vec<(String, String)>, String> {
+    let session = format!("{}@{}::{}", config.snapshot.slave_user.clone().unwrap(),
+                          config.snapshot.slave_hostname.clone().unwrap(),
+                          config.snapshot.slave_volume.clone().unwrap());
+    let out = match run(Command::new(&config.general.gluster_bin)
+                                .arg("volume")
+                                .arg("geo-replication")
+                                .arg(&config.snapshot.master_volume.clone().unwrap())
+                                .arg(&session)
+                                .arg("status"),
+                        &format!("gluster volume geo-replication {} {} status",
+                                 config.snapshot.master_volume.clone().unwrap(), session)) {
+        Ok(o) => o,
+        Err(e) => return Err(e),
+    };
+
+    let nodes = geo_replication_status(&out);
+    if nodes.len() == 0 {
+        Err(format!("No geo-replication session found: {}", session))
+    }
+    else {
+        Ok(nodes)
+    }
+}
+
+/// Geo-replication session is Active or Passive on all
+/// bricks, an already paused session is accepted as
+/// snapshot continues on a paused session.
+fn check_geo_replication(config: &Config) -> Result<String, String> {
+    let nodes = match geo_replication_nodes(config) {
+        Ok(n) => n,
+        Err(e) => return Err(e),
+    };
+
+    let bad: Vec<String> = nodes.iter()
+                                .filter(|&&(_, ref s)| s != "Active" && s != "Passive" && s != "Paused")
+                                .map(|&(ref n, ref s)| format!("{}: {}", n, s))
+                                .collect();
+
+    if bad.len() > 0 {
+        Err(format!("Geo-replication is not Active/Passive on: {}", bad.join(", ")))
+    }
+    else {
+        let states: Vec<String> = nodes.iter().map(|&(ref n, ref s)| format!("{}: {}", n, s)).collect();
+        Ok(format!("Geo-replication status: {}", states.join(", ")))
+    }
+}
+
+/// Slave node can be reached over ssh
+fn check_ssh(config: &Config) -> Result<String, String> {
+    let host = config.snapshot.slave_hostname.clone().unwrap();
+    if let Err(e) = run(Command::new("ssh")
+                                .arg("-o")
+                                .arg("BatchMode=yes")
+                                .arg("-o")
+                                .arg(SSH_CONNECT_TIMEOUT)
+                                .arg(&host)
+                                .arg("true"),
+                        &format!("ssh {} true", host)) {
+        return Err(e);
+    }
+
+    Ok(format!("Slave node {} reachable over ssh", host))
+}
+
+/// ggsnap_slave on slave node runs and can list snapshots
+fn check_ggsnap_slave(config: &Config) -> Result<String, String> {
+    let host = config.snapshot.slave_hostname.clone().unwrap();
+    if let Err(e) = run(Command::new("ssh")
+                                .arg("-o")
+                                .arg("BatchMode=yes")
+                                .arg("-o")
+                                .arg(SSH_CONNECT_TIMEOUT)
+                                .arg(&host)
+                                .arg(&config.general.ggsnap_slave_bin)
+                                .arg("--list"),
+                        &format!("ssh {} {} --list", host, config.general.ggsnap_slave_bin)) {
+        return Err(e);
+    }
+
+    Ok(format!("{} responds on slave node", config.general.ggsnap_slave_bin))
+}
+
+/// Runs all pre-flight checks before geo-replication
+/// is paused, the result of every check is logged.
+/// An error is returned if any check fails.
+pub fn run_checks(config: &Config) -> Result<Vec<Event>, Vec<Event>> {
+    let mut log = vec![Event::info(HostType::Master, "Running pre-flight checks")];
+    let mut failed = false;
+    let checks: Vec<(&str, fn(&Config) -> Result<String, String>)> = vec![
+        ("Volume status", check_bricks),
+        ("Geo-replication", check_geo_replication),
+        ("Ssh to slave", check_ssh),
+        ("ggsnap_slave", check_ggsnap_slave),
+    ];
+
+    for (name, check) in checks {
+        match check(config) {
+            Ok(l) => log.push(Event::info(HostType::Master, &format!("Pre-flight {}: OK, {}", name, l))),
+            Err(l) => {
+                log.push(Event::error(HostType::Master, &format!("Pre-flight {}: FAILED, {}", name, l)));
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        log.push(Event::error(HostType::Master, "Pre-flight checks failed, geo-replication is not paused"));
+        Err(log)
+    }
+    else {
+        Ok(log)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status() {
+        let detail = "Status of volume: vol
+------------------------------------------------------------------------------
+Brick                : Brick gluster1:/bricks/b1/brick
+TCP Port             : 49152
+Online               : Y
+------------------------------------------------------------------------------
+Brick                : Brick gluster2:/bricks/b2/brick
+TCP Port             : N/A
+Online               : N
+";
+        assert_eq!(offline_bricks(detail), vec![String::from("gluster2:/bricks/b2/brick")]);
+
+        let geo = "
+MASTER NODE    MASTER VOL    MASTER BRICK     SLAVE USER    SLAVE                  SLAVE NODE    STATUS     CRAWL STATUS       LAST_SYNCED
+---------------------------------------------------------------------------------------------------------------------------------------------
+gluster1       vol           /bricks/b1       root          slave1::slavevol       slave1        Active     Changelog Crawl    2018-02-16 11:44:03
+gluster2       vol           /bricks/b2       root          slave1::slavevol       slave1        Faulty     N/A                N/A
+";
+        assert_eq!(geo_replication_status(geo),
+                   vec![(String::from("gluster1"), String::from("Active")),
+                        (String::from("gluster2"), String::from("Faulty"))]);
+        assert_eq!(geo_replication_status("No active geo-replication sessions").len(), 0);
+    }
+}
@@ -0,0 +1,236 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+use lettre::smtp::authentication::{ Credentials, Mechanism };
+use lettre::{ ClientSecurity, ClientTlsParameters, EmailAddress, EmailTransport, SimpleSendableEmail, SmtpTransport };
+use native_tls::{ Certificate, TlsConnector };
+use ggsnap_utils::MailFromMaster;
+use ggsnap_utils::mail::{ self, MailMessage, SmtpSettings, TlsMode };
+
+/// TLS connector that trusts certificates in ca_file
+/// in PEM format together with system certificates
+fn tls_parameters(settings: &SmtpSettings) -> Result<ClientTlsParameters, String> {
+    let mut builder = match TlsConnector::builder() {
+        Ok(b) => b,
+        Err(e) => return Err(format!("Error creating TLS connector: {}", e)),
+    };
+
+    if let Some(ref ca_file) = settings.ca_file {
+        let mut pem: Vec<u8> = Vec::new();
+        if let Err(e) = File::open(ca_file).and_then(|mut f| f.read_to_end(&mut pem)) {
+            return Err(format!("Error reading smtp_ca_file: {}; {}", ca_file, e));
+        }
+
+        let cert = match Certificate::from_pem(&pem) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Error in smtp_ca_file: {}; {}", ca_file, e)),
+        };
+
+        if let Err(e) = builder.add_root_certificate(cert) {
+            return Err(format!("Error adding smtp_ca_file: {}; {}", ca_file, e));
+        }
+    }
+
+    match builder.build() {
+        Ok(c) => Ok(ClientTlsParameters::new(settings.tls_domain.clone(), c)),
+        Err(e) => Err(format!("Error creating TLS connector: {}", e)),
+    }
+}
+
+/// Builds SMTP transport from settings
+pub fn transport(settings: &SmtpSettings) -> Result<SmtpTransport, String> {
+    let security = match settings.tls {
+        TlsMode::None => ClientSecurity::None,
+        TlsMode::Starttls => match tls_parameters(settings) {
+            Ok(p) => ClientSecurity::Required(p),
+            Err(e) => return Err(e),
+        },
+        TlsMode::Implicit => match tls_parameters(settings) {
+            Ok(p) => ClientSecurity::Wrapper(p),
+            Err(e) => return Err(e),
+        },
+    };
+
+    let mut builder = match SmtpTransport::builder((settings.host.as_str(), settings.port), security) {
+        Ok(b) => b.smtp_utf8(true).timeout(Some(Duration::from_secs(settings.timeout))),
+        Err(e) => return Err(format!("Error connecting to SMTP server: {}:{}; {}", settings.host, settings.port, e)),
+    };
+
+    if let Some(ref auth) = settings.auth {
+        let mechanism = match auth.mechanism.as_str() {
+            "login" => Mechanism::Login,
+            "crammd5" => Mechanism::CramMd5,
+            _ => Mechanism::Plain,
+        };
+
+        builder = builder.credentials(Credentials::new(auth.username.clone(), auth.password.clone()))
+                         .authentication_mechanism(mechanism);
+    }
+
+    Ok(builder.build())
+}
+
+/// Sends mail with settings in [mail_from_master],
+/// smtp_timeout applies to SMTP commands, connecting
+/// to server is only limited by the system TCP timeout.
+pub fn send(mail_conf: &MailFromMaster, msg: &MailMessage) -> Result<String, String> {
+    let settings = mail::smtp_settings(mail_conf);
+
+    let mut mailer = match transport(&settings) {
+        Ok(m) => m,
+        Err(e) => return Err(format!("Error sending mail: {}", e)),
+    };
+
+    let email = SimpleSendableEmail::new(
+        EmailAddress::new(msg.from.clone()),
+        msg.to.iter().map(|t| EmailAddress::new(t.clone())).collect(),
+        msg.message_id.clone(),
+        msg.format(),
+    );
+
+    match mailer.send(&email) {
+        Ok(_) => Ok("Email sent OK".to_string()),
+        Err(e) => Err(format!("Error sending mail: {}", e)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ BufRead, BufReader, Write };
+    use std::net::TcpListener;
+    use std::thread;
+    use chrono::prelude::*;
+
+    /// Minimal SMTP server, serves connections until
+    /// one mail is received and returns all lines
+    /// received from client in that session.
+    fn smtp_stand_in(listener: TcpListener) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            loop {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut lines: Vec<String> = Vec::new();
+                let mut data = false;
+                let mut received = false;
+
+                stream.write_all(b"220 stand-in ESMTP\r\n").unwrap();
+                loop {
+                    let mut line = String::new();
+                    match reader.read_line(&mut line) {
+                        Ok(n) if n > 0 => (),
+                        _ => break,
+                    }
+                    let line = line.trim_end().to_string();
+                    lines.push(line.clone());
+
+                    let reply: &[u8] = if data {
+                        if line != "." {
+                            continue;
+                        }
+                        data = false;
+                        received = true;
+                        b"250 queued\r\n"
+                    }
+                    else if line.starts_with("EHLO") {
+                        b"250-stand-in\r\n250 8BITMIME\r\n"
+                    }
+                    else if line == "DATA" {
+                        data = true;
+                        b"354 end with .\r\n"
+                    }
+                    else if line == "QUIT" {
+                        let _ = stream.write_all(b"221 bye\r\n");
+                        break;
+                    }
+                    else {
+                        b"250 ok\r\n"
+                    };
+                    stream.write_all(reply).unwrap();
+                }
+
+                if received {
+                    return lines;
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn smtp_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = smtp_stand_in(listener);
+
+        let mut mail_conf = MailFromMaster {
+            smtp_host: Some(String::from("127.0.0.1")),
+            smtp_port: Some(port),
+            smtp_tls: Some(String::from("none")),
+            smtp_ca_file: None,
+            smtp_timeout: Some(5),
+            tls_domain: None,
+            authentication_mechanism: String::new(),
+            username: String::new(),
+            password: String::new(),
+            from_sender_address: String::from("ggsnap@example.com"),
+            to_addresses: vec![String::from("ops@example.com")],
+            subject: String::from("Snapshot"),
+            enable: true,
+            format: None,
+            text_template: None,
+            html_template: None,
+            attach_log: None,
+            notify: None,
+            notify_state_file: None,
+        };
+
+        let msg = MailMessage {
+            from: mail_conf.from_sender_address.clone(),
+            to: mail_conf.to_addresses.clone(),
+            subject: String::from("Snapshot: OK"),
+            date: Local::now(),
+            message_id: mail::message_id("ggsnap@example.com", Utc::now()),
+            text: String::from("ggsnap run: OK\n"),
+            html: None,
+            attachment: None,
+        };
+
+        assert_eq!(send(&mail_conf, &msg), Ok(String::from("Email sent OK")));
+
+        let lines = server.join().unwrap();
+        assert!(lines.iter().any(|l| l.starts_with("MAIL FROM:<ggsnap@example.com>")));
+        assert!(lines.iter().any(|l| l.starts_with("RCPT TO:<ops@example.com>")));
+        assert!(lines.contains(&String::from("Subject: Snapshot: OK")));
+        assert!(lines.contains(&format!("Message-ID: {}", msg.message_id)));
+        assert!(!lines.iter().any(|l| l.starts_with("AUTH")));
+
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        mail_conf.smtp_port = Some(closed_port);
+        assert!(send(&mail_conf, &msg).unwrap_err().starts_with("Error sending mail: "));
+    }
+}
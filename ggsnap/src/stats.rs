@@ -19,119 +19,144 @@
 /////////////////////////////////////////////////////////////////////////////////
 
 
-extern crate regex;
-
 use std::process::Command;
-use self::regex::Regex;
-use ggsnap_utils::Config;
+use chrono::prelude::*;
+use ggsnap_utils::{host_volume, manual_snapshot_template, snapshot_template, Config, HostType};
+
+/// A snapshot created by ggsnap with
+/// time parsed from snapshot name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    pub time: DateTime<Utc>,
+    pub side: HostType,
+}
 
 pub struct SnapStat {
-    snapnames: Vec<String>,
+    snapshots: Vec<Snapshot>,
+    others: Vec<String>,
 }
 
 impl SnapStat {
     /// Creates a new SnapStat containing valid snapshots
-    pub fn new(gluster_snap_list: String, volume: &String) -> SnapStat {
-        let mut snap_list: Vec<String> = Vec::new();
-        let r_str = format!("^ggsnap_{}_\\d{{8}}_\\d{{6}}$", volume);
-        let re_snap = Regex::new(r_str.as_str()).unwrap();
+    /// on master or slave, names are parsed with config
+    /// settings snapshot_name_template, snapshot_name_prefix
+    /// and volume name of side.
+    /// Snapshots not named by ggsnap, neither scheduled
+    /// nor manual, are kept separately.
+    pub fn new(gluster_snap_list: String, config: &Config, side: HostType) -> SnapStat {
+        let mut snapshots: Vec<Snapshot> = Vec::new();
+        let mut others: Vec<String> = Vec::new();
+        let template = snapshot_template(config);
+        let manual_template = manual_snapshot_template(config);
+        let prefix = config.snapshot.snapshot_name_prefix.clone().unwrap();
+        let volume = host_volume(config, &side);
 
         for line in gluster_snap_list.split("\n") {
-            if re_snap.is_match(line) {
-                snap_list.push(line.to_string());
+            let line = line.trim();
+
+            if let Some(n) = template.parse(line, &prefix, &volume) {
+                snapshots.push(Snapshot { name: line.to_string(), time: n.time, side: side });
+            }
+            else if !line.is_empty() && line != "No snapshots present" &&
+                    manual_template.parse(line, &prefix, &volume).is_none() {
+                others.push(line.to_string());
             }
         }
 
-        SnapStat { snapnames: snap_list }
+        snapshots.sort_by(|a, b| a.time.cmp(&b.time).then(a.name.cmp(&b.name)));
+
+        SnapStat { snapshots: snapshots, others: others }
     }
-    
+
     /// Total number of valid snapshots
     pub fn len(&self) -> usize {
-        self.snapnames.len()
+        self.snapshots.len()
+    }
+
+    /// Names of valid snapshots, oldest first
+    pub fn names(&self) -> Vec<String> {
+        self.snapshots.iter().map(|s| s.name.clone()).collect()
     }
 
     /// Returns the latest created snapshot
-    pub fn newest_snap(&self) -> String {
-        let mut newest_snap_date = 0;
-        let mut newest_snap_time = 0;
-        let mut newest_snap: String = String::from("");
-
-        for snap in self.snapnames.iter() {
-            let snap_split: Vec<&str> = snap.split('_').collect();
-            let snap_d: i32 = snap_split[2].parse::<i32>().unwrap();
-            let snap_t: i32 = snap_split[3].parse::<i32>().unwrap();
-            
-            if snap_d > newest_snap_date {
-                newest_snap_date = snap_d;
-                newest_snap_time = snap_t;
-                newest_snap = snap.clone();
-            }
-            else if snap_d == newest_snap_date && snap_t > newest_snap_time {
-                newest_snap_time = snap_t;
-                newest_snap = snap.clone();
-            }
-        }
+    pub fn newest_snap(&self) -> Option<&Snapshot> {
+        self.snapshots.last()
+    }
 
-        newest_snap
+    /// Returns creation time of the latest snapshot
+    pub fn newest_time(&self) -> Option<DateTime<Utc>> {
+        self.newest_snap().map(|s| s.time)
     }
 
     /// Returns the oldest created snapshot
-    pub fn oldest_snap(&self) -> String {
-        let mut oldest_snap_date = 99999999;
-        let mut oldest_snap_time = 999999;
-        let mut oldest_snap: String = String::from("");
-
-        for snap in self.snapnames.iter() {
-            let snap_split: Vec<&str> = snap.split('_').collect();
-            let snap_d: i32 = snap_split[2].parse::<i32>().unwrap();
-            let snap_t: i32 = snap_split[3].parse::<i32>().unwrap();
-            
-            if snap_d < oldest_snap_date {
-                oldest_snap_date = snap_d;
-                oldest_snap_time = snap_t;
-                oldest_snap = snap.clone();
-            }
-            else if snap_d == oldest_snap_date && snap_t < oldest_snap_time {
-                oldest_snap_time = snap_t;
-                oldest_snap = snap.clone();
-            }
-        }
+    pub fn oldest_snap(&self) -> Option<&Snapshot> {
+        self.snapshots.first()
+    }
+
+    /// Snapshots that are not created by ggsnap
+    pub fn others(&self) -> &Vec<String> {
+        &self.others
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.snapshots.iter().any(|s| s.name == name)
+    }
 
-        oldest_snap
+    /// Returns valid snapshots that are
+    /// missing in other SnapStat
+    pub fn missing_in(&self, other: &SnapStat) -> Vec<String> {
+        self.snapshots.iter().filter(|s| !other.contains(&s.name)).map(|s| s.name.clone()).collect()
     }
 
     /// Returns the number of different snapshots
     /// comparing two SnapStat struct.
     pub fn number_diff(&self, other: &SnapStat) -> u32 {
-        let mut no_diff: u32 = 0;
-
-        for snap in self.snapnames.iter() {
-            if !other.snapnames.contains(&snap) {
-                no_diff += 1;
-            }
-        }
-
-        for snap in other.snapnames.iter() {
-            if !self.snapnames.contains(&snap) {
-                no_diff += 1;
-            }
-        }
-
-        no_diff
+        (self.missing_in(other).len() + other.missing_in(self).len()) as u32
     }
 
 }
 
 /// Runs gluster command to get all snapshots
-pub fn get_statistics(config: &Config) -> SnapStat {
-    let output = Command::new(&config.general.gluster_bin)
-                         .arg("snapshot")
-                         .arg("list")
-                         .output()
-                         .expect("Error executing command: gluster snapshot list");
+pub fn get_statistics(config: &Config) -> Result<SnapStat, String> {
+    let output = match Command::new(&config.general.gluster_bin)
+                               .arg("snapshot")
+                               .arg("list")
+                               .output() {
+        Ok(o) => o,
+        Err(e) => return Err(format!("Master: Error executing command: gluster snapshot list; {}", e)),
+    };
 
     let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
-    SnapStat::new(stdout, &config.snapshot.master_volume.clone().unwrap())
+    Ok(SnapStat::new(stdout, config, HostType::Master))
+}
+
+/// Runs ggsnap_slave --list on slave node
+/// to get all snapshots on slave
+pub fn get_slave_statistics(config: &Config) -> Result<SnapStat, String> {
+    let cmd_out = Command::new("/bin/ssh")
+                          .arg(&config.snapshot.slave_hostname.clone().unwrap())
+                          .arg(&config.general.ggsnap_slave_bin)
+                          .arg("--list")
+                          .output();
+
+    match cmd_out {
+        Ok(o) => {
+            if o.status.success() {
+                Ok(SnapStat::new(String::from_utf8_lossy(&o.stdout).to_string(), &config, HostType::Slave))
+            }
+            else {
+                Err(format!("Master: Error running ggsnap_slave: {}{}",
+                            String::from_utf8_lossy(&o.stdout),
+                            String::from_utf8_lossy(&o.stderr)))
+            }
+        },
+        Err(e) => {
+            Err(format!("Master: Error running command: ssh {} {} --list; {}",
+                        config.snapshot.slave_hostname.clone().unwrap(),
+                        config.general.ggsnap_slave_bin, e))
+        },
+    }
 }
 
 
@@ -150,7 +175,9 @@ snap_vol_20180216_114403
 snap_vol_20180216_115150
 snap_vol_20180216_115548
 snap_vol_20180216_115928
-snap_vol_20180216_120438");
+snap_vol_20180216_120438
+my_backup_before_upgrade
+No snapshots present");
 
         let gluster_snap2: String = String::from(
 "snap_vol_20180214_095616
@@ -179,16 +206,40 @@ snap_vol_20180216_115924
 snap_vol_20180216_120438");
 
 
-        let stat = SnapStat::new(gluster_snap, &String::from("vol"));
-        let stat2 = SnapStat::new(gluster_snap2, &String::from("vol"));
-        let stat3 = SnapStat::new(gluster_snap3, &String::from("vol"));
-        let stat4 = SnapStat::new(gluster_snap4, &String::from("vol"));
+        let mut config = Config::default_config();
+        config.snapshot.snapshot_name_prefix = Some(String::from("snap"));
+        config.snapshot.master_volume = Some(String::from("vol"));
+        config.snapshot.slave_volume = Some(String::from("svol"));
+
+        let stat = SnapStat::new(gluster_snap, &config, HostType::Master);
+        let stat2 = SnapStat::new(gluster_snap2, &config, HostType::Master);
+        let stat3 = SnapStat::new(gluster_snap3, &config, HostType::Master);
+        let stat4 = SnapStat::new(gluster_snap4, &config, HostType::Master);
 
         assert_eq!(stat.len(), 7);
-        assert_eq!(stat.newest_snap(), "snap_vol_20180216_120438");
-        assert_eq!(stat.oldest_snap(), "snap_vol_20180214_095616");
+        assert_eq!(stat.newest_snap().map(|s| s.name.as_str()), Some("snap_vol_20180216_120438"));
+        assert_eq!(stat.newest_time(), Some(Local.from_local_datetime(&NaiveDate::from_ymd_opt(2018, 2, 16).unwrap().and_hms_opt(12, 4, 38).unwrap()).unwrap().with_timezone(&Utc)));
+        assert_eq!(stat.oldest_snap().map(|s| s.name.as_str()), Some("snap_vol_20180214_095616"));
+        assert_eq!(stat.oldest_snap().map(|s| s.side), Some(HostType::Master));
         assert_eq!(stat.number_diff(&stat2), 0);
         assert_eq!(stat.number_diff(&stat3), 1);
         assert_eq!(stat.number_diff(&stat4), 2);
+        assert_eq!(stat.missing_in(&stat4), vec![String::from("snap_vol_20180216_115928")]);
+        assert_eq!(stat4.missing_in(&stat), vec![String::from("snap_vol_20180216_115924")]);
+        assert_eq!(stat.others(), &vec![String::from("snap_vol_20180212_155212_GMT-2018.02.12-14.52.47"),
+                                        String::from("snap_20180213_092734_master"),
+                                        String::from("my_backup_before_upgrade")]);
+
+        let slave = SnapStat::new(String::from("snap_svol_20180216_120438\nggsnap_svol_20180216_115928"),
+                                  &config, HostType::Slave);
+        assert_eq!(slave.names(), vec![String::from("snap_svol_20180216_120438")]);
+        assert_eq!(slave.others(), &vec![String::from("ggsnap_svol_20180216_115928")]);
+        assert_eq!(slave.newest_snap().map(|s| s.side), Some(HostType::Slave));
+
+        let empty = SnapStat::new(String::from("No snapshots present\n"), &config, HostType::Master);
+        assert_eq!(empty.len(), 0);
+        assert!(empty.newest_snap().is_none());
+        assert!(empty.oldest_snap().is_none());
+        assert!(empty.newest_time().is_none());
     }
 }
@@ -0,0 +1,127 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+use std::sync::{ Mutex, MutexGuard };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use chrono::prelude::*;
+use serde_json::Value;
+use metrics::{ self, Series };
+
+/// Status of runs in this process, read by
+/// the HTTP endpoint in daemon mode.
+pub struct RunStatus {
+    session: String,
+    phase: String,
+    phase_started: Option<DateTime<Utc>>,
+    last_run: Option<Value>,
+    metrics: Option<Series>,
+}
+
+static STATUS: Mutex<RunStatus> = Mutex::new(RunStatus {
+    session: String::new(),
+    phase: String::new(),
+    phase_started: None,
+    last_run: None,
+    metrics: None,
+});
+
+/// Geo-replication is paused by ggsnap
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Metrics are kept in memory, set in daemon
+/// mode when HTTP endpoint is enabled.
+static KEEP_METRICS: AtomicBool = AtomicBool::new(false);
+
+fn status() -> MutexGuard<'static, RunStatus> {
+    match STATUS.lock() {
+        Ok(s) => s,
+        Err(p) => p.into_inner(),
+    }
+}
+
+pub fn keep_metrics() {
+    KEEP_METRICS.store(true, Ordering::SeqCst);
+}
+
+pub fn keeps_metrics() -> bool {
+    KEEP_METRICS.load(Ordering::SeqCst)
+}
+
+/// Sets current phase of run for session
+pub fn set_phase(session: &str, phase: &str) {
+    let mut s = status();
+    s.session = session.to_string();
+    s.phase = phase.to_string();
+    s.phase_started = Some(Utc::now());
+}
+
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+/// Run is finished, report is the JSON run report
+/// and metrics are kept if keep_metrics is set.
+pub fn finish(session: &str, report: Value, metrics: Option<Series>) {
+    let mut s = status();
+    s.session = session.to_string();
+    s.phase = String::from("idle");
+    s.phase_started = Some(Utc::now());
+    s.last_run = Some(report);
+
+    if metrics.is_some() && keeps_metrics() {
+        s.metrics = metrics;
+    }
+}
+
+/// Metrics of last run kept in memory
+pub fn last_metrics() -> Option<Series> {
+    status().metrics.clone()
+}
+
+/// Status as JSON: session, current phase, if
+/// geo-replication is paused by ggsnap and
+/// report of last run.
+pub fn status_json() -> String {
+    let s = status();
+    let session = if s.session.len() > 0 { Value::from(s.session.clone()) } else { Value::Null };
+    let phase = if s.phase.len() > 0 { s.phase.clone() } else { String::from("idle") };
+
+    json!({
+        "session": session,
+        "phase": phase,
+        "phase_started": s.phase_started.map(|t| t.to_rfc3339()),
+        "geo_replication_paused": PAUSED.load(Ordering::SeqCst),
+        "last_run": s.last_run.clone(),
+    }).to_string()
+}
+
+/// Metrics of last run in Prometheus text format
+/// with current phase and pause state.
+pub fn metrics_text() -> String {
+    let s = status();
+    let mut m = s.metrics.clone().unwrap_or(Series::new());
+
+    if s.session.len() > 0 {
+        let paused = if PAUSED.load(Ordering::SeqCst) { 1.0 } else { 0.0 };
+        m.insert(format!("ggsnap_geo_replication_paused{{session=\"{}\"}}", metrics::escape(&s.session)), paused);
+    }
+
+    metrics::format(&m)
+}
@@ -0,0 +1,197 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap, creates and saves snapshots for gluster geo-replicated clutsers. //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::net::{ TcpStream, ToSocketAddrs };
+use std::time::Duration;
+use native_tls::TlsConnector;
+use ggsnap_utils::HostType;
+use ggsnap_utils::event::Event;
+use ggsnap_utils::report::RunReport;
+use ggsnap_utils::webhook::{ self, NotifyWebhook, Url, DEFAULT_WEBHOOK_TIMEOUT };
+
+/// Sends request and reads status line of response,
+/// status other than 2xx is an error.
+fn exchange<S: Read + Write>(stream: S, request: &str) -> Result<(), String> {
+    let mut reader = BufReader::new(stream);
+
+    if let Err(e) = reader.get_mut().write_all(request.as_bytes()) {
+        return Err(format!("Error sending request: {}", e));
+    }
+
+    let mut status = String::new();
+    if let Err(e) = reader.read_line(&mut status) {
+        return Err(format!("Error reading response: {}", e));
+    }
+
+    let status = status.trim_end();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some(_) => Err(format!("Response: {}", status)),
+        None => Err(String::from("Invalid response")),
+    }
+}
+
+fn connect(url: &Url, timeout: Duration) -> Result<TcpStream, String> {
+    let addrs = match (url.host.as_str(), url.port).to_socket_addrs() {
+        Ok(a) => a,
+        Err(e) => return Err(format!("Error resolving: {}; {}", url.host, e)),
+    };
+
+    let mut error = format!("Error resolving: {}", url.host);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(s) => {
+                let _ = s.set_read_timeout(Some(timeout));
+                let _ = s.set_write_timeout(Some(timeout));
+                return Ok(s);
+            },
+            Err(e) => error = format!("Error connecting to: {}:{}; {}", url.host, url.port, e),
+        }
+    }
+
+    Err(error)
+}
+
+/// POSTs body to URL over http or https
+pub fn post(url: &str, content_type: &str, body: &str, timeout: Duration) -> Result<(), String> {
+    let url = match Url::parse(url) {
+        Ok(u) => u,
+        Err(e) => return Err(e),
+    };
+
+    let request = format!("POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: ggsnap\r\nContent-Type: {}\r\n\
+                           Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                          url.path, url.host_header(), content_type, body.len(), body);
+
+    let stream = match connect(&url, timeout) {
+        Ok(s) => s,
+        Err(e) => return Err(e),
+    };
+
+    if url.https {
+        let connector = match TlsConnector::builder().and_then(|b| b.build()) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Error creating TLS connector: {}", e)),
+        };
+
+        match connector.connect(&url.host, stream) {
+            Ok(s) => exchange(s, &request),
+            Err(e) => Err(format!("TLS error: {}", e)),
+        }
+    }
+    else {
+        exchange(stream, &request)
+    }
+}
+
+/// Posts run report to all URLs in [notify_webhook] and
+/// returns an event per URL. Only host is logged since
+/// chat webhook URLs contain secrets.
+pub fn notify(webhook: &NotifyWebhook, report: &RunReport) -> Vec<Event> {
+    let body = match webhook::payload(webhook, report) {
+        Ok(b) => b,
+        Err(e) => return vec![Event::error(HostType::Master, &format!("Error sending webhook: {}", e))],
+    };
+    let content_type = webhook.content_type.clone().unwrap_or(String::from("application/json"));
+    let timeout = Duration::from_secs(webhook.timeout.unwrap_or(DEFAULT_WEBHOOK_TIMEOUT));
+
+    webhook.urls.iter().map(|u| {
+        let host = Url::parse(u).map(|u| u.host).unwrap_or(u.clone());
+
+        match post(u, &content_type, &body, timeout) {
+            Ok(_) => Event::info(HostType::Master, &format!("Webhook sent OK: {}", host)),
+            Err(e) => Event::error(HostType::Master, &format!("Error sending webhook: {}; {}", host, e)),
+        }
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use chrono::prelude::*;
+    use ggsnap_utils::event::{ EventLog, Level };
+
+    /// Minimal HTTP server, answers one request with
+    /// status and returns request line, headers and body.
+    fn http_stand_in(listener: TcpListener, status: &'static str) -> thread::JoinHandle<(Vec<String>, String)> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head: Vec<String> = Vec::new();
+            let mut length = 0;
+
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                if line.is_empty() {
+                    break;
+                }
+                if line.starts_with("Content-Length: ") {
+                    length = line[16..].parse().unwrap();
+                }
+                head.push(line);
+            }
+
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            stream.write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).as_bytes()).unwrap();
+
+            (head, String::from_utf8(body).unwrap())
+        })
+    }
+
+    #[test]
+    fn webhook_post() {
+        let ok = TcpListener::bind("127.0.0.1:0").unwrap();
+        let failing = TcpListener::bind("127.0.0.1:0").unwrap();
+        let urls = vec![format!("http://127.0.0.1:{}/hooks/ggsnap", ok.local_addr().unwrap().port()),
+                        format!("http://127.0.0.1:{}/", failing.local_addr().unwrap().port())];
+        let ok = http_stand_in(ok, "200 OK");
+        let failing = http_stand_in(failing, "404 Not Found");
+
+        let hook = NotifyWebhook {
+            urls: urls,
+            on_success: None,
+            on_failure: None,
+            format: Some(String::from("template")),
+            template: Some(String::from("{\"text\": \"ggsnap {status}: {session}\"}")),
+            content_type: None,
+            timeout: Some(5),
+        };
+        let report = RunReport::from_log(&EventLog::new("vol root@slave::svol"), None, true, Utc::now());
+
+        let events: Vec<(Level, String)> = notify(&hook, &report).into_iter().map(|e| (e.level, e.message)).collect();
+        assert_eq!(events, vec![(Level::Info, String::from("Webhook sent OK: 127.0.0.1")),
+                                (Level::Error, String::from("Error sending webhook: 127.0.0.1; Response: HTTP/1.1 404 Not Found"))]);
+
+        let (head, body) = ok.join().unwrap();
+        assert_eq!(head[0], "POST /hooks/ggsnap HTTP/1.1");
+        assert!(head.contains(&String::from("Content-Type: application/json")));
+        assert_eq!(body, "{\"text\": \"ggsnap OK: vol root@slave::svol\"}");
+        assert_eq!(failing.join().unwrap().1, body);
+    }
+}
@@ -23,7 +23,8 @@ extern crate ggsnap_utils;
 
 use clap::{Arg, ArgMatches, App};
 use std::process::Command;
-use ggsnap_utils::{get_config, Config, ConfigReadErr };
+use ggsnap_utils::{get_config, hook, lock, pin, timezone, Config, ConfigReadErr, HostType };
+use ggsnap_utils::event::{ self, Event, Level };
 
 /// Parses command line arguments and
 /// checks that configuration is correct
@@ -31,7 +32,8 @@ fn main() {
     let matches = arg_matches();
 
     if matches.is_present("LIST") || matches.is_present("VOLUME") ||
-       matches.is_present("SNAPSHOT_NAME") || matches.is_present("REMOVE_SNAPSHOTS") {
+       matches.is_present("SNAPSHOT_NAME") || matches.is_present("REMOVE_SNAPSHOTS") ||
+       matches.is_present("PIN") || matches.is_present("UNPIN") {
         let mut snapshot_name: String = String::new();
         let mut config_file_exist = true;
         let mut _config = Config::default_config();
@@ -63,6 +65,86 @@ fn main() {
             _config.snapshot.snapshot_name_prefix = c.snapshot.snapshot_name_prefix.clone();
         }
 
+        if _config.general.pin_file.is_none() {
+            let c = Config::default_config();
+            _config.general.pin_file = c.general.pin_file.clone();
+        }
+
+        if _config.general.lvs_bin.is_none() {
+            let c = Config::default_config();
+            _config.general.lvs_bin = c.general.lvs_bin.clone();
+        }
+
+        if _config.general.lock_dir.is_none() {
+            let c = Config::default_config();
+            _config.general.lock_dir = c.general.lock_dir.clone();
+        }
+
+        if _config.general.lock_policy.is_none() {
+            let c = Config::default_config();
+            _config.general.lock_policy = c.general.lock_policy.clone();
+        }
+
+        if _config.general.lock_wait_timeout.is_none() {
+            let c = Config::default_config();
+            _config.general.lock_wait_timeout = c.general.lock_wait_timeout.clone();
+        }
+
+        if _config.general.lock_stale_seconds.is_none() {
+            let c = Config::default_config();
+            _config.general.lock_stale_seconds = c.general.lock_stale_seconds.clone();
+        }
+
+        if _config.snapshot.manual_snapshot_expire_days.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.manual_snapshot_expire_days = c.snapshot.manual_snapshot_expire_days.clone();
+        }
+
+        if _config.snapshot.snapshot_name_template.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.snapshot_name_template = c.snapshot.snapshot_name_template.clone();
+        }
+
+        if _config.snapshot.manual_snapshot_name_template.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.manual_snapshot_name_template = c.snapshot.manual_snapshot_name_template.clone();
+        }
+
+        if _config.snapshot.timezone.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.timezone = c.snapshot.timezone.clone();
+        }
+
+        if _config.snapshot.delete_oldest_at_limit.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.delete_oldest_at_limit = c.snapshot.delete_oldest_at_limit.clone();
+        }
+
+        if _config.snapshot.thin_pool_max_data_percent.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.thin_pool_max_data_percent = c.snapshot.thin_pool_max_data_percent.clone();
+        }
+
+        if _config.snapshot.thin_pool_max_metadata_percent.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.thin_pool_max_metadata_percent = c.snapshot.thin_pool_max_metadata_percent.clone();
+        }
+
+        if _config.snapshot.thin_pool_full_action.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.thin_pool_full_action = c.snapshot.thin_pool_full_action.clone();
+        }
+
+        if _config.snapshot.capacity_target_percent.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.capacity_target_percent = c.snapshot.capacity_target_percent.clone();
+        }
+
+        if _config.snapshot.capacity_keep_newest.is_none() {
+            let c = Config::default_config();
+            _config.snapshot.capacity_keep_newest = c.snapshot.capacity_keep_newest.clone();
+        }
+
         match matches.value_of("VOLUME") {
             Some(v) => _config.snapshot.slave_volume = Some(String::from(v)),
             None    => (),
@@ -86,6 +168,16 @@ fn main() {
                 }
             }
         }
+        else if matches.is_present("PIN") || matches.is_present("UNPIN") {
+            match pin_snapshot(&config, matches.value_of("PIN"), matches.value_of("UNTIL"),
+                               matches.value_of("UNPIN")) {
+                Ok(l) => println!("{}", l),
+                Err(e) => {
+                    println!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
         else if matches.is_present("VOLUME") || matches.is_present("SNAPSHOT_NAME") ||
                matches.is_present("REMOVE_SNAPSHOTS") {
             if config.snapshot.slave_volume == None {
@@ -102,23 +194,35 @@ fn main() {
                 std::process::exit(1);
             }
 
-            if matches.is_present("REMOVE_SNAPSHOTS") {
-                match remove_old_snapshots(&config) {
-                    Ok(l) => println!("{}", l),
-                    Err(e) => {
-                        println!("{}", e);
-                        std::process::exit(1);
-                    },
-                }
+            let session = format!("slave_{}", config.snapshot.slave_volume.clone().unwrap());
+            let _lock = match lock::acquire(&config, &session, &HostType::Slave) {
+                Ok((l, events)) => {
+                    print!("{}", event::format_json(&events));
+                    l
+                },
+                Err((lock::LockErr::Skip, events)) => {
+                    print!("{}", event::format_json(&events));
+                    std::process::exit(0);
+                },
+                Err((lock::LockErr::Fail, events)) => {
+                    print!("{}", event::format_json(&events));
+                    std::process::exit(1);
+                },
+            };
+
+            let res = if matches.is_present("REMOVE_SNAPSHOTS") {
+                remove_old_snapshots(&config)
             }
             else {
-                match create_snapshot(&config, &snapshot_name) {
-                    Ok(l) => println!("{}", l),
-                    Err(l) => {
-                        println!("{}", l);
-                        std::process::exit(1);
-                    },
-                }
+                create_snapshot(&config, &snapshot_name)
+            };
+
+            match res {
+                Ok(events) => print!("{}", event::format_json(&events)),
+                Err(events) => {
+                    print!("{}", event::format_json(&events));
+                    std::process::exit(1);
+                },
             }
         }
     }
@@ -145,11 +249,38 @@ fn list_snapshots(config: &Config) -> Result<String, String> {
     }
 }
 
-/// Creates snapshot and returns result
-fn create_snapshot(config: &Config, snap_name: &String) -> Result<String, String> {
-    let mut log: String = format!("Slave: Creating snapshot: {} on volume: {}", 
-                                  snap_name, config.snapshot.slave_volume.clone().unwrap());
+/// Checks snapshot limits and thin pool
+/// capacity, runs pre/post create hooks,
+/// creates snapshot and returns events
+/// that are printed as JSON lines for ggsnap.
+fn create_snapshot(config: &Config, snap_name: &String) -> Result<Vec<Event>, Vec<Event>> {
+    let mut log = match ggsnap_utils::limits::check_snapshot_limit(config, &HostType::Slave) {
+        Ok(l) => l,
+        Err(l) => return Err(l),
+    };
+
+    match ggsnap_utils::capacity::check_thin_pools(config, &HostType::Slave) {
+        Ok(l) => log.extend(l),
+        Err(l) => {
+            log.extend(l);
+            return Err(log);
+        },
+    }
 
+    match hook::run_hook(config, hook::Phase::PreCreate, snap_name, &HostType::Slave) {
+        Some(Ok(l)) => log.extend(l),
+        Some(Err(l)) => {
+            log.extend(l);
+            return Err(log);
+        },
+        None => (),
+    }
+
+    log.push(Event::info(HostType::Slave, &format!("Creating snapshot: {} on volume: {}",
+                                                   snap_name, config.snapshot.slave_volume.clone().unwrap())));
+
+    let cmd_text = format!("gluster snapshot create {} {} no-timestamp",
+                           snap_name, config.snapshot.slave_volume.clone().unwrap());
     let cmd_out = Command::new(&config.general.gluster_bin)
                           .arg("snapshot")
                           .arg("create")
@@ -160,19 +291,29 @@ fn create_snapshot(config: &Config, snap_name: &String) -> Result<String, String
 
     match cmd_out {
         Ok(o) => {
-            log = format!("{}\nSlave: {}{}", log, String::from_utf8_lossy(&o.stdout), 
-                          String::from_utf8_lossy(&o.stderr));
-            if o.status.success() {
-                Ok(log)
+            let level = if o.status.success() { Level::Info } else { Level::Error };
+            log.extend(Event::command(level, HostType::Slave, &cmd_text, o.status.code(),
+                                      &format!("{}{}", String::from_utf8_lossy(&o.stdout),
+                                               String::from_utf8_lossy(&o.stderr))));
+            if !o.status.success() {
+                return Err(log);
             }
-            else {
-                Err(log)
+
+            match hook::run_hook(config, hook::Phase::PostCreate, snap_name, &HostType::Slave) {
+                Some(Ok(l)) => {
+                    log.extend(l);
+                    Ok(log)
+                },
+                Some(Err(l)) => {
+                    log.extend(l);
+                    Err(log)
+                },
+                None => Ok(log),
             }
         }
         Err(e) => {
-            log = format!("{}\nSlave: Error running command: gluster snapshot create {} {} no-timestamp",
-                          log, snap_name, config.snapshot.slave_volume.clone().unwrap());
-            log = format!("{}\nSlave: Error: {}", log, e.to_string());
+            log.extend(Event::command(Level::Error, HostType::Slave, &cmd_text, None,
+                                      &format!("Error running command: {}\nError: {}", cmd_text, e.to_string())));
             Err(log)
         }
     }
@@ -181,15 +322,65 @@ fn create_snapshot(config: &Config, snap_name: &String) -> Result<String, String
 
 /// Delete snapshots according to settings
 /// in config file.
-fn remove_old_snapshots(config: &Config) -> Result<String, String> {
-    let mut log = String::from("Slave: Removing old snapshots");
+fn remove_old_snapshots(config: &Config) -> Result<Vec<Event>, Vec<Event>> {
+    let mut log = vec![Event::info(HostType::Slave, "Removing old snapshots")];
 
-    match ggsnap_utils::remove_old_snapshots(config, ggsnap_utils::HostType::Slave) {
+    match ggsnap_utils::remove_old_snapshots(config, HostType::Slave) {
         Ok(s) => {
-            log = format!("{}\nSlave: The following snapshots has been removed:\n{}", log, s);
-            Ok(format!("{}\nSlave: End of removing snapshots", log))
+            log.push(Event::info(HostType::Slave, "The following snapshots has been removed:"));
+            log.extend(s);
+            log.push(Event::info(HostType::Slave, "End of removing snapshots"));
+            Ok(log)
+        },
+        Err(e) => {
+            log.push(Event::error(HostType::Slave, "Error removing snapshots:"));
+            log.extend(e);
+            Err(log)
         },
-        Err(e) => Err(format!("{}\nSlave: Error removing snapshots:\n{}", log, e)),
+    }
+}
+
+/// Pins or unpins a snapshot in pin file
+/// on slave node, a pinned snapshot is
+/// not removed by --remove-snapshots.
+fn pin_snapshot(config: &Config, pin: Option<&str>, until: Option<&str>,
+                unpin: Option<&str>) -> Result<String, String> {
+    let mut pins = match pin::read_pins(config) {
+        Ok(p) => p,
+        Err(e) => return Err(format!("Slave: {}", e)),
+    };
+
+    let mut until_date = None;
+    if let Some(u) = until {
+        match pin::parse_until(u) {
+            Ok(d) => until_date = Some(d),
+            Err(e) => return Err(format!("Slave: {}", e)),
+        }
+    }
+
+    pins.remove_expired(timezone::config_timezone(config).today());
+
+    let mut log: String = String::new();
+
+    if let Some(p) = pin {
+        pins.add(p, until_date);
+        log = match until {
+            Some(u) => format!("Slave: Pinned snapshot: {} until: {}", p, u),
+            None => format!("Slave: Pinned snapshot: {}", p),
+        };
+    }
+    else if let Some(p) = unpin {
+        if pins.remove(p) {
+            log = format!("Slave: Unpinned snapshot: {}", p);
+        }
+        else {
+            log = format!("Slave: Snapshot is not pinned: {}", p);
+        }
+    }
+
+    match pin::write_pins(config, &pins) {
+        Ok(_) => Ok(log),
+        Err(e) => Err(format!("Slave: {}", e)),
     }
 }
 
@@ -203,7 +394,7 @@ fn arg_matches() -> ArgMatches<'static> {
         .arg(Arg::with_name("LIST")
              .short("l")
              .long("list")
-             .conflicts_with_all(&["VOLUME", "SNAPSHOT_NAME", "REMOVE_SNAPSHOTS"])
+             .conflicts_with_all(&["VOLUME", "SNAPSHOT_NAME", "REMOVE_SNAPSHOTS", "PIN", "UNPIN"])
              .help("Returns names of all snapshots available"))
         .arg(Arg::with_name("VOLUME")
              .short("v")
@@ -220,8 +411,8 @@ Requires SNAPSHOT_NAME."))
              .short("n")
              .long("snapshot-name")
              .takes_value(true)
-             .required_unless("REMOVE_SNAPSHOTS")
-             .conflicts_with("LIST")
+             .required_unless_one(&["REMOVE_SNAPSHOTS", "PIN", "UNPIN"])
+             .conflicts_with_all(&["LIST", "PIN", "UNPIN"])
              .help("Creates gluster snapshot on slave cluster.
 SNAPSHOT_NAME will be the name of the snapshot.
 Takes information about VOLUME from config file.
@@ -231,9 +422,30 @@ If run with --remove-snapshots, snapshots will be deleted."))
         .arg(Arg::with_name("REMOVE_SNAPSHOTS")
              .short("r")
              .long("remove-snapshots")
-             .conflicts_with_all(&["LIST", "SNAPSHOT_NAME"])
+             .conflicts_with_all(&["LIST", "SNAPSHOT_NAME", "PIN", "UNPIN"])
              .help("Removes old snapshots according to settings
 in config file."))
+        .arg(Arg::with_name("PIN")
+             .short("p")
+             .long("pin")
+             .takes_value(true)
+             .value_name("NAME")
+             .conflicts_with_all(&["LIST", "SNAPSHOT_NAME", "REMOVE_SNAPSHOTS", "UNPIN"])
+             .help("Pins snapshot NAME, a pinned snapshot
+is never removed with --remove-snapshots."))
+        .arg(Arg::with_name("UNTIL")
+             .long("until")
+             .takes_value(true)
+             .value_name("DATE")
+             .requires("PIN")
+             .help("Snapshot is pinned until and including
+DATE, format: YYYY-MM-DD"))
+        .arg(Arg::with_name("UNPIN")
+             .long("unpin")
+             .takes_value(true)
+             .value_name("NAME")
+             .conflicts_with_all(&["LIST", "SNAPSHOT_NAME", "REMOVE_SNAPSHOTS", "PIN"])
+             .help("Removes pin from snapshot NAME"))
         .after_help("Important! This program must run on slave (geo) node
 
 ggsnap_slave is executed from ggsnap that is on main mater node")
@@ -0,0 +1,422 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::collections::HashMap;
+use std::process::Command;
+use event::{Event, Level};
+use {deletable_snapshots, delete_snapshot, host_volume, list_volume_snapshots, Config, HostType};
+
+/// Usage of a thin pool in percent
+#[derive(Debug, PartialEq)]
+pub struct PoolUsage {
+    pub pool: String,
+    pub data_percent: f64,
+    pub metadata_percent: f64,
+}
+
+/// Error reading thin pool usage
+#[derive(Debug, PartialEq)]
+pub enum UsageErr {
+    NoLocalBricks,
+    ReadErr,
+}
+
+/// Key and value of a line in gluster volume status detail
+fn status_line(line: &str) -> (&str, &str) {
+    let mut kv = line.splitn(2, ':');
+    (kv.next().unwrap_or("").trim(), kv.next().unwrap_or("").trim())
+}
+
+/// Host of brick from value of Brick line, ex: Brick host:/path
+fn brick_host(value: &str) -> &str {
+    value.split_whitespace().last().unwrap_or("").split(':').next().unwrap_or("")
+}
+
+/// True if brick host is one of the local names or addresses.
+/// Short names are only compared if brick host has no domain.
+fn is_local_host(brick_host: &str, local_names: &Vec<String>) -> bool {
+    local_names.iter().any(|h| h == brick_host ||
+                               (!brick_host.contains('.') && h.split('.').next() == Some(brick_host)))
+}
+
+/// Parses output from gluster volume status VOLUME detail
+/// and returns the host of every brick.
+pub fn parse_brick_hosts(status_detail: &str) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+
+    for l in status_detail.lines() {
+        let (key, value) = status_line(l);
+        if key == "Brick" && !hosts.contains(&brick_host(value).to_string()) {
+            hosts.push(brick_host(value).to_string());
+        }
+    }
+
+    hosts
+}
+
+/// Parses output from gluster volume status VOLUME detail
+/// and returns the device of every brick on this node,
+/// local_names should contain the names and addresses
+/// of this node.
+pub fn parse_brick_devices(status_detail: &str, local_names: &Vec<String>) -> Vec<String> {
+    let mut devices: Vec<String> = Vec::new();
+    let mut local_brick = false;
+
+    for l in status_detail.lines() {
+        let (key, value) = status_line(l);
+
+        if key == "Brick" {
+            local_brick = is_local_host(brick_host(value), local_names);
+        }
+        else if key == "Device" && local_brick {
+            if !devices.contains(&value.to_string()) {
+                devices.push(value.to_string());
+            }
+        }
+    }
+
+    devices
+}
+
+/// Parses output from lvs, see get_pool_usage for fields,
+/// and returns usage of the thin pools used by devices.
+pub fn parse_thin_pools(lvs_output: &str, devices: &Vec<String>) -> Vec<PoolUsage> {
+    let mut pools: HashMap<String, (f64, f64)> = HashMap::new();
+    let mut brick_pools: Vec<String> = Vec::new();
+
+    for l in lvs_output.lines() {
+        let f: Vec<&str> = l.split(',').map(|f| f.trim()).collect();
+        if f.len() < 8 {
+            continue;
+        }
+
+        let pool_name = format!("{}/{}", f[2], f[3]);
+        if f[5].starts_with('t') {
+            let data = f[6].parse::<f64>().unwrap_or(0.0);
+            let metadata = f[7].parse::<f64>().unwrap_or(0.0);
+            pools.insert(pool_name, (data, metadata));
+        }
+        else if f[4].len() > 0 && devices.iter().any(|d| d == f[0] || d == f[1]) {
+            let brick_pool = format!("{}/{}", f[2], f[4]);
+            if !brick_pools.contains(&brick_pool) {
+                brick_pools.push(brick_pool);
+            }
+        }
+    }
+
+    brick_pools.into_iter()
+               .filter_map(|p| pools.get(&p).map(|&(d, m)| PoolUsage { pool: p.clone(), data_percent: d, metadata_percent: m }))
+               .collect()
+}
+
+/// Short and full hostname and all
+/// interface addresses of this node
+fn local_names() -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+
+    for args in vec![vec![], vec!["-f"], vec!["-I"]] {
+        if let Ok(o) = Command::new("hostname").args(&args).output() {
+            if !o.status.success() {
+                continue;
+            }
+
+            for name in String::from_utf8_lossy(&o.stdout).split_whitespace() {
+                if !names.contains(&name.to_string()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    names
+}
+
+/// Usage of the thin pools used by the bricks
+/// of volume on this node. Devices are read from
+/// gluster volume status VOLUME detail and pool
+/// usage from lvs. NoLocalBricks is returned if no
+/// brick host is a name or address of this node.
+pub fn get_pool_usage(config: &Config, host_type: &HostType) -> Result<Vec<PoolUsage>, (UsageErr, String)> {
+    let volume = host_volume(config, host_type);
+    let cmd_out = Command::new(&config.general.gluster_bin)
+                          .arg("volume")
+                          .arg("status")
+                          .arg(&volume)
+                          .arg("detail")
+                          .output();
+
+    let status = match cmd_out {
+        Ok(o) => {
+            if !o.status.success() {
+                return Err((UsageErr::ReadErr, format!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr))));
+            }
+            String::from_utf8_lossy(&o.stdout).to_string()
+        },
+        Err(e) => return Err((UsageErr::ReadErr, format!("Error running command: gluster volume status {} detail; {}", volume, e))),
+    };
+
+    let names = local_names();
+    let devices = parse_brick_devices(&status, &names);
+
+    if devices.len() == 0 {
+        return Err((UsageErr::NoLocalBricks,
+                    format!("No bricks of volume {} on this node, brick hosts: {}, this node: {}",
+                            volume, parse_brick_hosts(&status).join(", "), names.join(", "))));
+    }
+
+    let lvs_bin = config.general.lvs_bin.clone().unwrap();
+    let cmd_out = Command::new(&lvs_bin)
+                          .arg("--noheadings")
+                          .arg("--separator")
+                          .arg(",")
+                          .arg("-o")
+                          .arg("lv_dm_path,lv_path,vg_name,lv_name,pool_lv,lv_attr,data_percent,metadata_percent")
+                          .output();
+
+    match cmd_out {
+        Ok(o) => {
+            if !o.status.success() {
+                return Err((UsageErr::ReadErr, format!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr))));
+            }
+
+            let pools = parse_thin_pools(&String::from_utf8_lossy(&o.stdout), &devices);
+            if pools.len() == 0 {
+                Err((UsageErr::ReadErr, format!("No thin pools found for devices: {}", devices.join(", "))))
+            }
+            else {
+                Ok(pools)
+            }
+        },
+        Err(e) => Err((UsageErr::ReadErr, format!("Error running command: {}; {}", lvs_bin, e))),
+    }
+}
+
+/// Adds usage of every pool to log and returns true
+/// if any pool is at or above max_data or max_metadata
+/// percent, a max value of 0 is not checked.
+fn above_threshold(pools: &Vec<PoolUsage>, max_data: u32, max_metadata: u32, host_type: &HostType, log: &mut Vec<Event>) -> bool {
+    let mut full = false;
+
+    for p in pools.iter() {
+        log.push(Event::info(*host_type, &format!("Thin pool {}: data {:.2}%, metadata {:.2}%",
+                                                  p.pool, p.data_percent, p.metadata_percent)));
+
+        if (max_data > 0 && p.data_percent >= max_data as f64) ||
+           (max_metadata > 0 && p.metadata_percent >= max_metadata as f64) {
+            full = true;
+        }
+    }
+
+    full
+}
+
+/// Deletes the oldest unpinned ggsnap snapshot, one at a
+/// time, until thin pool usage is below max_data and
+/// max_metadata percent. The keep_newest newest snapshots
+/// are never deleted. Returns false if there are no more
+/// snapshots to delete and usage still is above threshold.
+/// On error the error events are added to log.
+fn prune_until_below(config: &Config, host_type: &HostType, max_data: u32, max_metadata: u32,
+                     keep_newest: usize, log: &mut Vec<Event>) -> Result<bool, ()> {
+    loop {
+        let deletable = match list_volume_snapshots(config, host_type) {
+            Ok(s) => deletable_snapshots(config, &s, host_type, keep_newest),
+            Err(e) => Err(e),
+        };
+
+        match deletable {
+            Ok(ref d) if d.len() > 0 => {
+                log.push(Event::info(*host_type, "Pruning oldest snapshot"));
+                match delete_snapshot(config, &d[0], host_type) {
+                    Ok(l) => log.extend(l),
+                    Err(l) => {
+                        log.extend(l);
+                        return Err(());
+                    },
+                }
+            },
+            Ok(_) => return Ok(false),
+            Err(e) => {
+                log.extend(Event::lines(Level::Error, *host_type, &e));
+                return Err(());
+            },
+        }
+
+        let pools = match get_pool_usage(config, host_type) {
+            Ok(p) => p,
+            Err((_, e)) => {
+                log.extend(Event::lines(Level::Error, *host_type, &format!("Error reading thin pool usage: {}", e)));
+                return Err(());
+            },
+        };
+
+        if !above_threshold(&pools, max_data, max_metadata, host_type, log) {
+            return Ok(true);
+        }
+    }
+}
+
+/// Checks that thin pools used by the bricks of volume
+/// on this node are below config settings
+/// thin_pool_max_data_percent and thin_pool_max_metadata_percent.
+/// Above threshold an error is returned if config setting
+/// thin_pool_full_action is refuse and if it is prune the
+/// oldest unpinned ggsnap snapshots are deleted until usage
+/// is below thresholds, the newest is never deleted.
+/// If usage can not be read a warning is logged
+/// and the snapshot is tried anyway.
+pub fn check_thin_pools(config: &Config, host_type: &HostType) -> Result<Vec<Event>, Vec<Event>> {
+    let volume = host_volume(config, host_type);
+    let max_data = config.snapshot.thin_pool_max_data_percent.unwrap_or(0);
+    let max_metadata = config.snapshot.thin_pool_max_metadata_percent.unwrap_or(0);
+    let prune = config.snapshot.thin_pool_full_action.clone().unwrap_or(String::from("refuse")) == "prune";
+
+    if max_data == 0 && max_metadata == 0 {
+        return Ok(vec![Event::info(*host_type, "Thin pool capacity check is disabled")]);
+    }
+
+    let mut log = vec![Event::info(*host_type, &format!("Checking thin pool capacity on volume: {}", volume))];
+
+    let pools = match get_pool_usage(config, host_type) {
+        Ok(p) => p,
+        Err((UsageErr::NoLocalBricks, e)) => {
+            log.push(Event::warning(*host_type, &format!("Warning: {}, thin pool capacity is not checked", e)));
+            return Ok(log);
+        },
+        Err((_, e)) => {
+            log.push(Event::warning(*host_type, "Warning: Can not read thin pool usage, continuing"));
+            log.extend(Event::lines(Level::Warning, *host_type, &e));
+            return Ok(log);
+        },
+    };
+
+    if !above_threshold(&pools, max_data, max_metadata, host_type, &mut log) {
+        return Ok(log);
+    }
+
+    log.push(Event::info(*host_type, &format!("Thin pool usage is above threshold, data: {}%, metadata: {}%",
+                                              max_data, max_metadata)));
+
+    if !prune {
+        log.push(Event::error(*host_type, "Error: Snapshot is not created, remove snapshots or free space in thin pool"));
+        return Err(log);
+    }
+
+    match prune_until_below(config, host_type, max_data, max_metadata, 1, &mut log) {
+        Ok(true) => Ok(log),
+        Ok(false) => {
+            log.push(Event::error(*host_type, "Error: No more unpinned snapshots created by ggsnap to prune"));
+            Err(log)
+        },
+        Err(_) => Err(log),
+    }
+}
+
+/// Deletes the oldest unpinned ggsnap snapshots, beyond
+/// what is removed by the time based settings, until thin
+/// pool usage is below config setting capacity_target_percent.
+/// The capacity_keep_newest newest snapshots are never deleted.
+/// If the target can not be reached a warning is logged.
+pub fn prune_to_capacity_target(config: &Config, host_type: &HostType) -> Result<Vec<Event>, Vec<Event>> {
+    let target = config.snapshot.capacity_target_percent.unwrap_or(0);
+    let keep_newest = config.snapshot.capacity_keep_newest.unwrap_or(0);
+    let mut log = vec![Event::info(*host_type, &format!("Checking capacity target {}% on volume: {}",
+                                                        target, host_volume(config, host_type)))];
+
+    let pools = match get_pool_usage(config, host_type) {
+        Ok(p) => p,
+        Err((UsageErr::NoLocalBricks, e)) => {
+            log.push(Event::warning(*host_type, &format!("Warning: {}, no snapshots pruned", e)));
+            return Ok(log);
+        },
+        Err((_, e)) => {
+            log.push(Event::warning(*host_type, "Warning: Can not read thin pool usage, no snapshots pruned"));
+            log.extend(Event::lines(Level::Warning, *host_type, &e));
+            return Ok(log);
+        },
+    };
+
+    if !above_threshold(&pools, target, target, host_type, &mut log) {
+        return Ok(log);
+    }
+
+    match prune_until_below(config, host_type, target, target, keep_newest as usize, &mut log) {
+        Ok(true) => Ok(log),
+        Ok(false) => {
+            log.push(Event::warning(*host_type, &format!("Warning: Capacity target is not reached, the {} newest and pinned snapshots are kept",
+                                                         keep_newest)));
+            Ok(log)
+        },
+        Err(_) => Err(log),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thin_pool_usage() {
+        let status = "Status of volume: vol
+------------------------------------------------------------------------------
+Brick                : Brick gluster1:/bricks/b1/brick
+TCP Port             : 49152
+Online               : Y
+Device               : /dev/mapper/vg_bricks-lv_b1
+------------------------------------------------------------------------------
+Brick                : Brick gluster2.example.com:/bricks/b1/brick
+TCP Port             : 49152
+Online               : Y
+Device               : /dev/mapper/vg_bricks-lv_b2
+";
+        let hostnames = vec![String::from("gluster2"), String::from("gluster2.example.com")];
+        let devices = parse_brick_devices(status, &hostnames);
+        assert_eq!(devices, vec![String::from("/dev/mapper/vg_bricks-lv_b2")]);
+        assert_eq!(parse_brick_hosts(status), vec![String::from("gluster1"), String::from("gluster2.example.com")]);
+
+        let lvs = "  /dev/mapper/vg_bricks-lv_b1,/dev/vg_bricks/lv_b1,vg_bricks,lv_b1,pool1,Vwi-aot---,12.00,
+  /dev/mapper/vg_bricks-lv_b2,/dev/vg_bricks/lv_b2,vg_bricks,lv_b2,pool2,Vwi-aot---,40.00,
+  /dev/mapper/vg_bricks-pool1,,vg_bricks,pool1,,twi-aotz--,10.50,2.25
+  /dev/mapper/vg_bricks-pool2,,vg_bricks,pool2,,twi-aotz--,91.00,5.10
+  /dev/mapper/centos-root,/dev/centos/root,centos,root,,-wi-ao----,,
+";
+        assert_eq!(parse_thin_pools(lvs, &devices),
+                   vec![PoolUsage { pool: String::from("vg_bricks/pool2"), data_percent: 91.0, metadata_percent: 5.1 }]);
+        assert_eq!(parse_thin_pools(lvs, &vec![String::from("/dev/sda1")]), vec![]);
+    }
+
+    #[test]
+    fn local_bricks() {
+        let status = "Brick                : Brick 10.0.0.11:/bricks/b1/brick
+Device               : /dev/mapper/vg_bricks-lv_b1
+Brick                : Brick gluster1.dc2:/bricks/b2/brick
+Device               : /dev/mapper/vg_bricks-lv_b2
+Brick                : Brick gluster1:/bricks/b3/brick
+Device               : /dev/mapper/vg_bricks-lv_b3
+";
+        let names = vec![String::from("gluster1"), String::from("gluster1.dc1"), String::from("10.0.0.11")];
+        assert_eq!(parse_brick_devices(status, &names),
+                   vec![String::from("/dev/mapper/vg_bricks-lv_b1"), String::from("/dev/mapper/vg_bricks-lv_b3")]);
+
+        let names = vec![String::from("gluster1.dc1")];
+        assert_eq!(parse_brick_devices(status, &names), vec![String::from("/dev/mapper/vg_bricks-lv_b3")]);
+    }
+}
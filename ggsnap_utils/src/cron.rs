@@ -0,0 +1,259 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use chrono::prelude::*;
+use chrono::Duration;
+use timezone::SnapTimeZone;
+
+/// Parsed cron expression with five fields:
+/// minute hour day-of-month month day-of-week
+/// Fields support *, numbers, ranges a-b,
+/// lists a,b and steps */n or a-b/n.
+/// Day of week 0 and 7 are both Sunday.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CronExpr {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    days_any: bool,
+    weekdays_any: bool,
+}
+
+/// Parses one field, returns all matching values
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<Vec<u32>, String> {
+    let mut values: Vec<u32> = Vec::new();
+    let err = || format!("Error in cron expression, invalid {} field: {}", name, field);
+
+    for part in field.split(',') {
+        let mut range_step = part.splitn(2, '/');
+        let range = range_step.next().unwrap_or("");
+        let step = match range_step.next() {
+            Some(s) => match s.parse::<u32>() {
+                Ok(s) if s > 0 => s,
+                _ => return Err(err()),
+            },
+            None => 1,
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        }
+        else {
+            let mut bounds = range.splitn(2, '-');
+            let start = match bounds.next().unwrap_or("").parse::<u32>() {
+                Ok(s) => s,
+                Err(_) => return Err(err()),
+            };
+            let end = match bounds.next() {
+                Some(e) => match e.parse::<u32>() {
+                    Ok(e) => e,
+                    Err(_) => return Err(err()),
+                },
+                None if step > 1 => max,
+                None => start,
+            };
+            (start, end)
+        };
+
+        if start < min || end > max || start > end {
+            return Err(err());
+        }
+
+        let mut v = start;
+        while v <= end {
+            if !values.contains(&v) {
+                values.push(v);
+            }
+            v += step;
+        }
+    }
+
+    values.sort();
+    Ok(values)
+}
+
+impl CronExpr {
+    /// Parses a cron expression, ex: "30 2 * * *"
+    pub fn parse(expr: &str) -> Result<CronExpr, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Error in cron expression: {}, expected 5 fields", expr));
+        }
+
+        let mut weekdays = match parse_field(fields[4], 0, 7, "day of week") {
+            Ok(w) => w,
+            Err(e) => return Err(e),
+        };
+        if weekdays.contains(&7) {
+            weekdays.retain(|&d| d != 7);
+            if !weekdays.contains(&0) {
+                weekdays.insert(0, 0);
+            }
+        }
+
+        Ok(CronExpr {
+            minutes: match parse_field(fields[0], 0, 59, "minute") { Ok(v) => v, Err(e) => return Err(e) },
+            hours: match parse_field(fields[1], 0, 23, "hour") { Ok(v) => v, Err(e) => return Err(e) },
+            days: match parse_field(fields[2], 1, 31, "day of month") { Ok(v) => v, Err(e) => return Err(e) },
+            months: match parse_field(fields[3], 1, 12, "month") { Ok(v) => v, Err(e) => return Err(e) },
+            weekdays: weekdays,
+            days_any: fields[2] == "*",
+            weekdays_any: fields[4] == "*",
+        })
+    }
+
+    /// Day matches day of month and day of week, if both
+    /// are restricted either may match as in cron.
+    fn day_matches(&self, date: &NaiveDate) -> bool {
+        let day = self.days.contains(&date.day());
+        let weekday = self.weekdays.contains(&date.weekday().num_days_from_sunday());
+
+        if self.days_any || self.weekdays_any {
+            day && weekday
+        }
+        else {
+            day || weekday
+        }
+    }
+
+    /// First time after time that matches expression, times
+    /// are wall clock times. Returns None if no time matches
+    /// within five years, ex: 30 February.
+    pub fn next_after(&self, time: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = time.date().and_hms(time.hour(), time.minute(), 0) + Duration::minutes(1);
+        let limit = *time + Duration::days(5 * 366);
+
+        while t < limit {
+            if !self.months.contains(&t.month()) {
+                let (y, m) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = NaiveDate::from_ymd(y, m, 1).and_hms(0, 0, 0);
+            }
+            else if !self.day_matches(&t.date()) {
+                t = t.date().succ().and_hms(0, 0, 0);
+            }
+            else if !self.hours.contains(&t.hour()) {
+                t = t.date().and_hms(t.hour(), 0, 0) + Duration::hours(1);
+            }
+            else if !self.minutes.contains(&t.minute()) {
+                t = t + Duration::minutes(1);
+            }
+            else {
+                return Some(t);
+            }
+        }
+
+        None
+    }
+}
+
+
+/// When a job in daemon mode runs, either
+/// a cron expression or an interval in seconds.
+#[derive(Debug, PartialEq, Clone)]
+pub enum JobSchedule {
+    Cron(CronExpr),
+    Interval(u64),
+}
+
+impl JobSchedule {
+    /// Schedule from config settings, at most one of cron
+    /// and interval may be set. None if both are missing.
+    pub fn from_config(cron: &Option<String>, interval: Option<u64>, name: &str) -> Result<Option<JobSchedule>, String> {
+        match (cron, interval) {
+            (&Some(_), Some(_)) => Err(format!("Error in parameters: {}_cron and {}_interval, only one may be set", name, name)),
+            (&Some(ref c), None) => match CronExpr::parse(c) {
+                Ok(c) => Ok(Some(JobSchedule::Cron(c))),
+                Err(e) => Err(format!("Error in parameter: {}_cron, {}", name, e)),
+            },
+            (&None, Some(0)) => Err(format!("Error in parameter: {}_interval, must be larger than 0", name)),
+            (&None, Some(i)) => Ok(Some(JobSchedule::Interval(i))),
+            (&None, None) => Ok(None),
+        }
+    }
+
+    /// Next time job should run after time, cron
+    /// expressions are in time zone tz.
+    pub fn next_after(&self, time: DateTime<Utc>, tz: &SnapTimeZone) -> Option<DateTime<Utc>> {
+        match *self {
+            JobSchedule::Interval(i) => Some(time + Duration::seconds(i as i64)),
+            JobSchedule::Cron(ref c) => {
+                let mut t = tz.naive_from_utc(time);
+
+                loop {
+                    t = match c.next_after(&t) {
+                        Some(n) => n,
+                        None => return None,
+                    };
+
+                    if let Some(u) = tz.naive_to_utc(&t) {
+                        if u > time {
+                            return Some(u);
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cron_next() {
+        let t = NaiveDate::from_ymd(2018, 2, 16).and_hms(11, 44, 3);
+
+        let c = CronExpr::parse("30 2 * * *").unwrap();
+        assert_eq!(c.next_after(&t), Some(NaiveDate::from_ymd(2018, 2, 17).and_hms(2, 30, 0)));
+
+        let c = CronExpr::parse("*/15 * * * *").unwrap();
+        assert_eq!(c.next_after(&t), Some(NaiveDate::from_ymd(2018, 2, 16).and_hms(11, 45, 0)));
+
+        let c = CronExpr::parse("0 0 * * 7").unwrap();
+        assert_eq!(c.next_after(&t), Some(NaiveDate::from_ymd(2018, 2, 18).and_hms(0, 0, 0)));
+
+        let c = CronExpr::parse("0 12 1 * 1").unwrap();
+        assert_eq!(c.next_after(&t), Some(NaiveDate::from_ymd(2018, 2, 19).and_hms(12, 0, 0)));
+
+        let c = CronExpr::parse("0 1 1 1-3,12 *").unwrap();
+        assert_eq!(c.next_after(&t), Some(NaiveDate::from_ymd(2018, 3, 1).and_hms(1, 0, 0)));
+
+        let c = CronExpr::parse("0 0 30 2 *").unwrap();
+        assert_eq!(c.next_after(&t), None);
+
+        assert!(CronExpr::parse("0 0 * *").is_err());
+        assert!(CronExpr::parse("60 0 * * *").is_err());
+        assert!(CronExpr::parse("0 0 * * mon").is_err());
+        assert!(CronExpr::parse("*/0 0 * * *").is_err());
+
+        let tz = SnapTimeZone::parse("Europe/Stockholm").unwrap();
+        let j = JobSchedule::from_config(&Some(String::from("30 2 * * *")), None, "snapshot").unwrap().unwrap();
+        assert_eq!(j.next_after(Utc.ymd(2018, 3, 24).and_hms(12, 0, 0), &tz), Some(Utc.ymd(2018, 3, 26).and_hms(0, 30, 0)));
+
+        let j = JobSchedule::from_config(&None, Some(3600), "snapshot").unwrap().unwrap();
+        assert_eq!(j.next_after(Utc.ymd(2018, 3, 24).and_hms(12, 0, 0), &tz), Some(Utc.ymd(2018, 3, 24).and_hms(13, 0, 0)));
+        assert_eq!(JobSchedule::from_config(&None, None, "prune"), Ok(None));
+        assert!(JobSchedule::from_config(&Some(String::from("* * * * *")), Some(60), "prune").is_err());
+    }
+}
@@ -0,0 +1,306 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use chrono::prelude::*;
+use serde_json;
+use HostType;
+
+/// Prompt from gluster snapshot delete that
+/// ends up in output when answered with yes
+static CONTINUE_PROMPT: &'static str = "Do you still want to continue? (y/n)";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Info => "info",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// One entry in the log of a run
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    pub timestamp: String,
+    pub level: Level,
+    pub host: HostType,
+    pub session: String,
+    pub phase: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Name of snapshot deleted by ggsnap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<String>,
+}
+
+impl Event {
+    /// Event that is not yet in a log, session and phase
+    /// are set when it is added with EventLog::extend.
+    pub fn new(level: Level, host: HostType, message: &str) -> Event {
+        Event {
+            timestamp: Utc::now().to_rfc3339(),
+            level: level,
+            host: host,
+            session: String::new(),
+            phase: String::new(),
+            message: message.to_string(),
+            command: None,
+            exit_code: None,
+            deleted: None,
+        }
+    }
+
+    pub fn info(host: HostType, message: &str) -> Event {
+        Event::new(Level::Info, host, message)
+    }
+
+    pub fn warning(host: HostType, message: &str) -> Event {
+        Event::new(Level::Warning, host, message)
+    }
+
+    pub fn error(host: HostType, message: &str) -> Event {
+        Event::new(Level::Error, host, message)
+    }
+
+    /// Snapshot deleted, message is the snapshot name
+    pub fn deleted(host: HostType, snapshot: &str) -> Event {
+        let mut e = Event::new(Level::Info, host, snapshot);
+        e.deleted = Some(snapshot.to_string());
+        e
+    }
+
+    /// One event per line in text, empty lines
+    /// and the yes/no prompt of gluster are left out.
+    pub fn lines(level: Level, host: HostType, text: &str) -> Vec<Event> {
+        strip_prompt(text).iter().map(|l| Event::new(level, host, l)).collect()
+    }
+
+    /// Events for output of a command, one event per line.
+    /// Exit code None means that the command did not run
+    /// or was killed.
+    pub fn command(level: Level, host: HostType, command: &str, exit_code: Option<i32>, output: &str) -> Vec<Event> {
+        let mut events = Event::lines(level, host, output);
+
+        if events.is_empty() && level == Level::Error {
+            events.push(Event::new(level, host, &format!("Error running command: {}", command)));
+        }
+
+        for e in events.iter_mut() {
+            e.command = Some(command.to_string());
+            e.exit_code = exit_code;
+        }
+
+        events
+    }
+}
+
+/// Start of a phase in a run, first_event is
+/// the index of the first event in the phase.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseStart {
+    pub name: String,
+    pub start: DateTime<Utc>,
+    pub first_event: usize,
+}
+
+/// Log of a run, events are collected with the
+/// session and the phase that is current when
+/// the event is added.
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    pub session: String,
+    pub phase: String,
+    pub phases: Vec<PhaseStart>,
+    pub events: Vec<Event>,
+}
+
+impl EventLog {
+    pub fn new(session: &str) -> EventLog {
+        EventLog {
+            session: session.to_string(),
+            phase: String::from("start"),
+            phases: vec![PhaseStart { name: String::from("start"), start: Utc::now(), first_event: 0 }],
+            events: Vec::new(),
+        }
+    }
+
+    /// Sets phase for events added after this
+    pub fn set_phase(&mut self, phase: &str) {
+        self.phase = phase.to_string();
+        self.phases.push(PhaseStart { name: phase.to_string(), start: Utc::now(), first_event: self.events.len() });
+    }
+
+    /// Events added while phase number i was current,
+    /// a phase that is set twice has two numbers.
+    pub fn phase_events(&self, i: usize) -> &[Event] {
+        let end = match self.phases.get(i + 1) {
+            Some(p) => p.first_event,
+            None => self.events.len(),
+        };
+
+        match self.phases.get(i) {
+            Some(p) => &self.events[p.first_event..end],
+            None => &[],
+        }
+    }
+
+    /// Adds events to current phase of log
+    pub fn extend(&mut self, events: Vec<Event>) {
+        for mut e in events {
+            e.session = self.session.clone();
+            e.phase = self.phase.clone();
+            self.events.push(e);
+        }
+    }
+
+    /// Adds event, one event per line in message
+    pub fn log(&mut self, level: Level, host: HostType, message: &str) {
+        self.extend(Event::lines(level, host, message));
+    }
+
+    pub fn info(&mut self, host: HostType, message: &str) {
+        self.log(Level::Info, host, message);
+    }
+
+    pub fn warning(&mut self, host: HostType, message: &str) {
+        self.log(Level::Warning, host, message);
+    }
+
+    pub fn error(&mut self, host: HostType, message: &str) {
+        self.log(Level::Error, host, message);
+    }
+
+    /// Adds output of a command, see Event::command
+    pub fn command(&mut self, level: Level, host: HostType, command: &str, exit_code: Option<i32>, output: &str) {
+        self.extend(Event::command(level, host, command, exit_code, output));
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.events.iter().any(|e| e.level == Level::Error)
+    }
+}
+
+/// Splits output in lines without empty lines
+/// and the yes/no prompt of gluster.
+fn strip_prompt(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for l in text.lines() {
+        for (i, s) in l.split(CONTINUE_PROMPT).enumerate() {
+            let s = if i > 0 { s.trim() } else { s.trim_end() };
+            if s.len() > 0 {
+                lines.push(s.to_string());
+            }
+        }
+    }
+
+    lines
+}
+
+/// Formats events as text lines prefixed with host
+pub fn format_text(events: &[Event]) -> String {
+    let mut text = String::new();
+
+    for e in events {
+        let line = match e.level {
+            Level::Warning if !e.message.contains("Warning") => format!("Warning: {}", e.message),
+            _ => e.message.clone(),
+        };
+        text = format!("{}\n{}: {}", text, ::host_label(&e.host), line);
+    }
+
+    text
+}
+
+/// Reads events written with format_json, as printed by
+/// ggsnap_slave. Lines that are not events, like errors
+/// from ssh, are added with level and host.
+pub fn parse_json(level: Level, host: HostType, text: &str) -> Vec<Event> {
+    let mut events: Vec<Event> = Vec::new();
+
+    for l in text.lines() {
+        match serde_json::from_str::<Event>(l) {
+            Ok(e) => events.push(e),
+            Err(_) => events.extend(Event::lines(level, host, l)),
+        }
+    }
+
+    events
+}
+
+/// Formats events as JSON lines, one object per event
+pub fn format_json(events: &[Event]) -> String {
+    let mut text = String::new();
+
+    for e in events {
+        match serde_json::to_string(e) {
+            Ok(j) => text = format!("{}{}\n", text, j),
+            Err(err) => text = format!("{}{{\"level\":\"error\",\"message\":\"Error formatting event: {}\"}}\n", text, err),
+        }
+    }
+
+    text
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_log() {
+        let mut log = EventLog::new("vol root@slave::svol");
+        log.set_phase("remove");
+        let slave = format_json(&vec![Event::info(HostType::Slave, "Removing old snapshots"),
+                                      Event::error(HostType::Slave, "Error deleting snap2")]);
+        log.extend(parse_json(Level::Info, HostType::Master, &format!("{}ssh: banner\n", slave)));
+        log.extend(Event::lines(Level::Info, HostType::Master, "snap1\nDeleting snapshot will erase all the information about the snapshot. Do you still want to continue? (y/n) snapshot delete: snap1: snap removed successfully\n\n"));
+        log.command(Level::Error, HostType::Master, "gluster volume geo-replication vol root@slave::svol resume", Some(1), "Geo-replication not paused\n");
+
+        let hosts: Vec<&HostType> = log.events.iter().map(|e| &e.host).collect();
+        assert_eq!(hosts, vec![&HostType::Slave, &HostType::Slave, &HostType::Master, &HostType::Master, &HostType::Master, &HostType::Master, &HostType::Master]);
+        assert_eq!(log.events[1].level, Level::Error);
+        assert_eq!(log.events[1].phase, "remove");
+        assert_eq!(log.events[2].message, "ssh: banner");
+        assert_eq!(log.events[5].message, "snapshot delete: snap1: snap removed successfully");
+        assert_eq!(log.events[6].exit_code, Some(1));
+        assert_eq!(log.events[6].phase, "remove");
+        assert!(log.has_errors());
+
+        assert_eq!(format_text(&log.events[..2]), "\nSlave: Removing old snapshots\nSlave: Error deleting snap2");
+
+        let json = format_json(&log.events[6..]);
+        assert!(json.starts_with("{\"timestamp\":\""));
+        assert!(json.ends_with("\"level\":\"error\",\"host\":\"master\",\"session\":\"vol root@slave::svol\",\"phase\":\"remove\",\"message\":\"Geo-replication not paused\",\"command\":\"gluster volume geo-replication vol root@slave::svol resume\",\"exit_code\":1}\n"));
+    }
+}
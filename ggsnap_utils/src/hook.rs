@@ -0,0 +1,289 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::io::{ ErrorKind, Read };
+use std::os::unix::process::CommandExt;
+use std::process::{ Command, Stdio };
+use std::sync::mpsc::{ self, Sender };
+use std::thread;
+use std::time::{ Duration, Instant };
+use libc;
+use event::{Event, Level};
+use {host_volume, systemd, Config, HostType};
+
+/// Default timeout in seconds for a hook
+pub static DEFAULT_HOOK_TIMEOUT: u64 = 300;
+
+/// Phases where a hook can run, pre/post create
+/// are run by ggsnap_slave and the others by ggsnap.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Phase {
+    PrePause,
+    PreSnapshot,
+    PostSnapshot,
+    PostResume,
+    PreCreate,
+    PostCreate,
+}
+
+impl Phase {
+    /// Name used in config file and in GGSNAP_PHASE
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Phase::PrePause => "pre_pause",
+            Phase::PreSnapshot => "pre_snapshot",
+            Phase::PostSnapshot => "post_snapshot",
+            Phase::PostResume => "post_resume",
+            Phase::PreCreate => "pre_create",
+            Phase::PostCreate => "post_create",
+        }
+    }
+}
+
+/// Hook command from sub section [hooks.PHASE]
+/// in config file
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Hook {
+    pub command: String,
+    pub timeout: Option<u64>,
+    pub on_failure: Option<String>,
+}
+
+/// Struct that holds information about sub section [hooks]
+/// in config file
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Hooks {
+    pub pre_pause: Option<Hook>,
+    pub pre_snapshot: Option<Hook>,
+    pub post_snapshot: Option<Hook>,
+    pub post_resume: Option<Hook>,
+    pub pre_create: Option<Hook>,
+    pub post_create: Option<Hook>,
+}
+
+impl Hooks {
+    fn get(&self, phase: Phase) -> Option<&Hook> {
+        match phase {
+            Phase::PrePause => self.pre_pause.as_ref(),
+            Phase::PreSnapshot => self.pre_snapshot.as_ref(),
+            Phase::PostSnapshot => self.post_snapshot.as_ref(),
+            Phase::PostResume => self.post_resume.as_ref(),
+            Phase::PreCreate => self.pre_create.as_ref(),
+            Phase::PostCreate => self.post_create.as_ref(),
+        }
+    }
+}
+
+/// Checks that on_failure in all hooks is abort or continue
+pub fn check_hooks(hooks: &Hooks) -> Result<(), String> {
+    let phases = [Phase::PrePause, Phase::PreSnapshot, Phase::PostSnapshot,
+                  Phase::PostResume, Phase::PreCreate, Phase::PostCreate];
+
+    for p in phases.iter() {
+        if let Some(h) = hooks.get(*p) {
+            if let Some(ref f) = h.on_failure {
+                if f != "abort" && f != "continue" {
+                    return Err(format!("Error in parameter: hooks.{}.on_failure, valid values are: abort, continue",
+                                       p.name()));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs hook for phase if configured in [hooks], returns
+/// None if there is no hook for phase.
+/// The command is run with sh -c and environment variables
+/// GGSNAP_PHASE, GGSNAP_SNAPSHOT_NAME, GGSNAP_VOLUME and
+/// GGSNAP_HOST (master or slave). A hook that runs longer
+/// than timeout is killed.
+/// A failed hook returns an error if on_failure is abort,
+/// if on_failure is continue a warning is logged.
+pub fn run_hook(config: &Config, phase: Phase, snap_name: &str, host_type: &HostType) -> Option<Result<Vec<Event>, Vec<Event>>> {
+    let hook = match config.hooks {
+        Some(ref h) => match h.get(phase) {
+            Some(h) => h,
+            None => return None,
+        },
+        None => return None,
+    };
+
+    let host = if *host_type == HostType::Master { "master" } else { "slave" };
+    let abort = hook.on_failure.clone().unwrap_or(String::from("abort")) == "abort";
+    let timeout = hook.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT);
+    let mut log = vec![Event::info(*host_type, &format!("Running {} hook: {}", phase.name(), hook.command))];
+
+    let res = run_command(&hook.command, timeout, &[("GGSNAP_PHASE", phase.name()),
+                                                    ("GGSNAP_SNAPSHOT_NAME", snap_name),
+                                                    ("GGSNAP_VOLUME", &host_volume(config, host_type)),
+                                                    ("GGSNAP_HOST", host)]);
+
+    match res {
+        Ok(o) => {
+            log.extend(Event::command(Level::Info, *host_type, &hook.command, Some(0), &o));
+            Some(Ok(log))
+        },
+        Err(e) => {
+            if abort {
+                log.push(Event::error(*host_type, &format!("Error: {} hook failed:", phase.name())));
+                log.extend(Event::lines(Level::Error, *host_type, &e));
+                Some(Err(log))
+            }
+            else {
+                log.push(Event::warning(*host_type, &format!("Warning: {} hook failed, continuing:", phase.name())));
+                log.extend(Event::lines(Level::Warning, *host_type, &e));
+                Some(Ok(log))
+            }
+        }
+    }
+}
+
+/// Runs command with sh -c and waits at most
+/// timeout seconds, returns stdout and stderr.
+/// On timeout the whole process group is killed.
+fn run_command(command: &str, timeout: u64, env: &[(&str, &str)]) -> Result<String, String> {
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c")
+       .arg(command)
+       .stdin(Stdio::null())
+       .stdout(Stdio::piped())
+       .stderr(Stdio::piped());
+
+    for &(k, v) in env {
+        cmd.env(k, v);
+    }
+
+    // Own process group so that all processes
+    // started by hook are killed on timeout
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setpgid(0, 0);
+            Ok(())
+        });
+    }
+
+    let mut child = match cmd.spawn() {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Error running command: {}; {}", command, e)),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    read_pipe(child.stdout.take().unwrap(), 0, tx.clone());
+    read_pipe(child.stderr.take().unwrap(), 1, tx);
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(s)) => break Ok(s),
+            Ok(None) => {
+                if start.elapsed() >= Duration::from_secs(timeout) {
+                    unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL); }
+                    let _ = child.wait();
+                    break Err(format!("Timeout after {} seconds", timeout));
+                }
+                systemd::watchdog();
+                thread::sleep(Duration::from_millis(100));
+            },
+            Err(e) => break Err(format!("Error waiting for command: {}", e)),
+        }
+    };
+
+    // A process started in background by hook can keep
+    // the pipes open, output is read for one more second
+    // after the hook exits and then the reader threads
+    // are left behind.
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut out: Vec<u8> = Vec::new();
+    let mut err: Vec<u8> = Vec::new();
+    let mut open = 2;
+    while open > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+
+        match rx.recv_timeout(deadline - now) {
+            Ok((0, Some(d))) => out.extend(d),
+            Ok((_, Some(d))) => err.extend(d),
+            Ok((_, None)) => open -= 1,
+            Err(_) => break,
+        }
+    }
+
+    let output = format!("{}{}", String::from_utf8_lossy(&out), String::from_utf8_lossy(&err));
+
+    match status {
+        Ok(s) => {
+            if s.success() {
+                Ok(output)
+            }
+            else {
+                Err(format!("{}\n{}", s, output))
+            }
+        },
+        Err(e) => Err(format!("{}\n{}", e, output)),
+    }
+}
+
+/// Reads pipe in a thread and sends the data to tx
+/// tagged with stream, None is sent at end of file.
+fn read_pipe<R: Read + Send + 'static>(mut pipe: R, stream: usize, tx: Sender<(usize, Option<Vec<u8>>)>) {
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if tx.send((stream, Some(buf[..n].to_vec()))).is_err() {
+                        return;
+                    }
+                },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+        let _ = tx.send((stream, None));
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hook_command() {
+        assert_eq!(run_command("echo $GGSNAP_PHASE", 5, &[("GGSNAP_PHASE", "pre_pause")]),
+                   Ok(String::from("pre_pause\n")));
+        assert!(run_command("exit 3", 5, &[]).is_err());
+        assert!(run_command("sleep 5", 1, &[]).unwrap_err().starts_with("Timeout after 1 seconds"));
+
+        let start = Instant::now();
+        assert_eq!(run_command("sleep 5 & echo started", 1, &[]), Ok(String::from("started\n")));
+        assert!(start.elapsed() < Duration::from_secs(4));
+
+        let start = Instant::now();
+        assert_eq!(run_command("sleep 30 & echo done", 10, &[]), Ok(String::from("done\n")));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+}
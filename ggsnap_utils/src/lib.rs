@@ -20,8 +20,11 @@
 
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 extern crate toml;
 extern crate chrono;
+extern crate chrono_tz;
+extern crate libc;
 
 use std::fs::File;
 use std::io::prelude::*;
@@ -30,6 +33,31 @@ use std::collections::HashSet;
 use std::iter::FromIterator;
 use chrono::prelude::*;
 
+pub mod capacity;
+pub mod cron;
+pub mod event;
+pub mod hook;
+pub mod limits;
+pub mod lock;
+pub mod logfile;
+pub mod mail;
+pub mod naming;
+pub mod notify;
+pub mod pin;
+pub mod report;
+pub mod state;
+pub mod syslog;
+pub mod systemd;
+pub mod tiers;
+pub mod timezone;
+pub mod webhook;
+
+use event::{Event, Level};
+use hook::Hooks;
+use webhook::NotifyWebhook;
+use naming::NameTemplate;
+use timezone::{config_timezone, SnapTimeZone};
+
 static CONF_FILE: &'static str = "ggsnap.conf";
 static CONF_ETC_DIR: &'static str = "/etc/ggsnap.conf";
 static CONF_ETC_SUB_DIR: &'static str = "/etc/ggsnap/ggsnap.conf";
@@ -41,7 +69,10 @@ static CONF_ETC_SUB_DIR: &'static str = "/etc/ggsnap/ggsnap.conf";
 pub struct Config {
     pub general: General,
     pub snapshot: Snapshot,
+    pub hooks: Option<Hooks>,
+    pub schedule: Option<Schedule>,
     pub mail_from_master: Option<MailFromMaster>,
+    pub notify_webhook: Option<NotifyWebhook>,
 }
 
 impl Config {
@@ -53,6 +84,23 @@ impl Config {
                 gluster_bin: String::from("/usr/sbin/gluster"),
                 ggsnap_slave_bin: String::from("/root/ggsnap_slave"),
                 log_file: String::from("ggsnap.log"),
+                pin_file: Some(String::from("ggsnap_pins.toml")),
+                lvs_bin: Some(String::from("/usr/sbin/lvs")),
+                lock_dir: Some(String::from("/var/run")),
+                lock_policy: Some(String::from("wait")),
+                lock_wait_timeout: Some(lock::DEFAULT_LOCK_WAIT),
+                lock_stale_seconds: Some(lock::DEFAULT_LOCK_STALE),
+                log_target: Some(String::from("file")),
+                log_format: Some(String::from("text")),
+                report_file: Some(String::new()),
+                log_dir: Some(String::new()),
+                log_rotate_size_mb: Some(0),
+                log_rotate_days: Some(0),
+                log_keep_files: Some(logfile::DEFAULT_KEEP_FILES),
+                log_compress: Some(false),
+                syslog_facility: Some(String::from(syslog::DEFAULT_FACILITY)),
+                syslog_ident: Some(String::from(syslog::DEFAULT_IDENT)),
+                metrics_file: Some(String::new()),
             },
             snapshot: Snapshot {
                 number_days_every_day: 10,
@@ -61,12 +109,26 @@ impl Config {
                 snapshot_name_prefix: Some(String::from("ggsnap")),
 		delay_after_pause_before_snapshot: Some(0),
 		delay_resume_geo_replication: Some(0),
+                manual_snapshot_expire_days: Some(30),
+                snapshot_name_template: Some(String::from(naming::DEFAULT_TEMPLATE)),
+                manual_snapshot_name_template: Some(String::from(naming::DEFAULT_MANUAL_TEMPLATE)),
+                timezone: Some(String::from("local")),
+                delete_oldest_at_limit: Some(false),
+                thin_pool_max_data_percent: Some(90),
+                thin_pool_max_metadata_percent: Some(90),
+                thin_pool_full_action: Some(String::from("refuse")),
+                capacity_target_percent: Some(0),
+                capacity_keep_newest: Some(7),
+                preflight_checks: Some(true),
                 master_volume: None,
                 slave_volume: None,
                 slave_hostname: None,
                 slave_user: None
             },
-            mail_from_master: None
+            hooks: None,
+            schedule: None,
+            mail_from_master: None,
+            notify_webhook: None,
         }
     }
 }
@@ -78,6 +140,23 @@ pub struct General {
     pub gluster_bin: String,
     pub ggsnap_slave_bin: String,
     pub log_file: String,
+    pub pin_file: Option<String>,
+    pub lvs_bin: Option<String>,
+    pub lock_dir: Option<String>,
+    pub lock_policy: Option<String>,
+    pub lock_wait_timeout: Option<u64>,
+    pub lock_stale_seconds: Option<u64>,
+    pub log_target: Option<String>,
+    pub log_format: Option<String>,
+    pub report_file: Option<String>,
+    pub log_dir: Option<String>,
+    pub log_rotate_size_mb: Option<u64>,
+    pub log_rotate_days: Option<u64>,
+    pub log_keep_files: Option<u32>,
+    pub log_compress: Option<bool>,
+    pub syslog_facility: Option<String>,
+    pub syslog_ident: Option<String>,
+    pub metrics_file: Option<String>,
 }
 
 /// Struct that holds information about sub section [snapshot]  
@@ -90,24 +169,62 @@ pub struct Snapshot {
     pub snapshot_name_prefix: Option<String>,
     pub delay_after_pause_before_snapshot: Option<u64>,
     pub delay_resume_geo_replication: Option<u64>,
+    pub manual_snapshot_expire_days: Option<u32>,
+    pub snapshot_name_template: Option<String>,
+    pub manual_snapshot_name_template: Option<String>,
+    pub timezone: Option<String>,
+    pub delete_oldest_at_limit: Option<bool>,
+    pub thin_pool_max_data_percent: Option<u32>,
+    pub thin_pool_max_metadata_percent: Option<u32>,
+    pub thin_pool_full_action: Option<String>,
+    pub capacity_target_percent: Option<u32>,
+    pub capacity_keep_newest: Option<u32>,
+    pub preflight_checks: Option<bool>,
     pub master_volume: Option<String>,
     pub slave_volume: Option<String>,
     pub slave_hostname: Option<String>,
     pub slave_user: Option<String>,
 }
 
+/// Struct that holds information about sub section [schedule]  
+/// in config file, used by ggsnap daemon
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Schedule {
+    pub snapshot_cron: Option<String>,
+    pub snapshot_interval: Option<u64>,
+    pub prune_cron: Option<String>,
+    pub prune_interval: Option<u64>,
+    pub catch_up: Option<bool>,
+    pub state_file: Option<String>,
+    pub http_listen: Option<String>,
+}
+
 /// Struct that holds information about sub section [mail_from_master]  
 /// in config file
 #[derive(Deserialize, Debug, PartialEq)]
 pub struct MailFromMaster {
+    pub smtp_host: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub smtp_tls: Option<String>,
+    pub smtp_ca_file: Option<String>,
+    pub smtp_timeout: Option<u64>,
     pub tls_domain: Option<String>,
+    #[serde(default)]
     pub authentication_mechanism: String,
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
     pub password: String,
     pub from_sender_address: String,
     pub to_addresses: Vec<String>,
     pub subject: String, 
     pub enable: bool,
+    pub format: Option<String>,
+    pub text_template: Option<String>,
+    pub html_template: Option<String>,
+    pub attach_log: Option<bool>,
+    pub notify: Option<String>,
+    pub notify_state_file: Option<String>,
 }
 
 
@@ -120,7 +237,8 @@ pub enum ConfigReadErr {
     ConfigValueErr,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
 pub enum HostType {
     Master,
     Slave,
@@ -206,6 +324,102 @@ pub fn get_config() -> Result<Config, (ConfigReadErr, String)> {
         Err(e) => return Err(e),
     };
     
+    if let Err(e) = check_name_templates(&config) {
+        return Err((ConfigReadErr::ConfigValueErr, format!("    {}", e)));
+    }
+
+    if let Some(ref z) = config.snapshot.timezone {
+        if let Err(e) = SnapTimeZone::parse(z) {
+            return Err((ConfigReadErr::ConfigValueErr, format!("    {}", e)));
+        }
+    }
+
+    if let Some(ref s) = config.schedule {
+        if let Err(e) = cron::JobSchedule::from_config(&s.snapshot_cron, s.snapshot_interval, "snapshot") {
+            return Err((ConfigReadErr::ConfigValueErr, format!("    {}", e)));
+        }
+
+        if let Err(e) = cron::JobSchedule::from_config(&s.prune_cron, s.prune_interval, "prune") {
+            return Err((ConfigReadErr::ConfigValueErr, format!("    {}", e)));
+        }
+    }
+
+    if let Some(ref t) = config.general.log_target {
+        if t != "file" && t != "journald" && t != "syslog" {
+            return Err((ConfigReadErr::ConfigValueErr,
+                        format!("    Error in parameter: log_target, valid values are: file, journald, syslog")));
+        }
+    }
+
+    if let Some(ref f) = config.general.syslog_facility {
+        if syslog::facility_code(f).is_none() {
+            return Err((ConfigReadErr::ConfigValueErr,
+                        format!("    Error in parameter: syslog_facility, valid values are: kern, user, mail, daemon, auth,\n    syslog, lpr, news, uucp, cron, authpriv, ftp and local0 to local7")));
+        }
+    }
+
+    if let Some(ref f) = config.general.log_format {
+        if f != "text" && f != "json" {
+            return Err((ConfigReadErr::ConfigValueErr,
+                        format!("    Error in parameter: log_format, valid values are: text, json")));
+        }
+    }
+
+    if let Some(ref m) = config.mail_from_master {
+        if let Some(ref f) = m.format {
+            if f != "html" && f != "text" {
+                return Err((ConfigReadErr::ConfigValueErr,
+                            format!("    Error in parameter: format in [mail_from_master], valid values are: html, text")));
+            }
+        }
+
+        if let Some(ref t) = m.smtp_tls {
+            if mail::TlsMode::from_name(t).is_none() {
+                return Err((ConfigReadErr::ConfigValueErr,
+                            format!("    Error in parameter: smtp_tls in [mail_from_master], valid values are: none, starttls, implicit")));
+            }
+        }
+
+        if m.authentication_mechanism != "" && m.authentication_mechanism != "plain" &&
+           m.authentication_mechanism != "login" && m.authentication_mechanism != "crammd5" {
+            return Err((ConfigReadErr::ConfigValueErr,
+                        format!("    Error in parameter: authentication_mechanism in [mail_from_master], valid values are: plain,\n    login, crammd5")));
+        }
+
+        if let Some(ref n) = m.notify {
+            if notify::Policy::from_name(n).is_none() {
+                return Err((ConfigReadErr::ConfigValueErr,
+                            format!("    Error in parameter: notify in [mail_from_master], valid values are: always, failure,\n    change, daily, weekly")));
+            }
+        }
+    }
+
+    if let Some(ref w) = config.notify_webhook {
+        if let Err(e) = webhook::check_webhook(w) {
+            return Err((ConfigReadErr::ConfigValueErr, format!("    {}", e)));
+        }
+    }
+
+    if let Some(ref p) = config.general.lock_policy {
+        if p != "wait" && p != "skip" && p != "fail" {
+            return Err((ConfigReadErr::ConfigValueErr,
+                        format!("    Error in parameter: lock_policy, valid values are: wait, skip, fail")));
+        }
+    }
+
+    if let Some(ref h) = config.hooks {
+        if let Err(e) = hook::check_hooks(h) {
+            return Err((ConfigReadErr::ConfigValueErr, format!("    {}", e)));
+        }
+    }
+
+    if let Some(ref a) = config.snapshot.thin_pool_full_action {
+        if a != "refuse" && a != "prune" {
+            return Err((ConfigReadErr::ConfigValueErr,
+                        format!("    Error in parameter: thin_pool_full_action, valid values are: refuse, prune")));
+        }
+    }
+
     let month_30 = vec![4, 6, 9, 11];
     let today = Local::today();
     let mut date1 = today - chrono::Duration::days(config.snapshot.number_days_every_day as i64);
@@ -263,6 +477,74 @@ pub fn get_config() -> Result<Config, (ConfigReadErr, String)> {
     
 }
 
+/// Checks that snapshot name templates are valid,
+/// the manual template must contain {label} and
+/// the ordinary template must not.
+/// Names created with one template must not be
+/// possible to read with the other, or manual
+/// and ordinary snapshots would be pruned by
+/// the wrong rules. Sample names are tried, which
+/// finds common overlaps but does not prove there
+/// are none, names that match both templates are
+/// therefore never removed.
+fn check_name_templates(config: &Config) -> Result<(), String> {
+    let normal = match config.snapshot.snapshot_name_template {
+        Some(ref t) => t.clone(),
+        None => String::from(naming::DEFAULT_TEMPLATE),
+    };
+
+    let normal = match NameTemplate::new(&normal) {
+        Ok(t) => {
+            if t.has_label() {
+                return Err(String::from("Error in parameter: snapshot_name_template, {label} is only allowed in manual_snapshot_name_template"));
+            }
+            t
+        },
+        Err(e) => return Err(e),
+    };
+
+    let manual = match config.snapshot.manual_snapshot_name_template {
+        Some(ref t) => t.clone(),
+        None => String::from(naming::DEFAULT_MANUAL_TEMPLATE),
+    };
+
+    let manual = match NameTemplate::new(&manual) {
+        Ok(t) => {
+            if !t.has_label() {
+                return Err(String::from("Error in parameter: manual_snapshot_name_template, {label} is required"));
+            }
+            t
+        },
+        Err(e) => return Err(e),
+    };
+
+    let prefix = config.snapshot.snapshot_name_prefix.clone().unwrap_or(String::from("ggsnap"));
+    let mut volumes = vec![String::from("vol")];
+    if let Some(ref v) = config.snapshot.master_volume {
+        volumes.push(v.clone());
+    }
+    if let Some(ref v) = config.snapshot.slave_volume {
+        volumes.push(v.clone());
+    }
+
+    let time = Utc.from_utc_datetime(&NaiveDate::from_ymd_opt(2018, 2, 16).unwrap().and_hms_opt(11, 30, 0).unwrap());
+    for volume in volumes.iter() {
+        let name = normal.format(&prefix, volume, None, time);
+        if manual.parse(&name, &prefix, volume).is_some() {
+            return Err(format!("Error in parameter: manual_snapshot_name_template, matches snapshot name: {}", name));
+        }
+
+        for label in ["label", "x", "1", "pre-upgrade"].iter() {
+            let name = manual.format(&prefix, volume, Some(label), time);
+            if normal.parse(&name, &prefix, volume).is_some() {
+                return Err(format!("Error in parameter: manual_snapshot_name_template, manual snapshot name: {}\n    matches snapshot_name_template", name));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Parses config string and returns a Config populated with
 /// the content from string
 fn parse_config(config_content: &String) -> Result<Config, (ConfigReadErr, String)> {
@@ -275,17 +557,26 @@ fn parse_config(config_content: &String) -> Result<Config, (ConfigReadErr, Strin
 
 /// Uses config file parameters in [snapshot]
 /// to deside what to save and what to delete
-/// On success a String containing removed snapshots
-/// will be returned. On error, error message will be returned
-pub fn remove_old_snapshots(config: &Config, host_type: HostType) -> Result<String, String> {
+/// Snapshots pinned in pin file are never deleted.
+/// If capacity_target_percent is set more snapshots
+/// are deleted until thin pool usage is below target.
+/// On success events with removed snapshots will be
+/// returned. On error, the events with the errors.
+pub fn remove_old_snapshots(config: &Config, host_type: HostType) -> Result<Vec<Event>, Vec<Event>> {
     let mut _snap_output: String = String::new();
     let mut _gluster_snaps: Vec<String> = Vec::new();
     let mut _rm_every_day: Vec<String> = Vec::new();
     let mut _rm_weeks_one: Vec<String> = Vec::new();
     let mut _rm_months_total: Vec<String> = Vec::new();
+    let mut _rm_manual: Vec<String> = Vec::new();
     let mut rm_tot: Vec<String> = Vec::new();
-    let mut rm_tot_res: String = String::new();
+    let mut rm_tot_res: Vec<Event> = Vec::new();
     let mut del_err: bool = false;
+    let pins = match pin::read_pins(config) {
+        Ok(p) => p,
+        Err(e) => return Err(Event::lines(Level::Error, host_type,
+                                          &format!("Error reading pinned snapshots, no snapshots removed\n{}", e))),
+    };
     let cmd_out = Command::new(&config.general.gluster_bin)
                           .arg("snapshot")
                           .arg("list")
@@ -303,114 +594,40 @@ pub fn remove_old_snapshots(config: &Config, host_type: HostType) -> Result<Stri
                 rm_tot.extend(_rm_weeks_one);
                 _rm_months_total = get_remove_months_total(&config, &_gluster_snaps, &host_type);
                 rm_tot.extend(_rm_months_total);
+                _rm_manual = get_remove_manual(&config, &_snap_output, &host_type);
+                rm_tot.extend(_rm_manual);
                 rm_tot.sort();
+                rm_tot = remove_pinned(&config, rm_tot, &pins, &host_type, &mut rm_tot_res);
 
                 for l in rm_tot {
-                    let yes_out = Command::new("/bin/yes").stdout(Stdio::piped()).spawn();
-
-                    if yes_out.is_err() {
-                        del_err = true;
-                        if rm_tot_res.len() == 0 {
-                            if host_type == HostType::Master {
-                                rm_tot_res = format!("Master: Error executing command yes, to delete snapshots");
-                            }
-                            else {
-                                rm_tot_res = format!("Slave: Error executing command yes, to delete snapshots");
-                            }
-                        }
-                        else {
-                            if host_type == HostType::Master {
-                                rm_tot_res = format!("{}\nMaster: Error executing command yes, to delete snapshots", rm_tot_res);
-                            }
-                            else {
-                                rm_tot_res = format!("{}\nSlave: Error executing command yes, to delete snapshots", rm_tot_res);
-                            }
+                    systemd::watchdog();
+                    match delete_snapshot(&config, &l, &host_type) {
+                        Ok(r) => rm_tot_res.extend(r),
+                        Err(r) => {
+                            del_err = true;
+                            rm_tot_res.extend(r);
                         }
-
-                        continue;
                     }
+                }
 
-                    let yes_out = yes_out.unwrap();
-
-                    let rm_out = Command::new(&config.general.gluster_bin)
-                                         .arg("snapshot")
-                                         .arg("delete")
-                                         .arg(&l)
-                                         .stdin(yes_out.stdout.unwrap())
-                                         .output();
-
-                    match rm_out {
-                        Ok(o) => {
-                            if o.status.success() {
-                                if rm_tot_res.len() == 0 {
-                                    if host_type == HostType::Master {
-                                        rm_tot_res = format!("Master: {}\nMaster: {}", l, String::from_utf8_lossy(&o.stdout));
-                                    }
-                                    else {
-                                        rm_tot_res = format!("Slave: {}\nSlave: {}", l, String::from_utf8_lossy(&o.stdout));
-                                    }
-                                }
-                                else {
-                                    if host_type == HostType::Master {
-                                        rm_tot_res = format!("{}\nMaster: {}\nMaster: {}", rm_tot_res, l, String::from_utf8_lossy(&o.stdout));
-                                    }
-                                    else {
-                                        rm_tot_res = format!("{}\nSlave: {}\nSlave: {}", rm_tot_res, l, String::from_utf8_lossy(&o.stdout));
-                                    }
-                                }
-                            }
-                            else {
-                                del_err = true;
-                                if rm_tot_res.len() == 0 {
-                                    if host_type == HostType::Master {
-                                        rm_tot_res = format!("Master: Error deleting snapshot: {}\nMaster: {}{}", l, 
-                                                             String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
-                                    }
-                                    else {
-                                        rm_tot_res = format!("Slave: Error deleting snapshot: {}\nSlave: {}{}", l, 
-                                                             String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
-                                    }
-                                }
-                                else {
-                                    if host_type == HostType::Master {
-                                        rm_tot_res = format!("{}\nMaster: Error deleting snapshot: {}\nMaster: {}{}", rm_tot_res, l, 
-                                                             String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
-                                    }
-                                    else {
-                                        rm_tot_res = format!("{}\nSlave: Error deleting snapshot: {}\nSlave: {}{}", rm_tot_res, l, 
-                                                             String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
-                                    }
-                                }
-                            }
-                        },
-                        Err(e) => {
+                if config.snapshot.capacity_target_percent.unwrap_or(0) > 0 {
+                    match capacity::prune_to_capacity_target(&config, &host_type) {
+                        Ok(r) => rm_tot_res.extend(r),
+                        Err(r) => {
                             del_err = true;
-                            if rm_tot_res.len() == 0 {
-                                if host_type == HostType::Master {
-                                    rm_tot_res = format!("Master: Error executing delete snapshot: {}\nMaster: {}", l, e.to_string());
-                                }
-                                else {
-                                    rm_tot_res = format!("Slave: Error executing delete snapshot: {}\nSlave: {}", l, e.to_string());
-                                }
-                            }
-                            else {
-                                if host_type == HostType::Master {
-                                    rm_tot_res = format!("{}\nMaster: Error executing delete snapshot: {}\nMaster: {}", rm_tot_res, l, e.to_string());
-                                }
-                                else {
-                                    rm_tot_res = format!("{}\nSlave: Error executing delete snapshot: {}\nSlave: {}", rm_tot_res, l, e.to_string());
-                                }
-                            }
-                        },
+                            rm_tot_res.extend(r);
+                        }
                     }
                 }
             }
             else {
-                return Err(format!("Error getting snapshots: {}{}", String::from_utf8_lossy(&o.stdout),
-                            String::from_utf8_lossy(&o.stderr)))
+                return Err(Event::lines(Level::Error, host_type,
+                                        &format!("Error getting snapshots: {}{}", String::from_utf8_lossy(&o.stdout),
+                                                 String::from_utf8_lossy(&o.stderr))))
             }
         },
-        Err(e) => return Err(format!("Error executing command: gluster snapshot list\n{}", e.to_string())),
+        Err(e) => return Err(Event::lines(Level::Error, host_type,
+                                          &format!("Error executing command: gluster snapshot list\n{}", e.to_string()))),
     }
 
     if del_err {
@@ -421,41 +638,243 @@ pub fn remove_old_snapshots(config: &Config, host_type: HostType) -> Result<Stri
     }
 }
 
+/// Deletes snapshot with gluster snapshot delete,
+/// returns events with name and output of gluster.
+fn delete_snapshot(config: &Config, snap_name: &str, host_type: &HostType) -> Result<Vec<Event>, Vec<Event>> {
+    let yes_out = match Command::new("/bin/yes").stdout(Stdio::piped()).spawn() {
+        Ok(y) => y,
+        Err(_) => return Err(vec![Event::error(*host_type, "Error executing command yes, to delete snapshots")]),
+    };
+
+    let cmd_text = format!("gluster snapshot delete {}", snap_name);
+    let rm_out = Command::new(&config.general.gluster_bin)
+                         .arg("snapshot")
+                         .arg("delete")
+                         .arg(snap_name)
+                         .stdin(yes_out.stdout.unwrap())
+                         .output();
 
-/// Filters all snapshots done by ggsnap
-/// and returns an ordered vector.
-fn filter_gluster_snapshots(all_snaps: &String, config: &Config, host_type: &HostType) -> Vec<String> {
-    let mut snap_prefix = config.snapshot.snapshot_name_prefix.clone().unwrap();
-    let mut filtered_snaps: Vec<String> = Vec::new();
+    match rm_out {
+        Ok(o) => {
+            if o.status.success() {
+                let mut events = vec![Event::deleted(*host_type, snap_name)];
+                events.extend(Event::command(Level::Info, *host_type, &cmd_text, o.status.code(),
+                                             &String::from_utf8_lossy(&o.stdout)));
+                Ok(events)
+            }
+            else {
+                let mut events = vec![Event::error(*host_type, &format!("Error deleting snapshot: {}", snap_name))];
+                events.extend(Event::command(Level::Error, *host_type, &cmd_text, o.status.code(),
+                                             &format!("{}{}", String::from_utf8_lossy(&o.stdout),
+                                                      String::from_utf8_lossy(&o.stderr))));
+                Err(events)
+            }
+        },
+        Err(e) => Err(vec![Event::error(*host_type, &format!("Error executing delete snapshot: {}", snap_name)),
+                           Event::error(*host_type, &e.to_string())]),
+    }
+}
+
+/// Lists snapshots of volume on master or
+/// slave with gluster snapshot list VOLUME,
+/// returns one snapshot name per line.
+fn list_volume_snapshots(config: &Config, host_type: &HostType) -> Result<String, String> {
+    let volume = host_volume(config, host_type);
+    let cmd_out = Command::new(&config.general.gluster_bin)
+                          .arg("snapshot")
+                          .arg("list")
+                          .arg(&volume)
+                          .output();
 
+    match cmd_out {
+        Ok(o) => {
+            if o.status.success() {
+                let snaps: Vec<String> = String::from_utf8_lossy(&o.stdout).lines()
+                    .filter(|l| l.trim().len() > 0 && !l.starts_with("No snapshots present"))
+                    .map(|l| l.to_string())
+                    .collect();
+                Ok(snaps.join("\n"))
+            }
+            else {
+                Err(format!("Error listing snapshots: {}{}", String::from_utf8_lossy(&o.stdout),
+                            String::from_utf8_lossy(&o.stderr)))
+            }
+        },
+        Err(e) => Err(format!("Error running command: gluster snapshot list {}; {}", volume, e)),
+    }
+}
+
+/// Snapshots created by ggsnap that are not
+/// pinned, ordered by creation time oldest first.
+/// The keep_newest newest snapshots are left out.
+/// snaps should be output from list_volume_snapshots.
+fn deletable_snapshots(config: &Config, snaps: &String, host_type: &HostType, keep_newest: usize) -> Result<Vec<String>, String> {
+    let pins = match pin::read_pins(config) {
+        Ok(p) => p,
+        Err(e) => return Err(format!("Error reading pinned snapshots, no snapshots removed\n{}", e)),
+    };
+    let today = config_timezone(config).today();
+    let mut gluster_snaps = filter_gluster_snapshots(snaps, config, host_type);
+    let len = gluster_snaps.len();
+    gluster_snaps.truncate(len - std::cmp::min(len, keep_newest));
+
+    Ok(gluster_snaps.into_iter()
+       .filter(|s| !pins.is_pinned(s, today))
+       .collect())
+}
+
+/// Log prefix for host type
+fn host_label(host_type: &HostType) -> &'static str {
     if *host_type == HostType::Master {
-        snap_prefix = format!("{}_{}_", snap_prefix, config.snapshot.master_volume.clone().unwrap());
+        "Master"
     }
     else {
-        snap_prefix = format!("{}_{}_", snap_prefix, config.snapshot.slave_volume.clone().unwrap());
+        "Slave"
     }
+}
 
-    let snap_pre_parts: Vec<&str> = snap_prefix.split("_").collect();
-    let snap_pre_parts_len = snap_pre_parts.len();
+
+/// Template for snapshot names from config setting
+/// snapshot_name_template, template is checked when
+/// config file is read.
+pub fn snapshot_template(config: &Config) -> NameTemplate {
+    NameTemplate::with_zone(&config.snapshot.snapshot_name_template.clone().unwrap(), config_timezone(config))
+        .expect("Invalid snapshot_name_template")
+}
+
+/// Template for manual snapshot names from config setting
+/// manual_snapshot_name_template, template is checked when
+/// config file is read.
+pub fn manual_snapshot_template(config: &Config) -> NameTemplate {
+    NameTemplate::with_zone(&config.snapshot.manual_snapshot_name_template.clone().unwrap(), config_timezone(config))
+        .expect("Invalid manual_snapshot_name_template")
+}
+
+/// Returns name of snapshot according to
+/// config setting snapshot_name_template
+pub fn snapshot_name(config: &Config, volume: &str, date: DateTime<Local>) -> String {
+    snapshot_template(config).format(&config.snapshot.snapshot_name_prefix.clone().unwrap(),
+                                     volume, None, date.with_timezone(&Utc))
+}
+
+/// Returns name of a manual snapshot with label according
+/// to config setting manual_snapshot_name_template.
+/// Manual snapshots are only removed according to
+/// config setting manual_snapshot_expire_days.
+pub fn manual_snapshot_name(config: &Config, volume: &str, label: &str, date: DateTime<Local>) -> String {
+    manual_snapshot_template(config).format(&config.snapshot.snapshot_name_prefix.clone().unwrap(),
+                                            volume, Some(label), date.with_timezone(&Utc))
+}
+
+/// Checks that label for manual snapshot is not empty
+/// and only contains letters, digits and -
+pub fn is_valid_label(label: &str) -> bool {
+    label.len() > 0 && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Returns all manual snapshots that should be deleted
+/// according to config setting manual_snapshot_expire_days,
+/// if setting is 0 manual snapshots are never deleted.
+/// all_snaps should be the unfiltered output from
+/// gluster snapshot list. Names that also match
+/// snapshot_name_template are never deleted.
+fn get_remove_manual(config: &Config, all_snaps: &String, host_type: &HostType) -> Vec<String> {
+    let mut rm_snaps: Vec<String> = Vec::new();
+    let expire_days = config.snapshot.manual_snapshot_expire_days.unwrap_or(0);
+    let template = manual_snapshot_template(config);
+    let prefix = config.snapshot.snapshot_name_prefix.clone().unwrap();
+    let volume = host_volume(config, host_type);
+
+    if expire_days == 0 {
+        return rm_snaps;
+    }
+
+    let expire = Utc::now() - chrono::Duration::days(expire_days as i64);
+    let normal = snapshot_template(config);
 
     for l in all_snaps.split("\n") {
-        if l.len() == (snap_prefix.len() + 15) && l.starts_with(&snap_prefix) {
-            let snap_parts: Vec<&str> = l.split("_").collect();
-
-            match snap_parts[snap_pre_parts_len-1].parse::<u32>() {
-                Ok(_) => {
-                    match snap_parts[snap_pre_parts_len].parse::<u32>() {
-                        Ok(_) => filtered_snaps.push(l.to_string()),
-                        Err(_) => (),
-                    }
-                },
-                Err(_) => (),
+        if let Some(n) = template.parse(l, &prefix, &volume) {
+            if n.time < expire && normal.parse(l, &prefix, &volume).is_none() {
+                rm_snaps.push(l.to_string());
             }
         }
     }
 
-    filtered_snaps.sort();
-    filtered_snaps
+    rm_snaps
+}
+
+/// Session name used in logs:
+/// master_volume user@slave_hostname::slave_volume
+pub fn session_name(config: &Config) -> String {
+    format!("{} {}@{}::{}", config.snapshot.master_volume.clone().unwrap_or(String::new()),
+            config.snapshot.slave_user.clone().unwrap_or(String::new()),
+            config.snapshot.slave_hostname.clone().unwrap_or(String::new()),
+            config.snapshot.slave_volume.clone().unwrap_or(String::new()))
+}
+
+/// Volume name on master or slave
+pub fn host_volume(config: &Config, host_type: &HostType) -> String {
+    if *host_type == HostType::Master {
+        config.snapshot.master_volume.clone().unwrap()
+    }
+    else {
+        config.snapshot.slave_volume.clone().unwrap()
+    }
+}
+
+/// Parses snapshot names with config setting
+/// snapshot_name_template and returns name and
+/// creation time in config setting timezone,
+/// sorted by creation time.
+/// Names not created by ggsnap, or that also
+/// match manual_snapshot_name_template, are left out.
+fn parse_snapshot_times(config: &Config, snaps: &Vec<String>, host_type: &HostType) -> Vec<(String, NaiveDateTime)> {
+    let template = snapshot_template(config);
+    let manual = manual_snapshot_template(config);
+    let prefix = config.snapshot.snapshot_name_prefix.clone().unwrap();
+    let volume = host_volume(config, host_type);
+    let tz = config_timezone(config);
+    let mut snap_times: Vec<(String, NaiveDateTime)> = Vec::new();
+
+    for s in snaps.iter() {
+        if manual.parse(s, &prefix, &volume).is_some() {
+            continue;
+        }
+
+        if let Some(n) = template.parse(s, &prefix, &volume) {
+            snap_times.push((s.clone(), tz.naive_from_utc(n.time)));
+        }
+    }
+
+    snap_times.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+    snap_times
+}
+
+/// Removes pinned snapshots from the list
+/// of snapshots to delete, every kept
+/// snapshot is added to log.
+fn remove_pinned(config: &Config, rm_snaps: Vec<String>, pins: &pin::Pins, host_type: &HostType, log: &mut Vec<Event>) -> Vec<String> {
+    let today = config_timezone(config).today();
+    let mut rm_unpinned: Vec<String> = Vec::new();
+
+    for s in rm_snaps {
+        if pins.is_pinned(&s, today) {
+            log.push(Event::info(*host_type, &format!("Keeping pinned snapshot: {}", s)));
+        }
+        else {
+            rm_unpinned.push(s);
+        }
+    }
+
+    rm_unpinned
+}
+
+/// Filters all snapshots done by ggsnap
+/// and returns a vector ordered by creation time.
+fn filter_gluster_snapshots(all_snaps: &String, config: &Config, host_type: &HostType) -> Vec<String> {
+    let snaps: Vec<String> = all_snaps.split("\n").map(|l| l.to_string()).collect();
+
+    parse_snapshot_times(config, &snaps, host_type).into_iter().map(|(s, _)| s).collect()
 }
 
 /// Returns all snapshots that should be deleted
@@ -464,30 +883,21 @@ fn filter_gluster_snapshots(all_snaps: &String, config: &Config, host_type: &Hos
 /// containing only snapshots that is made by ggsnap.
 fn get_remove_every_day(config: &Config, all_gluster_snaps: &Vec<String>, host_type: &HostType) -> Vec<String> {
     let mut rm_snaps: HashSet<String> = HashSet::new();
-    let mut dt = Local::now();
-    let mut _snap_pre: String = String::new();
+    let snap_times = parse_snapshot_times(config, all_gluster_snaps, host_type);
+    let mut day = config_timezone(config).today();
 
     for _i in 1..config.snapshot.number_days_every_day + 1 {
-        if *host_type == HostType::Master {
-            _snap_pre = format!("{}_{}_{}_", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                               config.snapshot.master_volume.clone().unwrap(), dt.format("%Y%m%d"));
-        }        
-        else {
-            _snap_pre = format!("{}_{}_{}_", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                               config.snapshot.slave_volume.clone().unwrap(), dt.format("%Y%m%d"));
-        }
-
-        let found = all_gluster_snaps.iter().filter(|&& ref s| s.starts_with(&_snap_pre));
-        let mut found_sort: Vec<&String> = found.collect();
-        found_sort.sort_by(|a, b| b.cmp(a));
+        let found = snap_times.iter().filter(|&&(_, ref t)| t.date() == day);
+        let mut found_sort: Vec<&(String, NaiveDateTime)> = found.collect();
+        found_sort.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
         let mut found_iter = found_sort.iter();
         found_iter.next();
         
-        for &s in found_iter {
+        for &&(ref s, _) in found_iter {
             rm_snaps.insert(s.clone());
         }
 
-        dt = dt + chrono::Duration::days(-1);
+        day = day + chrono::Duration::days(-1);
     }
 
     Vec::from_iter(rm_snaps)
@@ -499,10 +909,12 @@ fn get_remove_every_day(config: &Config, all_gluster_snaps: &Vec<String>, host_t
 /// containing only snapshots that is made by ggsnap.
 fn get_remove_weeks_with_one(config: &Config, all_gluster_snaps: &Vec<String>, host_type: &HostType) -> Vec<String> {
     let mut rm_snaps: HashSet<String> = HashSet::new();
-    let mut date = Local::today();
+    let snap_times = parse_snapshot_times(config, all_gluster_snaps, host_type);
+    let today = config_timezone(config).today();
+    let mut date = today;
     date = date + chrono::Duration::days(-((config.snapshot.number_days_every_day) as i64));
-    let mut _date1 = Local::today();
-    let mut _date2 = Local::today();
+    let mut _date1 = today;
+    let mut _date2 = today;
 
     if config.snapshot.number_weeks_with_one == 0 {
         return Vec::new();
@@ -512,34 +924,16 @@ fn get_remove_weeks_with_one(config: &Config, all_gluster_snaps: &Vec<String>, h
         _date1 = date + chrono::Duration::weeks(-(week_no as i64));
         _date2 = _date1 + chrono::Duration::days(-6);
 
-        let mut _snap_first: String = String::new();
-        let mut _snap_last: String = String::new();
-        
-        if *host_type == HostType::Master {
-            _snap_first = format!("{}_{}_{}_240000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                                config.snapshot.master_volume.clone().unwrap(), _date1.format("%Y%m%d"));
-        }
-        else {
-            _snap_first = format!("{}_{}_{}_240000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                                config.snapshot.slave_volume.clone().unwrap(), _date1.format("%Y%m%d"));
-        }
-    
-        if *host_type == HostType::Master {
-            _snap_last = format!("{}_{}_{}_000000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                                config.snapshot.master_volume.clone().unwrap(), _date2.format("%Y%m%d"));
-        }
-        else {
-            _snap_last = format!("{}_{}_{}_000000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                                config.snapshot.slave_volume.clone().unwrap(), _date2.format("%Y%m%d"));
-        }
-    
+        let week_first = _date1;
+        let week_last = _date2.and_hms(0, 0, 0);
     
-        let mut found: Vec<&String> = all_gluster_snaps.iter().filter(|&& ref s| *s < _snap_first && *s > _snap_last).collect();
+        let mut found: Vec<&(String, NaiveDateTime)> = snap_times.iter()
+            .filter(|&&(_, ref t)| t.date() <= week_first && *t > week_last).collect();
 
-        found.sort_by(|a, b| b.cmp(a));
+        found.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
         found.pop();
 
-        for l in found {
+        for &(ref l, _) in found {
             rm_snaps.insert(l.clone());
         }
     }
@@ -554,20 +948,20 @@ fn get_remove_weeks_with_one(config: &Config, all_gluster_snaps: &Vec<String>, h
 /// containing only snapshots that is made by ggsnap.
 fn get_remove_months_total(config: &Config, all_gluster_snaps: &Vec<String>, host_type: &HostType) -> Vec<String> {
     let mut rm_snaps: HashSet<String> = HashSet::new();
-    let mut last_month = Local::today();
-    let mut month_start = Local::today();
-    let mut _month_end = Local::today();
+    let snap_times = parse_snapshot_times(config, all_gluster_snaps, host_type);
+    let today = config_timezone(config).today();
+    let mut last_month = today;
+    let mut month_start = today;
+    let mut _month_end = today;
     
     month_start = month_start + chrono::Duration::days(-(config.snapshot.number_days_every_day as i64));
     month_start = month_start + chrono::Duration::weeks(-(config.snapshot.number_weeks_with_one as i64));
-    _month_end = Local.ymd(month_start.year(), month_start.month(), 1);
+    _month_end = NaiveDate::from_ymd(month_start.year(), month_start.month(), 1);
 
     let mut year = last_month.year() - ((config.snapshot.number_months_total/12) as i32);
     let mut month = last_month.month() - (config.snapshot.number_months_total%12);
     let mut day = last_month.day();
     let months_30 = vec!(4, 6, 9, 11);
-    let mut _snap_first: String = String::new();
-    let mut _snap_last: String = String::new();
 
     if month < 1 {
         year = year -1;
@@ -588,21 +982,13 @@ fn get_remove_months_total(config: &Config, all_gluster_snaps: &Vec<String>, hos
         }
     }
 
-    last_month = Local.ymd(year, month, day);
+    last_month = NaiveDate::from_ymd(year, month, day);
 
-    if *host_type == HostType::Master {
-        _snap_last = format!("{}_{}_{}_000000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                            config.snapshot.master_volume.clone().unwrap(), last_month.format("%Y%m%d"));
-    }
-    else {
-        _snap_last = format!("{}_{}_{}_000000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                            config.snapshot.slave_volume.clone().unwrap(), last_month.format("%Y%m%d"));
-    }
-
-    let old_snaps: Vec<&String> = all_gluster_snaps.iter().filter(|&& ref s| *s < _snap_last).collect();
+    let oldest_kept = last_month.and_hms(0, 0, 0);
+    let old_snaps: Vec<&(String, NaiveDateTime)> = snap_times.iter().filter(|&&(_, ref t)| *t < oldest_kept).collect();
 
-    for o in old_snaps {
-        rm_snaps.insert(o.clone().to_string());
+    for &(ref o, _) in old_snaps {
+        rm_snaps.insert(o.clone());
     }
 
     loop {
@@ -612,32 +998,17 @@ fn get_remove_months_total(config: &Config, all_gluster_snaps: &Vec<String>, hos
                break;
         }
 
-        if *host_type == HostType::Master {
-            _snap_first = format!("{}_{}_{}_240000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                                 config.snapshot.master_volume.clone().unwrap(), month_start.format("%Y%m%d"));
-        }
-        else {
-            _snap_first = format!("{}_{}_{}_240000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                                 config.snapshot.slave_volume.clone().unwrap(), month_start.format("%Y%m%d"));
-        }
-    
-        if *host_type == HostType::Master {
-            _snap_last = format!("{}_{}_{}_000000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                                config.snapshot.master_volume.clone().unwrap(), _month_end.format("%Y%m%d"));
-        }
-        else {
-            _snap_last = format!("{}_{}_{}_000000", config.snapshot.snapshot_name_prefix.clone().unwrap(), 
-                                config.snapshot.slave_volume.clone().unwrap(), _month_end.format("%Y%m%d"));
-        }
+        let month_first = month_start;
+        let month_last = _month_end.and_hms(0, 0, 0);
 
+        let mut all_in_month: Vec<&(String, NaiveDateTime)> = snap_times.iter()
+            .filter(|&&(_, ref t)| t.date() <= month_first && *t > month_last).collect();
 
-        let mut all_in_month: Vec<&String> = all_gluster_snaps.iter().filter(|&& ref s| *s < _snap_first && *s > _snap_last).collect();
-
-        all_in_month.sort_by(|a, b| b.cmp(a));
+        all_in_month.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
         all_in_month.pop();
 
-        for s in all_in_month {
-            rm_snaps.insert(s.clone().to_string());
+        for &(ref s, _) in all_in_month {
+            rm_snaps.insert(s.clone());
         }
 
         let mut y = _month_end.year();
@@ -652,7 +1023,7 @@ fn get_remove_months_total(config: &Config, all_gluster_snaps: &Vec<String>, hos
             m -= 1;
         }
 
-        _month_end = Local.ymd(y, m, d);
+        _month_end = NaiveDate::from_ymd(y, m, d);
 
         if _month_end.year() == last_month.year() &&
            _month_end.month() == last_month.month() {
@@ -671,7 +1042,7 @@ fn get_remove_months_total(config: &Config, all_gluster_snaps: &Vec<String>, hos
             d = 28;
         }
 
-        month_start = Local.ymd(_month_end.year(), _month_end.month(), d);
+        month_start = NaiveDate::from_ymd(_month_end.year(), _month_end.month(), d);
 
     }
 
@@ -815,6 +1186,80 @@ ggsnap_v_o_l_{}_061011",
     }
 
 
+    #[test]
+    fn get_manual() {
+        let d = Local::now();
+        let old = d + chrono::Duration::days(-31);
+        let new = d + chrono::Duration::days(-29);
+
+        let mut config = Config::default_config();
+        config.snapshot.slave_volume = Some(String::from("v_o_l"));
+
+        let s = format!(
+"ggsnap-pre-upgrade_v_o_l_{}
+ggsnap-pre-upgrade_v_o_l_{}
+ggsnap-pre_upgrade_v_o_l_{}
+ggsnap_v_o_l_{}
+ggsnap-label_vol_{}
+ggsnap-label_v_o_l_{}_x",
+            old.format("%Y%m%d_%H%M%S"), new.format("%Y%m%d_%H%M%S"), old.format("%Y%m%d_%H%M%S"),
+            old.format("%Y%m%d_%H%M%S"), old.format("%Y%m%d_%H%M%S"), old.format("%Y%m%d_%H%M%S"));
+
+        assert_eq!(get_remove_manual(&config, &s, &HostType::Slave),
+                   vec![format!("ggsnap-pre-upgrade_v_o_l_{}", old.format("%Y%m%d_%H%M%S"))]);
+
+        config.snapshot.manual_snapshot_expire_days = Some(0);
+        assert_eq!(get_remove_manual(&config, &s, &HostType::Slave).len(), 0);
+
+        assert_eq!(manual_snapshot_name(&config, "v_o_l", "pre-upgrade", old),
+                   format!("ggsnap-pre-upgrade_v_o_l_{}", old.format("%Y%m%d_%H%M%S")));
+        assert!(is_valid_label("pre-upgrade"));
+        assert!(!is_valid_label("pre_upgrade"));
+        assert!(!is_valid_label(""));
+    }
+
+    #[test]
+    fn name_templates() {
+        let mut config = Config::default_config();
+        config.snapshot.slave_volume = Some(String::from("vol"));
+
+        assert_eq!(check_name_templates(&config), Ok(()));
+        config.snapshot.manual_snapshot_name_template = Some(String::from("{prefix}_{volume}_{local:%Y%m%d_%H%M%S}{label}"));
+        assert_eq!(check_name_templates(&config), Ok(()));
+        config.snapshot.snapshot_name_template = Some(String::from("{prefix}_{volume}_{local:%Y%m%d_%H%M%S}"));
+        config.snapshot.manual_snapshot_name_template = Some(String::from("{prefix}_{volume}_{label}_{local:%Y%m%d_%H%M%S}"));
+        assert_eq!(check_name_templates(&config), Ok(()));
+
+        config.snapshot.snapshot_name_template = Some(String::from("{prefix}-x_{volume}_{local:%Y%m%d_%H%M%S}"));
+        config.snapshot.manual_snapshot_name_template = Some(String::from(naming::DEFAULT_MANUAL_TEMPLATE));
+        assert!(check_name_templates(&config).is_err());
+
+        // Names matching both templates are never removed
+        config.snapshot.manual_snapshot_expire_days = Some(1);
+        let name = String::from("ggsnap-x_vol_20180216_113000");
+        assert_eq!(parse_snapshot_times(&config, &vec![name.clone()], &HostType::Slave), Vec::new());
+        assert_eq!(get_remove_manual(&config, &name, &HostType::Slave), Vec::<String>::new());
+    }
+
+    #[test]
+    fn get_deletable() {
+        let mut config = Config::default_config();
+        config.snapshot.slave_volume = Some(String::from("vol"));
+        config.general.pin_file = Some(String::from("/nonexistent/ggsnap_pins.toml"));
+
+        let s = String::from("ggsnap_vol_20180216_114403
+ggsnap-label_vol_20180210_114403
+ggsnap_vol_20180214_095616
+other_snapshot
+ggsnap_vol_20180215_095616");
+
+        assert_eq!(deletable_snapshots(&config, &s, &HostType::Slave, 0).unwrap(),
+                   vec!["ggsnap_vol_20180214_095616", "ggsnap_vol_20180215_095616", "ggsnap_vol_20180216_114403"]);
+        assert_eq!(deletable_snapshots(&config, &s, &HostType::Slave, 2).unwrap(),
+                   vec!["ggsnap_vol_20180214_095616"]);
+        assert_eq!(deletable_snapshots(&config, &s, &HostType::Slave, 5).unwrap().len(), 0);
+    }
+
     #[test]
     fn get_weeks_with_one() {
         let mut dates: Vec<String> = Vec::new();
@@ -0,0 +1,217 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::process::Command;
+use event::{Event, Level};
+use {deletable_snapshots, delete_snapshot, host_volume, list_volume_snapshots, Config, HostType};
+
+/// Snapshot limits for a volume
+/// from gluster snapshot config
+#[derive(Debug, PartialEq)]
+pub struct SnapLimits {
+    pub hard_limit: u32,
+    pub soft_limit: Option<u32>,
+}
+
+/// Parses output from gluster snapshot config VOLUME,
+/// effective limits for the volume are used if present
+/// otherwise the system limits.
+pub fn parse_snapshot_config(output: &str) -> Option<SnapLimits> {
+    let mut hard_limit: Option<u32> = None;
+    let mut soft_limit: Option<u32> = None;
+    let mut effective_hard: Option<u32> = None;
+
+    for l in output.lines() {
+        let mut kv = l.splitn(2, ':');
+        let key = kv.next().unwrap_or("").trim();
+        let value = kv.next().unwrap_or("").trim();
+        let number = value.split(|c: char| !c.is_digit(10)).next().unwrap_or("").parse::<u32>().ok();
+
+        match key {
+            "snap-max-hard-limit" => {
+                if number.is_some() {
+                    hard_limit = number;
+                }
+            },
+            "Effective snap-max-hard-limit" => effective_hard = number,
+            "Effective snap-max-soft-limit" => soft_limit = number,
+            _ => (),
+        }
+    }
+
+    match effective_hard.or(hard_limit) {
+        Some(h) => Some(SnapLimits { hard_limit: h, soft_limit: soft_limit }),
+        None => None,
+    }
+}
+
+/// Reads snapshot limits for volume on
+/// master or slave with gluster snapshot config
+pub fn get_snapshot_limits(config: &Config, host_type: &HostType) -> Result<SnapLimits, String> {
+    let volume = host_volume(config, host_type);
+    let cmd_out = Command::new(&config.general.gluster_bin)
+                          .arg("snapshot")
+                          .arg("config")
+                          .arg(&volume)
+                          .output();
+
+    match cmd_out {
+        Ok(o) => {
+            let o_str = format!("{}{}", String::from_utf8_lossy(&o.stdout), String::from_utf8_lossy(&o.stderr));
+            if !o.status.success() {
+                return Err(o_str);
+            }
+
+            match parse_snapshot_config(&o_str) {
+                Some(l) => Ok(l),
+                None => Err(format!("Can not find snap-max-hard-limit in output:\n{}", o_str)),
+            }
+        },
+        Err(e) => Err(format!("Error running command: gluster snapshot config {}; {}", volume, e)),
+    }
+}
+
+/// Highest number of snapshots kept by the
+/// retention settings in [snapshot], manual
+/// and pinned snapshots are not included.
+pub fn retention_snapshot_count(config: &Config) -> u32 {
+    config.snapshot.number_days_every_day +
+        config.snapshot.number_weeks_with_one +
+        config.snapshot.number_months_total
+}
+
+/// Checks snapshot limits for volume before a new
+/// snapshot is created. Warns if retention settings
+/// keeps more snapshots than allowed by gluster.
+/// If the volume is at snap-max-hard-limit the oldest
+/// unpinned ggsnap snapshots are deleted when config
+/// setting delete_oldest_at_limit is true, otherwise
+/// an error is returned as the snapshot would fail.
+/// If limits can not be read a warning is logged
+/// and the snapshot is tried anyway.
+pub fn check_snapshot_limit(config: &Config, host_type: &HostType) -> Result<Vec<Event>, Vec<Event>> {
+    let volume = host_volume(config, host_type);
+    let mut log = vec![Event::info(*host_type, &format!("Checking snapshot limits on volume: {}", volume))];
+
+    let limits = match get_snapshot_limits(config, host_type) {
+        Ok(l) => l,
+        Err(e) => {
+            log.push(Event::warning(*host_type, "Warning: Can not read snapshot limits, continuing"));
+            log.extend(Event::lines(Level::Warning, *host_type, &e));
+            return Ok(log);
+        },
+    };
+
+    let retention = retention_snapshot_count(config);
+    if retention >= limits.hard_limit {
+        log.push(Event::warning(*host_type, &format!("Warning: Retention settings keep up to {} snapshots, snap-max-hard-limit is {}",
+                                                     retention, limits.hard_limit)));
+    }
+    else if let Some(soft) = limits.soft_limit {
+        if retention >= soft {
+            log.push(Event::warning(*host_type, &format!("Warning: Retention settings keep up to {} snapshots, snap-max-soft-limit is {}",
+                                                         retention, soft)));
+        }
+    }
+
+    let snap_output = match list_volume_snapshots(config, host_type) {
+        Ok(s) => s,
+        Err(e) => {
+            log.extend(Event::lines(Level::Error, *host_type, &e));
+            return Err(log);
+        },
+    };
+
+    let snap_count = snap_output.lines().count() as u32;
+
+    if snap_count < limits.hard_limit {
+        return Ok(log);
+    }
+
+    if !config.snapshot.delete_oldest_at_limit.unwrap_or(false) {
+        log.push(Event::error(*host_type, &format!("Error: Volume has {} snapshots and snap-max-hard-limit is {}, \
+                                                    remove snapshots or set delete_oldest_at_limit",
+                                                   snap_count, limits.hard_limit)));
+        return Err(log);
+    }
+
+    let mut to_delete = snap_count - limits.hard_limit + 1;
+    log.push(Event::info(*host_type, &format!("Volume is at snap-max-hard-limit {}, deleting {} oldest snapshots",
+                                              limits.hard_limit, to_delete)));
+
+    let deletable = match deletable_snapshots(config, &snap_output, host_type, 0) {
+        Ok(d) => d,
+        Err(e) => {
+            log.extend(Event::lines(Level::Error, *host_type, &e));
+            return Err(log);
+        },
+    };
+
+    for s in deletable.iter().take(to_delete as usize) {
+        match delete_snapshot(config, s, host_type) {
+            Ok(l) => log.extend(l),
+            Err(l) => {
+                log.extend(l);
+                return Err(log);
+            },
+        }
+
+        to_delete -= 1;
+    }
+
+    if to_delete > 0 {
+        log.push(Event::error(*host_type, "Error: No more unpinned snapshots created by ggsnap to delete"));
+        return Err(log);
+    }
+
+    Ok(log)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_config() {
+        let output = "Snapshot System Configuration:
+snap-max-hard-limit : 256
+snap-max-soft-limit : 90%
+auto-delete : disable
+activate-on-create : disable
+
+Snapshot Volume Configuration:
+
+Volume : vol
+snap-max-hard-limit : 100
+Effective snap-max-hard-limit : 100
+Effective snap-max-soft-limit : 90 (90%)
+";
+        assert_eq!(parse_snapshot_config(output), Some(SnapLimits { hard_limit: 100, soft_limit: Some(90) }));
+
+        let output = "Snapshot System Configuration:
+snap-max-hard-limit : 256
+snap-max-soft-limit : 90%
+";
+        assert_eq!(parse_snapshot_config(output), Some(SnapLimits { hard_limit: 256, soft_limit: None }));
+        assert_eq!(parse_snapshot_config("Snapshot command failed"), None);
+    }
+}
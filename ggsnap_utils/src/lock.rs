@@ -0,0 +1,187 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::fs::{ File, OpenOptions };
+use std::io::prelude::*;
+use std::os::unix::io::AsRawFd;
+use std::path::{ Path, PathBuf };
+use std::thread;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+use libc;
+use event::{Event, Level};
+use {systemd, Config, HostType};
+
+/// Default seconds to wait for lock with lock_policy wait
+pub static DEFAULT_LOCK_WAIT: u64 = 3600;
+
+/// Default seconds before a held lock is reported as stale
+pub static DEFAULT_LOCK_STALE: u64 = 86400;
+
+/// Type to describe why lock was not taken,
+/// Skip is returned with lock_policy skip.
+#[derive(PartialEq, Debug)]
+pub enum LockErr {
+    Skip,
+    Fail,
+}
+
+/// Exclusive run lock for a session, the
+/// lock is released when RunLock is dropped.
+#[derive(Debug)]
+pub struct RunLock {
+    _file: File,
+    pub path: PathBuf,
+}
+
+/// Lock file name for session, characters
+/// other than letters, digits, - and _ are
+/// replaced with _
+pub fn lock_file_name(session: &str) -> String {
+    let name: String = session.chars()
+                              .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+                              .collect();
+    format!("ggsnap_{}.lock", name)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Tries to take lock without blocking, returns
+/// None if lock is held by another process.
+fn try_lock(path: &Path) -> Result<Option<File>, String> {
+    let mut file = match OpenOptions::new().read(true).write(true).create(true).open(path) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("Error opening lock file: {}\n{}", path.display(), e)),
+    };
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return Ok(None);
+    }
+
+    let _ = file.set_len(0);
+    let _ = write!(file, "{} {}\n", std::process::id(), now_secs());
+    Ok(Some(file))
+}
+
+/// Reads pid and time the lock was taken from lock file
+fn lock_holder(path: &Path) -> Option<(u32, u64)> {
+    let mut content = String::new();
+    if let Ok(mut f) = File::open(path) {
+        let _ = f.read_to_string(&mut content);
+    }
+
+    let mut fields = content.split_whitespace();
+    match (fields.next().map(|p| p.parse::<u32>()), fields.next().map(|t| t.parse::<u64>())) {
+        (Some(Ok(p)), Some(Ok(t))) => Some((p, t)),
+        _ => None,
+    }
+}
+
+/// Takes exclusive lock for session in config setting lock_dir.
+/// If another run holds the lock config setting lock_policy decides:
+/// wait - waits at most lock_wait_timeout seconds, then fails
+/// skip - returns LockErr::Skip
+/// fail - returns LockErr::Fail
+/// The lock file is never removed, a lock held by a process
+/// that no longer exists is released by the kernel and the
+/// file is reused. A lock held longer than lock_stale_seconds
+/// is reported in the returned events but not removed.
+pub fn acquire(config: &Config, session: &str, host_type: &HostType) -> Result<(RunLock, Vec<Event>), (LockErr, Vec<Event>)> {
+    let dir = config.general.lock_dir.clone().unwrap_or(String::from("/var/run"));
+    let path = Path::new(&dir).join(lock_file_name(session));
+    let policy = config.general.lock_policy.clone().unwrap_or(String::from("wait"));
+    let wait = config.general.lock_wait_timeout.unwrap_or(DEFAULT_LOCK_WAIT);
+    let stale = config.general.lock_stale_seconds.unwrap_or(DEFAULT_LOCK_STALE);
+    let start = now_secs();
+    let mut log: Vec<Event> = Vec::new();
+    let mut reported = false;
+
+    loop {
+        match try_lock(&path) {
+            Ok(Some(f)) => return Ok((RunLock { _file: f, path: path.clone() }, log)),
+            Ok(None) => (),
+            Err(e) => {
+                log.extend(Event::lines(Level::Error, *host_type, &e));
+                return Err((LockErr::Fail, log));
+            },
+        }
+
+        // Pid in file is from an earlier run if the
+        // holder has not yet written its own pid
+        let holder = match lock_holder(&path) {
+            Some((pid, _)) if !Path::new(&format!("/proc/{}", pid)).exists() => None,
+            h => h,
+        };
+
+        if !reported {
+            reported = true;
+            log.push(match holder {
+                Some((pid, t)) if now_secs() >= t + stale =>
+                    Event::warning(*host_type, &format!("Warning: Lock {} held by pid {} for {} seconds, it may be hung",
+                                                        path.display(), pid, now_secs() - t)),
+                Some((pid, _)) => Event::info(*host_type, &format!("Lock {} held by pid {}", path.display(), pid)),
+                None => Event::info(*host_type, &format!("Lock {} held by another process", path.display())),
+            });
+        }
+
+        if policy == "skip" {
+            log.push(Event::info(*host_type, &format!("Another run of session {} is in progress, skipping", session)));
+            return Err((LockErr::Skip, log));
+        }
+        else if policy == "fail" || now_secs() >= start + wait {
+            log.push(Event::error(*host_type, &format!("Error: Another run of session {} is in progress", session)));
+            return Err((LockErr::Fail, log));
+        }
+
+        systemd::watchdog();
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn run_lock() {
+        let mut config = Config::default_config();
+        config.general.lock_dir = Some(std::env::temp_dir().to_string_lossy().to_string());
+        config.general.lock_policy = Some(String::from("skip"));
+        let session = format!("test-{}", std::process::id());
+
+        assert_eq!(lock_file_name("vol_root@slave::svol"), "ggsnap_vol_root_slave__svol.lock");
+
+        let (lock, _) = acquire(&config, &session, &HostType::Master).unwrap();
+        assert_eq!(acquire(&config, &session, &HostType::Master).unwrap_err().0, LockErr::Skip);
+        config.general.lock_policy = Some(String::from("fail"));
+        assert_eq!(acquire(&config, &session, &HostType::Master).unwrap_err().0, LockErr::Fail);
+
+        drop(lock);
+        let (lock, _) = acquire(&config, &session, &HostType::Master).unwrap();
+        assert_eq!(lock_holder(&lock.path).map(|h| h.0), Some(std::process::id()));
+        let path = lock.path.clone();
+        drop(lock);
+        let _ = fs::remove_file(&path);
+    }
+}
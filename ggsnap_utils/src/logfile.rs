@@ -0,0 +1,227 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process::Command;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use Config;
+
+/// Rotation settings for log files
+#[derive(Debug, PartialEq)]
+pub struct Rotation {
+    pub max_size: u64,
+    pub max_age_days: u64,
+    pub keep_files: u32,
+    pub compress: bool,
+}
+
+impl Rotation {
+    pub fn from_config(config: &Config) -> Rotation {
+        Rotation {
+            max_size: config.general.log_rotate_size_mb.unwrap_or(0) * 1024 * 1024,
+            max_age_days: config.general.log_rotate_days.unwrap_or(0),
+            keep_files: config.general.log_keep_files.unwrap_or(DEFAULT_KEEP_FILES),
+            compress: config.general.log_compress.unwrap_or(false),
+        }
+    }
+
+    /// True if a file of size and age in seconds
+    /// should be rotated, 0 disables a limit.
+    pub fn needs_rotation(&self, size: u64, age_seconds: u64) -> bool {
+        (self.max_size > 0 && size >= self.max_size) ||
+        (self.max_age_days > 0 && age_seconds >= self.max_age_days * 24 * 3600)
+    }
+}
+
+pub const DEFAULT_KEEP_FILES: u32 = 5;
+
+/// Name of rotated file number n, ex: ggsnap.log.2.gz
+fn rotated_name(path: &Path, n: u32, compress: bool) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+    if compress {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+/// Stamp file next to log file with the time in seconds
+/// since epoch when log file was started, ex: .ggsnap.log.rotated
+/// Appends to the log file do not change it.
+fn stamp_name(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or(String::new());
+    path.with_file_name(format!(".{}.rotated", name))
+}
+
+fn now_secs() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+fn write_stamp(path: &Path) -> Result<(), String> {
+    let stamp = stamp_name(path);
+    match fs::write(&stamp, format!("{}\n", now_secs())) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error writing log rotation stamp: {}\nError: {}", stamp.display(), e)),
+    }
+}
+
+/// Age of file in seconds from time in stamp file,
+/// a file without stamp is stamped now and has age 0.
+fn file_age(path: &Path) -> Result<u64, String> {
+    let started = match fs::read_to_string(stamp_name(path)) {
+        Ok(s) => s.trim().parse::<u64>().ok(),
+        Err(_) => None,
+    };
+
+    match started {
+        Some(t) => Ok(now_secs().saturating_sub(t)),
+        None => write_stamp(path).map(|_| 0),
+    }
+}
+
+/// Rotates file if it is larger or older than allowed,
+/// file is renamed to file.1, file.1 to file.2 and so on,
+/// files above keep_files are removed. Rotated files are
+/// compressed with gzip if compress is set. Age is from
+/// the stamp file that is written when file is started.
+/// Returns a message if file was rotated. Without size
+/// and age limits nothing is done and no stamp is written.
+pub fn rotate_if_needed(path: &Path, rotation: &Rotation) -> Result<Option<String>, String> {
+    if rotation.max_size == 0 && rotation.max_age_days == 0 {
+        return Ok(None)
+    }
+
+    let meta = match fs::metadata(path) {
+        Ok(m) => m,
+        Err(_) => return write_stamp(path).map(|_| None),
+    };
+
+    let age = match file_age(path) {
+        Ok(a) => a,
+        Err(e) => return Err(e),
+    };
+
+    if !rotation.needs_rotation(meta.len(), age) {
+        return Ok(None)
+    }
+
+    for c in &[true, false] {
+        let oldest = rotated_name(path, rotation.keep_files, *c);
+        if rotation.keep_files > 0 && oldest.exists() {
+            if let Err(e) = fs::remove_file(&oldest) {
+                return Err(format!("Error removing rotated log file: {}\nError: {}", oldest.display(), e));
+            }
+        }
+    }
+
+    for n in (1..rotation.keep_files).rev() {
+        for c in &[true, false] {
+            let from = rotated_name(path, n, *c);
+            if from.exists() {
+                if let Err(e) = fs::rename(&from, rotated_name(path, n + 1, *c)) {
+                    return Err(format!("Error rotating log file: {}\nError: {}", from.display(), e));
+                }
+            }
+        }
+    }
+
+    if rotation.keep_files == 0 {
+        if let Err(e) = fs::remove_file(path) {
+            return Err(format!("Error removing log file: {}\nError: {}", path.display(), e));
+        }
+
+        return write_stamp(path).map(|_| Some(format!("Log file removed: {}", path.display())));
+    }
+
+    let first = rotated_name(path, 1, false);
+    if let Err(e) = fs::rename(path, &first) {
+        return Err(format!("Error rotating log file: {}\nError: {}", path.display(), e));
+    }
+
+    if let Err(e) = write_stamp(path) {
+        return Err(e);
+    }
+
+    if rotation.compress {
+        match Command::new("gzip").arg("-f").arg(&first).output() {
+            Ok(ref o) if o.status.success() => (),
+            Ok(o) => return Err(format!("Error compressing log file: {}\nError: {}", first.display(),
+                                        String::from_utf8_lossy(&o.stderr))),
+            Err(e) => return Err(format!("Error running gzip on log file: {}\nError: {}", first.display(), e)),
+        }
+    }
+
+    Ok(Some(format!("Log file rotated: {}", path.display())))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn rotate_log() {
+        let rotation = Rotation { max_size: 10, max_age_days: 0, keep_files: 2, compress: false };
+        assert!(!rotation.needs_rotation(9, 1000000));
+        assert!(rotation.needs_rotation(10, 0));
+        assert!(Rotation { max_size: 0, max_age_days: 1, keep_files: 2, compress: false }.needs_rotation(0, 86400));
+
+        let dir = env::temp_dir().join(format!("ggsnap_rotate_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("ggsnap.log");
+
+        for i in 0..4 {
+            let mut f = File::create(&log).unwrap();
+            write!(f, "run number {}", i).unwrap();
+            assert!(rotate_if_needed(&log, &rotation).unwrap().is_some());
+        }
+
+        assert!(!log.exists());
+        assert_eq!(fs::read_to_string(dir.join("ggsnap.log.1")).unwrap(), "run number 3");
+        assert_eq!(fs::read_to_string(dir.join("ggsnap.log.2")).unwrap(), "run number 2");
+        assert!(!dir.join("ggsnap.log.3").exists());
+
+        File::create(&log).unwrap();
+        assert_eq!(rotate_if_needed(&log, &rotation), Ok(None));
+
+        let _ = fs::remove_file(stamp_name(&log));
+        let disabled = Rotation { max_size: 0, max_age_days: 0, keep_files: 2, compress: false };
+        assert_eq!(rotate_if_needed(&log, &disabled), Ok(None));
+        assert!(!stamp_name(&log).exists());
+
+        // Age is from stamp, appending does not change it
+        let rotation = Rotation { max_size: 0, max_age_days: 1, keep_files: 2, compress: false };
+        assert_eq!(rotate_if_needed(&log, &rotation), Ok(None));
+        fs::write(stamp_name(&log), format!("{}\n", now_secs() - 86400)).unwrap();
+        File::create(&log).unwrap().write_all(b"appended").unwrap();
+        assert!(rotate_if_needed(&log, &rotation).unwrap().is_some());
+        assert_eq!(file_age(&log), Ok(0));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
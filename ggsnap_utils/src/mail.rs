@@ -0,0 +1,544 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+
+use std::fs::File;
+use std::io::Read;
+use std::process;
+use chrono::prelude::*;
+use notify::DigestRun;
+use report::RunReport;
+use MailFromMaster;
+
+/// Default template of plain text part of status mail
+pub static DEFAULT_TEXT_TEMPLATE: &'static str = "ggsnap run: {status}
+
+Session:   {session}
+Snapshot:  {snapshot}
+Started:   {date}
+Duration:  {duration} s
+
+Steps:
+{steps}
+
+Deleted snapshots:
+{deleted}
+
+Errors:
+{errors}
+{attachment}";
+
+/// Default template of html part of status mail
+pub static DEFAULT_HTML_TEMPLATE: &'static str = "<html>
+<body>
+<h2>ggsnap run: {status}</h2>
+<table>
+<tr><td>Session</td><td>{session}</td></tr>
+<tr><td>Snapshot</td><td>{snapshot}</td></tr>
+<tr><td>Started</td><td>{date}</td></tr>
+<tr><td>Duration</td><td>{duration} s</td></tr>
+</table>
+<h3>Steps</h3>
+{steps}
+<h3>Deleted snapshots</h3>
+{deleted}
+<h3>Errors</h3>
+{errors}
+{attachment}
+</body>
+</html>
+";
+
+/// Name of attached log file
+pub static LOG_FILE_NAME: &'static str = "ggsnap.log";
+
+/// Sentence about the attached log, empty if no log is attached
+static ATTACHMENT_TEXT: &'static str = "Mail status and full log is in attached file {log_file}.";
+
+/// Default SMTP timeout in seconds
+pub static DEFAULT_SMTP_TIMEOUT: u64 = 60;
+
+/// TLS of SMTP connection, from config setting smtp_tls
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsMode {
+    None,
+    Starttls,
+    Implicit,
+}
+
+impl TlsMode {
+    pub fn from_name(name: &str) -> Option<TlsMode> {
+        match name {
+            "none" => Some(TlsMode::None),
+            "starttls" => Some(TlsMode::Starttls),
+            "implicit" => Some(TlsMode::Implicit),
+            _ => None,
+        }
+    }
+
+    /// Standard port of mode
+    pub fn default_port(&self) -> u16 {
+        match *self {
+            TlsMode::None => 25,
+            TlsMode::Starttls => 587,
+            TlsMode::Implicit => 465,
+        }
+    }
+}
+
+/// Authentication to SMTP server
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmtpAuth {
+    pub username: String,
+    pub password: String,
+    pub mechanism: String,
+}
+
+/// SMTP server settings with defaults filled in
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: TlsMode,
+    pub tls_domain: String,
+    pub ca_file: Option<String>,
+    pub timeout: u64,
+    pub auth: Option<SmtpAuth>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    match *value {
+        Some(ref v) if v.len() > 0 => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// SMTP settings from [mail_from_master]. Without
+/// smtp_tls, STARTTLS is used if tls_domain is set
+/// and server is tls_domain, otherwise localhost
+/// without TLS. Port defaults to the standard port of
+/// TLS mode and certificates are validated against
+/// tls_domain or host. Authentication is only done
+/// if username is set.
+pub fn smtp_settings(mail_conf: &MailFromMaster) -> SmtpSettings {
+    let tls_domain = non_empty(&mail_conf.tls_domain);
+    let tls = match mail_conf.smtp_tls.as_ref().and_then(|t| TlsMode::from_name(t)) {
+        Some(t) => t,
+        None if tls_domain.is_some() => TlsMode::Starttls,
+        None => TlsMode::None,
+    };
+    let host = match non_empty(&mail_conf.smtp_host) {
+        Some(h) => h,
+        None if tls != TlsMode::None && tls_domain.is_some() => tls_domain.clone().unwrap(),
+        None => String::from("localhost"),
+    };
+
+    SmtpSettings {
+        port: mail_conf.smtp_port.unwrap_or(tls.default_port()),
+        tls: tls,
+        tls_domain: tls_domain.unwrap_or(host.clone()),
+        host: host,
+        ca_file: non_empty(&mail_conf.smtp_ca_file),
+        timeout: mail_conf.smtp_timeout.unwrap_or(DEFAULT_SMTP_TIMEOUT),
+        auth: if mail_conf.username.len() > 0 {
+            Some(SmtpAuth {
+                username: mail_conf.username.clone(),
+                password: mail_conf.password.clone(),
+                mechanism: if mail_conf.authentication_mechanism.len() > 0 {
+                    mail_conf.authentication_mechanism.clone()
+                }
+                else {
+                    String::from("plain")
+                },
+            })
+        }
+        else {
+            None
+        },
+    }
+}
+
+/// A composed status mail
+#[derive(Debug, Clone, PartialEq)]
+pub struct MailMessage {
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub date: DateTime<Local>,
+    pub message_id: String,
+    pub text: String,
+    pub html: Option<String>,
+    pub attachment: Option<(String, String)>,
+}
+
+static BASE64_CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Base64 encodes data in lines of 76 characters
+fn base64(data: &[u8]) -> String {
+    let mut encoded: Vec<u8> = Vec::new();
+
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_CHARS[((n >> (18 - 6 * i)) & 63) as usize]);
+            }
+            else {
+                encoded.push(b'=');
+            }
+        }
+    }
+
+    let lines: Vec<String> = encoded.chunks(76).map(|l| String::from_utf8_lossy(l).to_string()).collect();
+    lines.join("\r\n")
+}
+
+/// Header value, encoded according to RFC 2047
+/// if not plain ascii
+fn header_value(value: &str) -> String {
+    if value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+        value.to_string()
+    }
+    else {
+        format!("=?UTF-8?B?{}?=", base64(value.as_bytes()).replace("\r\n", ""))
+    }
+}
+
+/// Creates a unique Message-ID in domain
+/// of sender address
+pub fn message_id(from: &str, time: DateTime<Utc>) -> String {
+    let domain = match from.rfind('@') {
+        Some(i) if i + 1 < from.len() => from[i + 1..].trim_end_matches('>').to_string(),
+        _ => String::from("localhost"),
+    };
+
+    format!("<{}.{}.{}@{}>", time.format("%Y%m%d%H%M%S"), time.timestamp_subsec_nanos(),
+            process::id(), domain)
+}
+
+impl MailMessage {
+    /// Message with headers and MIME parts, lines
+    /// end with CRLF. Text and html are alternatives
+    /// and log is attached in a multipart/mixed.
+    pub fn format(&self) -> String {
+        let boundary = format!("ggsnap-{}", self.message_id.trim_matches(|c| c == '<' || c == '>')
+                                                           .replace('@', "."));
+        let mut msg = format!("From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: {}\r\nMIME-Version: 1.0\r\n",
+                              self.from, self.to.join(", "), header_value(&self.subject),
+                              self.date.to_rfc2822(), self.message_id);
+
+        let text_part = format!("Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+                                base64(self.text.as_bytes()));
+
+        let body = match self.html {
+            Some(ref html) => format!("Content-Type: multipart/alternative; boundary=\"{0}-alt\"\r\n\r\n\
+                                       --{0}-alt\r\n{1}\r\n\
+                                       --{0}-alt\r\n\
+                                       Content-Type: text/html; charset=utf-8\r\n\
+                                       Content-Transfer-Encoding: base64\r\n\r\n{2}\r\n\r\n\
+                                       --{0}-alt--\r\n",
+                                      boundary, text_part, base64(html.as_bytes())),
+            None => text_part,
+        };
+
+        match self.attachment {
+            Some((ref name, ref content)) => {
+                msg.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{0}\"\r\n\r\n\
+                                       --{0}\r\n{1}\r\n\
+                                       --{0}\r\n\
+                                       Content-Type: text/plain; charset=utf-8; name=\"{2}\"\r\n\
+                                       Content-Disposition: attachment; filename=\"{2}\"\r\n\
+                                       Content-Transfer-Encoding: base64\r\n\r\n{3}\r\n\r\n\
+                                       --{0}--\r\n",
+                                      boundary, body, name, base64(content.as_bytes())));
+            },
+            None => msg.push_str(&body),
+        }
+
+        msg
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Items as lines in text or list in html
+fn list(items: &[String], html: bool) -> String {
+    if items.is_empty() {
+        String::from("None")
+    }
+    else if html {
+        format!("<ul>\n{}</ul>", items.iter().map(|i| format!("<li>{}</li>\n", escape_html(i)))
+                                         .collect::<Vec<String>>().concat())
+    }
+    else {
+        items.iter().map(|i| format!("  {}", i)).collect::<Vec<String>>().join("\n")
+    }
+}
+
+/// Fills in template with values from report.
+/// Fields: {status}, {session}, {snapshot}, {date},
+/// {duration}, {steps}, {deleted}, {errors}, {log_file}
+/// and {attachment}, which is empty if log is not attached.
+/// Values are escaped in html.
+pub fn render(template: &str, report: &RunReport, html: bool, attached: bool) -> String {
+    let value = |v: &str| if html { escape_html(v) } else { v.to_string() };
+    let attachment = match (attached, html) {
+        (false, _) => String::new(),
+        (true, false) => format!("\n{}\n", ATTACHMENT_TEXT),
+        (true, true) => format!("<p>{}</p>", ATTACHMENT_TEXT),
+    };
+    let date = run_date(&report.timestamp);
+    let steps: Vec<String> = report.steps.iter()
+        .map(|s| format!("{}: {} ({:.1} s)", s.name, s.status, s.duration_seconds)).collect();
+    let deleted: Vec<String> = report.deleted.iter()
+        .map(|d| format!("{}: {}", ::host_label(&d.host), d.snapshot)).collect();
+
+    template.replace("{status}", if report.success { "OK" } else { "Error" })
+            .replace("{session}", &value(&report.session))
+            .replace("{snapshot}", &value(&report.snapshot.clone().unwrap_or(String::from("None"))))
+            .replace("{date}", &date)
+            .replace("{duration}", &format!("{:.1}", report.duration_seconds))
+            .replace("{steps}", &list(&steps, html))
+            .replace("{deleted}", &list(&deleted, html))
+            .replace("{errors}", &list(&report.errors, html))
+            .replace("{attachment}", &attachment)
+            .replace("{log_file}", LOG_FILE_NAME)
+}
+
+/// Template from file in config, or default
+fn template(file: &Option<String>, default: &str) -> Result<String, String> {
+    match *file {
+        Some(ref f) if f.len() > 0 => {
+            let mut content = String::new();
+            match File::open(f).and_then(|mut file| file.read_to_string(&mut content)) {
+                Ok(_) => Ok(content),
+                Err(e) => Err(format!("Error reading mail template: {}; {}", f, e)),
+            }
+        },
+        _ => Ok(default.to_string()),
+    }
+}
+
+/// Composes status mail of run according to
+/// config section [mail_from_master], full log
+/// is attached if attach_log is true.
+pub fn compose(mail_conf: &MailFromMaster, report: &RunReport, log: &str, date: DateTime<Local>) -> Result<MailMessage, String> {
+    let attach_log = mail_conf.attach_log.unwrap_or(true);
+    let text = match template(&mail_conf.text_template, DEFAULT_TEXT_TEMPLATE) {
+        Ok(t) => render(&t, report, false, attach_log),
+        Err(e) => return Err(e),
+    };
+
+    let html = if mail_conf.format.clone().unwrap_or(String::from("html")) == "html" {
+        match template(&mail_conf.html_template, DEFAULT_HTML_TEMPLATE) {
+            Ok(t) => Some(render(&t, report, true, attach_log)),
+            Err(e) => return Err(e),
+        }
+    }
+    else {
+        None
+    };
+
+    let attachment = if attach_log {
+        Some((String::from(LOG_FILE_NAME), log.to_string()))
+    }
+    else {
+        None
+    };
+
+    let status = if report.success { "OK" } else { "Error" };
+
+    Ok(MailMessage {
+        from: mail_conf.from_sender_address.clone(),
+        to: mail_conf.to_addresses.clone(),
+        subject: format!("{}: {}", mail_conf.subject, status),
+        date: date,
+        message_id: message_id(&mail_conf.from_sender_address, date.with_timezone(&Utc)),
+        text: text,
+        html: html,
+        attachment: attachment,
+    })
+}
+
+/// Local time of run from RFC 3339 timestamp
+pub(crate) fn run_date(timestamp: &str) -> String {
+    match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(d) => d.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string(),
+        Err(_) => timestamp.to_string(),
+    }
+}
+
+/// Composes digest mail with one line per run
+/// in text and a table in html, errors of failed
+/// runs are listed below.
+pub fn compose_digest(mail_conf: &MailFromMaster, runs: &[DigestRun], date: DateTime<Local>) -> MailMessage {
+    let failed = runs.iter().filter(|r| !r.success).count();
+    let mut text = format!("ggsnap digest: {} runs, {} failed\n\n", runs.len(), failed);
+    let mut rows = String::new();
+
+    for r in runs.iter() {
+        let status = if r.success { "OK" } else { "Error" };
+        let snapshot = r.snapshot.clone().unwrap_or(String::from("None"));
+
+        text.push_str(&format!("{}  {:<5}  {:>7.1} s  {}  {}\n", run_date(&r.timestamp), status,
+                               r.duration_seconds, r.session, snapshot));
+        for e in r.errors.iter() {
+            text.push_str(&format!("    {}\n", e));
+        }
+
+        rows.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{:.1} s</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                               run_date(&r.timestamp), status, r.duration_seconds, escape_html(&r.session),
+                               escape_html(&snapshot), list(&r.errors, true)));
+    }
+
+    let html = if mail_conf.format.clone().unwrap_or(String::from("html")) == "html" {
+        Some(format!("<html>\n<body>\n<h2>ggsnap digest: {} runs, {} failed</h2>\n<table>\n\
+                      <tr><th>Started</th><th>Status</th><th>Duration</th><th>Session</th><th>Snapshot</th><th>Errors</th></tr>\n\
+                      {}</table>\n</body>\n</html>\n", runs.len(), failed, rows))
+    }
+    else {
+        None
+    };
+
+    MailMessage {
+        from: mail_conf.from_sender_address.clone(),
+        to: mail_conf.to_addresses.clone(),
+        subject: format!("{}: Digest, {} runs, {} failed", mail_conf.subject, runs.len(), failed),
+        date: date,
+        message_id: message_id(&mail_conf.from_sender_address, date.with_timezone(&Utc)),
+        text: text,
+        html: html,
+        attachment: None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::{ Event, EventLog };
+    use HostType;
+
+    fn test_mail_conf() -> MailFromMaster {
+        MailFromMaster {
+            smtp_host: None,
+            smtp_port: None,
+            smtp_tls: None,
+            smtp_ca_file: None,
+            smtp_timeout: None,
+            tls_domain: None,
+            authentication_mechanism: String::from("plain"),
+            username: String::new(),
+            password: String::new(),
+            from_sender_address: String::from("ggsnap@example.com"),
+            to_addresses: vec![String::from("a@example.com"), String::from("b@example.com")],
+            subject: String::from("Snapshot"),
+            enable: true,
+            format: None,
+            text_template: None,
+            html_template: None,
+            attach_log: None,
+            notify: None,
+            notify_state_file: None,
+        }
+    }
+
+    fn error_report() -> RunReport {
+        let mut log = EventLog::new("vol root@slave::svol");
+        log.set_phase("remove");
+        log.extend(vec![Event::deleted(HostType::Slave, "ggsnap_vol_20180214_095616")]);
+        log.set_phase("resume");
+        log.error(HostType::Master, "Error resuming <geo-replication>");
+        RunReport::from_log(&log, Some("ggsnap_vol_20180216_120438"), false, Utc::now())
+    }
+
+    #[test]
+    fn mime_message() {
+        assert_eq!(base64(b"ggsnap"), "Z2dzbmFw");
+        assert_eq!(base64(b"ggsnap!"), "Z2dzbmFwIQ==");
+        assert_eq!(base64(b"ggsnap!!"), "Z2dzbmFwISE=");
+        assert_eq!(base64(&[b'a'; 60]).lines().next().unwrap().len(), 76);
+        assert_eq!(header_value("Snapshot: OK"), "Snapshot: OK");
+        assert_eq!(header_value("Ögonblick"), "=?UTF-8?B?w5Znb25ibGljaw==?=");
+
+        let report = error_report();
+
+        let text = render(DEFAULT_TEXT_TEMPLATE, &report, false, true);
+        assert!(text.ends_with("\n\nMail status and full log is in attached file ggsnap.log.\n"));
+        assert!(!render(DEFAULT_TEXT_TEMPLATE, &report, false, false).contains("attached"));
+        assert!(text.starts_with("ggsnap run: Error\n\nSession:   vol root@slave::svol\nSnapshot:  ggsnap_vol_20180216_120438\n"));
+        assert!(text.contains("Deleted snapshots:\n  Slave: ggsnap_vol_20180214_095616\n"));
+        assert!(text.contains("Errors:\n  Master: Error resuming <geo-replication>\n"));
+        let html = render(DEFAULT_HTML_TEMPLATE, &report, true, true);
+        assert!(html.contains("<li>Master: Error resuming &lt;geo-replication&gt;</li>"));
+        assert!(html.contains("<p>Mail status and full log is in attached file ggsnap.log.</p>"));
+        assert!(!render(DEFAULT_HTML_TEMPLATE, &report, true, false).contains("attached"));
+
+        let mail_conf = test_mail_conf();
+        let date = Local.from_local_datetime(&NaiveDate::from_ymd_opt(2018, 2, 16).unwrap().and_hms_opt(12, 4, 38).unwrap()).unwrap();
+        let mail = compose(&mail_conf, &report, "full log", date).unwrap();
+        assert!(mail.message_id.starts_with("<") && mail.message_id.ends_with("@example.com>"));
+
+        let msg = mail.format();
+        assert!(msg.starts_with("From: ggsnap@example.com\r\nTo: a@example.com, b@example.com\r\nSubject: Snapshot: Error\r\n"));
+        assert!(msg.contains(&format!("\r\nDate: {}\r\nMessage-ID: {}\r\nMIME-Version: 1.0\r\n", date.to_rfc2822(), mail.message_id)));
+        assert!(msg.contains("Content-Type: multipart/mixed;"));
+        assert!(msg.contains("Content-Type: multipart/alternative;"));
+        assert!(msg.contains("Content-Type: text/html; charset=utf-8\r\n"));
+        assert!(msg.contains("Content-Disposition: attachment; filename=\"ggsnap.log\"\r\nContent-Transfer-Encoding: base64\r\n\r\nZnVsbCBsb2c=\r\n"));
+        assert!(!msg.contains("\n\n"));
+    }
+
+    #[test]
+    fn digest_mail() {
+        let mail_conf = test_mail_conf();
+        let report = error_report();
+        let date = Local.from_local_datetime(&NaiveDate::from_ymd_opt(2018, 2, 16).unwrap().and_hms_opt(12, 4, 38).unwrap()).unwrap();
+        let digest = compose_digest(&mail_conf, &[DigestRun::from_report(&report)], date);
+        assert_eq!(digest.subject, "Snapshot: Digest, 1 runs, 1 failed");
+        assert!(digest.text.contains("  Error  "));
+        assert!(digest.text.contains("\n    Master: Error resuming <geo-replication>\n"));
+        assert!(digest.attachment.is_none());
+    }
+
+    #[test]
+    fn smtp_server_settings() {
+        let mut mail_conf = test_mail_conf();
+        let smtp = smtp_settings(&mail_conf);
+        assert_eq!((smtp.host.as_str(), smtp.port, smtp.tls, smtp.timeout), ("localhost", 25, TlsMode::None, 60));
+        assert!(smtp.auth.is_none());
+
+        mail_conf.tls_domain = Some(String::from("smtp.example.com"));
+        let smtp = smtp_settings(&mail_conf);
+        assert_eq!((smtp.host.as_str(), smtp.port, smtp.tls), ("smtp.example.com", 587, TlsMode::Starttls));
+
+        mail_conf.smtp_host = Some(String::from("10.0.0.25"));
+        mail_conf.smtp_tls = Some(String::from("implicit"));
+        mail_conf.username = String::from("ggsnap");
+        let smtp = smtp_settings(&mail_conf);
+        assert_eq!((smtp.host.as_str(), smtp.port, smtp.tls_domain.as_str()), ("10.0.0.25", 465, "smtp.example.com"));
+        assert_eq!(smtp.auth.map(|a| a.mechanism), Some(String::from("plain")));
+    }
+}
@@ -0,0 +1,337 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+use chrono::prelude::*;
+use timezone::SnapTimeZone;
+
+/// Default template for snapshot names
+pub static DEFAULT_TEMPLATE: &'static str = "{prefix}_{volume}_{zone:%Y%m%d_%H%M%S}";
+/// Default template for manual snapshot names
+pub static DEFAULT_MANUAL_TEMPLATE: &'static str = "{prefix}-{label}_{volume}_{zone:%Y%m%d_%H%M%S}";
+
+#[derive(Debug, PartialEq, Clone)]
+enum Part {
+    Literal(String),
+    Prefix,
+    Volume,
+    Label,
+    Time(SnapTimeZone, String),
+}
+
+/// Template for snapshot names, used both to
+/// create names and to parse names back.
+/// Available fields:
+/// * {prefix} - snapshot_name_prefix
+/// * {volume} - volume name
+/// * {label}  - label of manual snapshot
+/// * {local:FORMAT}, {utc:FORMAT} or {zone:FORMAT} - creation time
+///   in local time, UTC or time zone from config setting timezone
+///
+/// FORMAT is a strftime format that must give
+/// fixed width and contain date and time to seconds.
+#[derive(Debug, PartialEq, Clone)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+    time_width: usize,
+}
+
+/// A snapshot name parsed with a template
+#[derive(Debug, PartialEq, Clone)]
+pub struct SnapName {
+    pub label: Option<String>,
+    pub time: DateTime<Utc>,
+}
+
+impl NameTemplate {
+    /// Parses template and checks that it contains
+    /// {volume} and exactly one time field.
+    /// {zone:FORMAT} is the same as {local:FORMAT}.
+    pub fn new(template: &str) -> Result<NameTemplate, String> {
+        NameTemplate::with_zone(template, SnapTimeZone::Local)
+    }
+
+    /// Parses template, zone is used for {zone:FORMAT}
+    pub fn with_zone(template: &str, zone: SnapTimeZone) -> Result<NameTemplate, String> {
+        let mut parts: Vec<Part> = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            if c == '}' {
+                return Err(format!("Error in snapshot name template: {}, unmatched }}", template));
+            }
+            if c != '{' {
+                literal.push(c);
+                continue;
+            }
+
+            let mut field = String::new();
+            let mut closed = false;
+            while let Some(f) = chars.next() {
+                if f == '}' {
+                    closed = true;
+                    break;
+                }
+                field.push(f);
+            }
+
+            if !closed {
+                return Err(format!("Error in snapshot name template: {}, unmatched {{", template));
+            }
+
+            if literal.len() > 0 {
+                parts.push(Part::Literal(literal.clone()));
+                literal.clear();
+            }
+
+            if field == "prefix" {
+                parts.push(Part::Prefix);
+            }
+            else if field == "volume" {
+                parts.push(Part::Volume);
+            }
+            else if field == "label" {
+                parts.push(Part::Label);
+            }
+            else if field.starts_with("local:") {
+                parts.push(Part::Time(SnapTimeZone::Local, field["local:".len()..].to_string()));
+            }
+            else if field.starts_with("utc:") {
+                parts.push(Part::Time(SnapTimeZone::Utc, field["utc:".len()..].to_string()));
+            }
+            else if field.starts_with("zone:") {
+                parts.push(Part::Time(zone, field["zone:".len()..].to_string()));
+            }
+            else {
+                return Err(format!("Error in snapshot name template: {}, unknown field {{{}}}", template, field));
+            }
+        }
+
+        if literal.len() > 0 {
+            parts.push(Part::Literal(literal));
+        }
+
+        let times: Vec<&String> = parts.iter().filter_map(|p| match *p {
+            Part::Time(_, ref f) => Some(f),
+            _ => None,
+        }).collect();
+
+        if times.len() != 1 {
+            return Err(format!("Error in snapshot name template: {}, exactly one time field {{local:FORMAT}}, {{utc:FORMAT}} or {{zone:FORMAT}} is required", template));
+        }
+
+        if !parts.contains(&Part::Volume) {
+            return Err(format!("Error in snapshot name template: {}, field {{volume}} is required", template));
+        }
+
+        if parts.iter().filter(|p| **p == Part::Label).count() > 1 {
+            return Err(format!("Error in snapshot name template: {}, field {{label}} can only be used once", template));
+        }
+
+        let time_width = match check_time_format(times[0]) {
+            Ok(w) => w,
+            Err(e) => return Err(format!("Error in snapshot name template: {}, {}", template, e)),
+        };
+
+        Ok(NameTemplate { parts: parts, time_width: time_width })
+    }
+
+    /// True if template contains {label}
+    pub fn has_label(&self) -> bool {
+        self.parts.contains(&Part::Label)
+    }
+
+    /// Creates snapshot name from template
+    pub fn format(&self, prefix: &str, volume: &str, label: Option<&str>, time: DateTime<Utc>) -> String {
+        let mut name = String::new();
+
+        for p in self.parts.iter() {
+            match *p {
+                Part::Literal(ref l) => name.push_str(l),
+                Part::Prefix => name.push_str(prefix),
+                Part::Volume => name.push_str(volume),
+                Part::Label => name.push_str(label.unwrap_or("")),
+                Part::Time(zone, ref f) => name.push_str(&zone.naive_from_utc(time).format(f).to_string()),
+            }
+        }
+
+        name
+    }
+
+    /// Parses a snapshot name, returns None if name
+    /// does not match template with prefix and volume.
+    pub fn parse(&self, name: &str, prefix: &str, volume: &str) -> Option<SnapName> {
+        let (label, zone, time) = match self.match_parts(0, name, prefix, volume) {
+            Some((label, Some((zone, time)))) => (label, zone, time),
+            _ => return None,
+        };
+
+        match zone.naive_to_utc(&time) {
+            Some(t) => Some(SnapName { label: label, time: t }),
+            None => None,
+        }
+    }
+
+    /// Matches name against parts from idx to end,
+    /// returns label and time found in name.
+    fn match_parts(&self, idx: usize, name: &str, prefix: &str, volume: &str)
+                   -> Option<(Option<String>, Option<(SnapTimeZone, NaiveDateTime)>)> {
+        if idx == self.parts.len() {
+            return if name.len() == 0 { Some((None, None)) } else { None };
+        }
+
+        match self.parts[idx] {
+            Part::Literal(ref l) => self.match_literal(idx, name, l, prefix, volume),
+            Part::Prefix => self.match_literal(idx, name, prefix, prefix, volume),
+            Part::Volume => self.match_literal(idx, name, volume, prefix, volume),
+            Part::Time(zone, ref f) => {
+                if name.len() < self.time_width || !name.is_char_boundary(self.time_width) {
+                    return None;
+                }
+
+                let time = match NaiveDateTime::parse_from_str(&name[..self.time_width], f) {
+                    Ok(t) => t,
+                    Err(_) => return None,
+                };
+
+                match self.match_parts(idx + 1, &name[self.time_width..], prefix, volume) {
+                    Some((label, _)) => Some((label, Some((zone, time)))),
+                    None => None,
+                }
+            },
+            Part::Label => {
+                // Label only contains ascii, longest label is tried first
+                let label_len = name.chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '-').count();
+
+                for end in (1..label_len + 1).rev() {
+                    if let Some((_, time)) = self.match_parts(idx + 1, &name[end..], prefix, volume) {
+                        return Some((Some(name[..end].to_string()), time));
+                    }
+                }
+
+                None
+            },
+        }
+    }
+
+    fn match_literal(&self, idx: usize, name: &str, literal: &str, prefix: &str, volume: &str)
+                     -> Option<(Option<String>, Option<(SnapTimeZone, NaiveDateTime)>)> {
+        if name.starts_with(literal) {
+            self.match_parts(idx + 1, &name[literal.len()..], prefix, volume)
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// Checks that time format gives the same
+/// width for all times and that the time
+/// can be parsed back to seconds.
+/// Returns width of formatted time.
+fn check_time_format(format: &str) -> Result<usize, String> {
+    let t1 = NaiveDate::from_ymd(2018, 1, 2).and_hms(3, 4, 5);
+    let t2 = NaiveDate::from_ymd(2018, 12, 31).and_hms(23, 59, 59);
+    let s1 = t1.format(format).to_string();
+    let s2 = t2.format(format).to_string();
+
+    if s1.len() != s2.len() {
+        return Err(format!("time format: {} does not give fixed width", format));
+    }
+
+    match (NaiveDateTime::parse_from_str(&s1, format), NaiveDateTime::parse_from_str(&s2, format)) {
+        (Ok(p1), Ok(p2)) => {
+            if p1 == t1 && p2 == t2 {
+                Ok(s1.len())
+            }
+            else {
+                Err(format!("time format: {} must contain date and time to seconds", format))
+            }
+        },
+        _ => Err(format!("time format: {} must contain date and time to seconds", format)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_template() {
+        let t = NameTemplate::new(DEFAULT_TEMPLATE).unwrap();
+        let time = Local.ymd(2018, 2, 16).and_hms(12, 4, 38).with_timezone(&Utc);
+        let name = t.format("ggsnap", "v_o_l", None, time);
+
+        assert_eq!(name, "ggsnap_v_o_l_20180216_120438");
+        assert_eq!(t.parse(&name, "ggsnap", "v_o_l"), Some(SnapName { label: None, time: time }));
+        assert_eq!(t.parse(&name, "ggsnap", "v_o"), None);
+        assert_eq!(t.parse(&name, "snap", "v_o_l"), None);
+        assert_eq!(t.parse("ggsnap_v_o_l_20180216_120438_GMT", "ggsnap", "v_o_l"), None);
+        assert_eq!(t.parse("ggsnap_v_o_l_20180216_1204", "ggsnap", "v_o_l"), None);
+        assert_eq!(t.parse("ggsnap-x_v_o_l_20180216_120438", "ggsnap", "v_o_l"), None);
+        assert!(!t.has_label());
+
+        let t = NameTemplate::with_zone(DEFAULT_TEMPLATE, SnapTimeZone::Utc).unwrap();
+        let time = Utc.ymd(2018, 2, 16).and_hms(12, 4, 38);
+        assert_eq!(t.format("ggsnap", "v_o_l", None, time), "ggsnap_v_o_l_20180216_120438");
+    }
+
+    #[test]
+    fn utc_and_label_template() {
+        let t = NameTemplate::new("{prefix}-{volume}-{utc:%Y%m%dT%H%M%SZ}").unwrap();
+        let time = Utc.ymd(2018, 2, 16).and_hms(12, 4, 38);
+        let name = t.format("ggsnap", "vol", None, time);
+
+        assert_eq!(name, "ggsnap-vol-20180216T120438Z");
+        assert_eq!(t.parse(&name, "ggsnap", "vol").unwrap().time, time);
+
+        let t = NameTemplate::new(DEFAULT_MANUAL_TEMPLATE).unwrap();
+        let name = t.format("ggsnap", "vol", Some("pre-upgrade"), time);
+
+        assert_eq!(t.parse(&name, "ggsnap", "vol").unwrap().label, Some(String::from("pre-upgrade")));
+        assert_eq!(t.parse(&name, "ggsnap", "vol").unwrap().time, time);
+        assert!(t.has_label());
+
+        let t = NameTemplate::new("{volume}_{utc:%Y%m%d_%H%M%S}_{label}").unwrap();
+        let name = t.format("ggsnap", "vol", Some("pre-upgrade"), time);
+
+        assert_eq!(name, "vol_20180216_120438_pre-upgrade");
+        assert_eq!(t.parse(&name, "ggsnap", "vol").unwrap().label, Some(String::from("pre-upgrade")));
+
+        let zone = SnapTimeZone::parse("America/New_York").unwrap();
+        let t = NameTemplate::with_zone("{prefix}_{volume}_{zone:%Y%m%d_%H%M%S}", zone).unwrap();
+        let name = t.format("ggsnap", "vol", None, time);
+
+        assert_eq!(name, "ggsnap_vol_20180216_070438");
+        assert_eq!(t.parse(&name, "ggsnap", "vol").unwrap().time, time);
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!(NameTemplate::new("{prefix}_{volume}").is_err());
+        assert!(NameTemplate::new("{prefix}_{local:%Y%m%d_%H%M%S}").is_err());
+        assert!(NameTemplate::new("{prefix}_{volume}_{local:%Y%m%d}").is_err());
+        assert!(NameTemplate::new("{prefix}_{volume}_{local:%B%d_%H%M%S}").is_err());
+        assert!(NameTemplate::new("{prefix}_{volume}_{foo}_{local:%Y%m%d_%H%M%S}").is_err());
+        assert!(NameTemplate::new("{prefix}_{volume_{local:%Y%m%d_%H%M%S}").is_err());
+        assert!(NameTemplate::new("{label}{label}{volume}_{local:%Y%m%d_%H%M%S}").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use chrono::prelude::*;
+use toml;
+use report::RunReport;
+use state::state_file_path;
+
+/// Default state file for mail notifications
+pub static DEFAULT_NOTIFY_STATE_FILE: &'static str = "ggsnap_notify_state.toml";
+
+static DIGEST_DATE_FORMAT: &'static str = "%Y-%m-%d";
+
+/// When mail is sent, from config setting notify
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    Always,
+    Failure,
+    Change,
+    Daily,
+    Weekly,
+}
+
+impl Policy {
+    pub fn from_name(name: &str) -> Option<Policy> {
+        match name {
+            "always" => Some(Policy::Always),
+            "failure" => Some(Policy::Failure),
+            "change" => Some(Policy::Change),
+            "daily" => Some(Policy::Daily),
+            "weekly" => Some(Policy::Weekly),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Policy::Always => "always",
+            Policy::Failure => "failure",
+            Policy::Change => "change",
+            Policy::Daily => "daily",
+            Policy::Weekly => "weekly",
+        }
+    }
+}
+
+/// A run waiting to be sent in a digest
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct DigestRun {
+    pub timestamp: String,
+    pub session: String,
+    pub snapshot: Option<String>,
+    pub success: bool,
+    pub duration_seconds: f64,
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+impl DigestRun {
+    pub fn from_report(report: &RunReport) -> DigestRun {
+        DigestRun {
+            timestamp: report.timestamp.clone(),
+            session: report.session.clone(),
+            snapshot: report.snapshot.clone(),
+            success: report.success,
+            duration_seconds: report.duration_seconds,
+            errors: report.errors.clone(),
+        }
+    }
+}
+
+/// Result of last run per session and runs of current
+/// digest period, stored in notify state file.
+/// digest_start is the first day of the period.
+/// Runs of all sessions are in the same digest.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+pub struct NotifyState {
+    pub digest_start: Option<String>,
+    #[serde(default)]
+    pub run: Vec<DigestRun>,
+    #[serde(default)]
+    pub session_success: BTreeMap<String, bool>,
+}
+
+/// What to send after a run
+#[derive(Debug, PartialEq)]
+pub enum Notification {
+    Skip,
+    Run,
+    Digest(Vec<DigestRun>),
+}
+
+/// True if today is in a later period than start
+fn new_period(policy: Policy, start: NaiveDate, today: NaiveDate) -> bool {
+    match policy {
+        Policy::Weekly => {
+            let (s, t) = (start.iso_week(), today.iso_week());
+            (t.year(), t.week()) > (s.year(), s.week())
+        },
+        _ => today > start,
+    }
+}
+
+/// Decides if mail of run is sent according to policy
+/// and updates state. With change mail is sent on first
+/// failure and on recovery of the session. With daily and weekly, runs are
+/// saved and the first run in a new day or week sends a
+/// digest of the runs in the previous period.
+pub fn notification(policy: Policy, state: &mut NotifyState, report: &RunReport, today: NaiveDate) -> Notification {
+    let last_success = state.session_success.insert(report.session.clone(), report.success).unwrap_or(true);
+
+    match policy {
+        Policy::Always => Notification::Run,
+        Policy::Failure if !report.success => Notification::Run,
+        Policy::Change if report.success != last_success => Notification::Run,
+        Policy::Failure | Policy::Change => Notification::Skip,
+        Policy::Daily | Policy::Weekly => {
+            let start = state.digest_start.as_ref()
+                             .and_then(|s| NaiveDate::parse_from_str(s, DIGEST_DATE_FORMAT).ok());
+            let mut notification = Notification::Skip;
+
+            match start {
+                Some(s) if !new_period(policy, s, today) => (),
+                _ => {
+                    if !state.run.is_empty() {
+                        notification = Notification::Digest(state.run.drain(..).collect());
+                    }
+                    state.digest_start = Some(today.format(DIGEST_DATE_FORMAT).to_string());
+                },
+            }
+
+            state.run.push(DigestRun::from_report(report));
+            notification
+        },
+    }
+}
+
+/// State to save when mail could not be sent, previous
+/// state is kept so a change is sent again after next run
+/// and with daily and weekly the run is added to the runs
+/// that were not sent, they are in the next digest.
+pub fn unsent(policy: Policy, previous: &NotifyState, report: &RunReport) -> NotifyState {
+    let mut state = previous.clone();
+
+    if policy == Policy::Daily || policy == Policy::Weekly {
+        state.run.push(DigestRun::from_report(report));
+    }
+    state
+}
+
+/// Reads notify state, if state file
+/// does not exist an empty state is returned.
+pub fn read_notify_state(state_file: &str) -> Result<NotifyState, String> {
+    let path = match state_file_path(state_file) {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+    let mut content = String::new();
+
+    if !path.exists() {
+        return Ok(NotifyState::default());
+    }
+
+    match File::open(&path) {
+        Ok(mut f) => {
+            if let Err(e) = f.read_to_string(&mut content) {
+                return Err(format!("Error: Can not read notify state file: {}\n{}", path.display(), e));
+            }
+        },
+        Err(e) => return Err(format!("Error: Can not open notify state file: {}\n{}", path.display(), e)),
+    }
+
+    match toml::from_str(&content) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(format!("Error parse notify state file: {}\n{}", path.display(), e)),
+    }
+}
+
+/// Writes notify state to state file
+pub fn write_notify_state(state_file: &str, state: &NotifyState) -> Result<(), String> {
+    let path = match state_file_path(state_file) {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+
+    let content = match toml::to_string(state) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Error: Can not serialize notify state: {}", e)),
+    };
+
+    match File::create(&path) {
+        Ok(mut f) => match f.write_all(content.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Error writing to notify state file: {}\nError: {}", path.display(), e)),
+        },
+        Err(e) => Err(format!("Error opening notify state file: {}\nError: {}", path.display(), e)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::EventLog;
+
+    #[test]
+    fn notify_policy() {
+        let log = EventLog::new("vol root@slave::svol");
+        let ok = RunReport::from_log(&log, Some("ggsnap_vol_20180216_120438"), true, Utc::now());
+        let failed = RunReport::from_log(&log, None, false, Utc::now());
+        let day = |d: u32| NaiveDate::from_ymd_opt(2018, 2, d).unwrap();
+
+        let mut state = NotifyState::default();
+        assert_eq!(notification(Policy::Failure, &mut state, &ok, day(12)), Notification::Skip);
+        assert_eq!(notification(Policy::Failure, &mut state, &failed, day(12)), Notification::Run);
+        assert_eq!(notification(Policy::Always, &mut state, &ok, day(12)), Notification::Run);
+
+        let mut state = NotifyState::default();
+        assert_eq!(notification(Policy::Change, &mut state, &ok, day(12)), Notification::Skip);
+        assert_eq!(notification(Policy::Change, &mut state, &failed, day(12)), Notification::Run);
+        assert_eq!(notification(Policy::Change, &mut state, &failed, day(12)), Notification::Skip);
+        assert_eq!(notification(Policy::Change, &mut state, &ok, day(12)), Notification::Run);
+
+        let previous = NotifyState::default();
+        let mut state = unsent(Policy::Change, &previous, &failed);
+        assert_eq!(notification(Policy::Change, &mut state, &failed, day(12)), Notification::Run);
+
+        let mut state = NotifyState::default();
+        assert_eq!(notification(Policy::Daily, &mut state, &ok, day(12)), Notification::Skip);
+        assert_eq!(notification(Policy::Daily, &mut state, &failed, day(12)), Notification::Skip);
+        match notification(Policy::Daily, &mut state, &ok, day(13)) {
+            Notification::Digest(runs) => {
+                assert_eq!(runs.len(), 2);
+                assert_eq!(runs[1].success, false);
+            },
+            n => panic!("Expected digest: {:?}", n),
+        }
+        assert_eq!(state.run.len(), 1);
+        assert_eq!(state.digest_start, Some(String::from("2018-02-13")));
+
+        let mut previous = state.clone();
+        match notification(Policy::Daily, &mut previous.clone(), &failed, day(14)) {
+            Notification::Digest(runs) => assert_eq!(runs.len(), 1),
+            n => panic!("Expected digest: {:?}", n),
+        }
+        previous = unsent(Policy::Daily, &previous, &failed);
+        assert_eq!(previous.run.len(), 2);
+        assert_eq!(previous.digest_start, Some(String::from("2018-02-13")));
+
+        let saved: NotifyState = toml::from_str(&toml::to_string(&state).unwrap()).unwrap();
+        assert_eq!(saved, state);
+
+        // Monday 12 to Sunday 18 is one week
+        let mut state = NotifyState::default();
+        assert_eq!(notification(Policy::Weekly, &mut state, &ok, day(12)), Notification::Skip);
+        assert_eq!(notification(Policy::Weekly, &mut state, &ok, day(18)), Notification::Skip);
+        match notification(Policy::Weekly, &mut state, &ok, day(19)) {
+            Notification::Digest(runs) => assert_eq!(runs.len(), 2),
+            n => panic!("Expected digest: {:?}", n),
+        }
+    }
+
+    #[test]
+    fn notify_per_session() {
+        let failed1 = RunReport::from_log(&EventLog::new("vol1 root@slave::svol1"), None, false, Utc::now());
+        let ok2 = RunReport::from_log(&EventLog::new("vol2 root@slave::svol2"), None, true, Utc::now());
+        let day = NaiveDate::from_ymd_opt(2018, 2, 12).unwrap();
+
+        let mut state = NotifyState::default();
+        assert_eq!(notification(Policy::Change, &mut state, &failed1, day), Notification::Run);
+        assert_eq!(notification(Policy::Change, &mut state, &ok2, day), Notification::Skip);
+        assert_eq!(notification(Policy::Change, &mut state, &failed1, day), Notification::Skip);
+        assert_eq!(notification(Policy::Change, &mut state, &ok2, day), Notification::Skip);
+
+        let saved: NotifyState = toml::from_str(&toml::to_string(&state).unwrap()).unwrap();
+        assert_eq!(saved, state);
+
+        // State file from before sessions were kept apart
+        let old: NotifyState = toml::from_str("last_success = false\ndigest_start = \"2018-02-12\"\n").unwrap();
+        assert_eq!(old.digest_start, Some(String::from("2018-02-12")));
+        assert!(old.session_success.is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use chrono::NaiveDate;
+use toml;
+use Config;
+
+static PIN_DATE_FORMAT: &'static str = "%Y-%m-%d";
+
+/// A pinned snapshot, a pinned snapshot is never
+/// removed by ggsnap. If until is set the pin is
+/// valid to and including that date (YYYY-MM-DD).
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Pin {
+    pub name: String,
+    pub until: Option<String>,
+}
+
+/// All pinned snapshots, stored in
+/// the pin file on each host.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+pub struct Pins {
+    #[serde(default)]
+    pub pin: Vec<Pin>,
+}
+
+impl Pins {
+    /// Returns true if snapshot is pinned
+    /// and the pin has not expired
+    pub fn is_pinned(&self, snap_name: &str, today: NaiveDate) -> bool {
+        self.pin.iter().any(|p| p.name == snap_name && !p.is_expired(today))
+    }
+
+    /// Adds a pin, if snapshot already is
+    /// pinned the until date is replaced.
+    pub fn add(&mut self, snap_name: &str, until: Option<NaiveDate>) {
+        let until = until.map(|d| d.format(PIN_DATE_FORMAT).to_string());
+
+        for p in self.pin.iter_mut() {
+            if p.name == snap_name {
+                p.until = until;
+                return;
+            }
+        }
+
+        self.pin.push(Pin { name: snap_name.to_string(), until: until });
+    }
+
+    /// Removes pin, returns false if
+    /// snapshot was not pinned.
+    pub fn remove(&mut self, snap_name: &str) -> bool {
+        let len = self.pin.len();
+        self.pin.retain(|p| p.name != snap_name);
+        len != self.pin.len()
+    }
+
+    /// Removes all pins that has expired
+    pub fn remove_expired(&mut self, today: NaiveDate) {
+        self.pin.retain(|p| !p.is_expired(today));
+    }
+}
+
+impl Pin {
+    /// A pin without until date never expires,
+    /// an until date that can not be parsed is
+    /// treated as never expiring.
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        match self.until {
+            Some(ref u) => match parse_until(u) {
+                Ok(d) => d < today,
+                Err(_) => false,
+            },
+            None => false,
+        }
+    }
+}
+
+/// Parses date given to --until, format: YYYY-MM-DD
+pub fn parse_until(until: &str) -> Result<NaiveDate, String> {
+    match NaiveDate::parse_from_str(until, PIN_DATE_FORMAT) {
+        Ok(d) => Ok(d),
+        Err(e) => Err(format!("Error: Invalid date: {}, expected format YYYY-MM-DD; {}", until, e)),
+    }
+}
+
+/// Path to pin file, a relative path in
+/// config file is relative to the binary.
+pub fn pin_file_path(config: &Config) -> Result<PathBuf, String> {
+    let pin_file = match config.general.pin_file {
+        Some(ref p) => PathBuf::from(p),
+        None => return Err(String::from("Error: Missing config value: pin_file")),
+    };
+
+    if pin_file.is_relative() {
+        let mut exe_path = match std::env::current_exe() {
+            Ok(p)  => p,
+            Err(e) => return Err(format!("Error getting exe path, can not find pin file.\nError: {}", e)),
+        };
+
+        exe_path.pop();
+        exe_path.push(pin_file);
+        Ok(exe_path)
+    }
+    else {
+        Ok(pin_file)
+    }
+}
+
+/// Reads all pins from pin file,
+/// if pin file does not exist no
+/// snapshots are pinned.
+pub fn read_pins(config: &Config) -> Result<Pins, String> {
+    let path = match pin_file_path(config) {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+    let mut content = String::new();
+
+    if !path.exists() {
+        return Ok(Pins::default());
+    }
+
+    match File::open(&path) {
+        Ok(mut f) => {
+            if let Err(e) = f.read_to_string(&mut content) {
+                return Err(format!("Error: Can not read pin file: {}\n{}", path.display(), e));
+            }
+        },
+        Err(e) => return Err(format!("Error: Can not open pin file: {}\n{}", path.display(), e)),
+    }
+
+    match parse_pins(&content) {
+        Ok(p) => Ok(p),
+        Err(e) => Err(format!("Error parse pin file: {}\n{}", path.display(), e)),
+    }
+}
+
+/// Writes all pins to pin file
+pub fn write_pins(config: &Config, pins: &Pins) -> Result<(), String> {
+    let path = match pin_file_path(config) {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+
+    let content = match toml::to_string(pins) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Error: Can not serialize pins: {}", e)),
+    };
+
+    match File::create(&path) {
+        Ok(mut f) => match f.write_all(content.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Error writing to pin file: {}\nError: {}", path.display(), e)),
+        },
+        Err(e) => Err(format!("Error opening pin file: {}\nError: {}", path.display(), e)),
+    }
+}
+
+fn parse_pins(content: &str) -> Result<Pins, toml::de::Error> {
+    toml::from_str(content)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_until() {
+        let mut pins = parse_pins("
+            [[pin]]
+            name = 'ggsnap_vol_20180214_095616'
+
+            [[pin]]
+            name = 'ggsnap_vol_20180216_114403'
+            until = '2018-03-01'
+            ").unwrap();
+
+        let before = NaiveDate::from_ymd(2018, 3, 1);
+        let after = NaiveDate::from_ymd(2018, 3, 2);
+
+        assert!(pins.is_pinned("ggsnap_vol_20180214_095616", after));
+        assert!(pins.is_pinned("ggsnap_vol_20180216_114403", before));
+        assert!(!pins.is_pinned("ggsnap_vol_20180216_114403", after));
+        assert!(!pins.is_pinned("ggsnap_vol_20180216_115150", before));
+
+        pins.remove_expired(after);
+        assert_eq!(pins.pin.len(), 1);
+
+        pins.add("ggsnap_vol_20180214_095616", Some(before));
+        assert_eq!(pins.pin.len(), 1);
+        assert!(!pins.is_pinned("ggsnap_vol_20180214_095616", after));
+
+        assert!(pins.remove("ggsnap_vol_20180214_095616"));
+        assert!(!pins.remove("ggsnap_vol_20180214_095616"));
+
+        let s = toml::to_string(&pins).unwrap();
+        assert_eq!(parse_pins(&s).unwrap(), pins);
+    }
+
+    #[test]
+    fn until_format() {
+        assert_eq!(parse_until("2018-02-30").is_err(), true);
+        assert_eq!(parse_until("20180228").is_err(), true);
+        assert_eq!(parse_until("2018-02-28").unwrap(), NaiveDate::from_ymd(2018, 2, 28));
+    }
+}
@@ -0,0 +1,169 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use chrono::prelude::*;
+use serde_json;
+use event::{ EventLog, Level };
+use HostType;
+
+/// Result of one phase of a run
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Step {
+    pub name: String,
+    pub status: String,
+    pub started: String,
+    pub duration_seconds: f64,
+}
+
+/// Snapshot deleted during a run
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Deleted {
+    pub host: HostType,
+    pub snapshot: String,
+}
+
+/// Machine readable summary of a run,
+/// written as one JSON line per run.
+#[derive(Serialize, Debug)]
+pub struct RunReport {
+    pub timestamp: String,
+    pub session: String,
+    pub snapshot: Option<String>,
+    pub success: bool,
+    pub duration_seconds: f64,
+    pub steps: Vec<Step>,
+    pub deleted: Vec<Deleted>,
+    pub errors: Vec<String>,
+    pub mail: Option<String>,
+}
+
+fn seconds(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    match to.signed_duration_since(from).num_microseconds() {
+        Some(us) => us as f64 / 1000000.0,
+        None => 0.0,
+    }
+}
+
+impl RunReport {
+    /// Builds report from log of a run, a step per phase
+    /// that is failed if phase has errors, a phase that is
+    /// set twice gives two steps. Deleted snapshots are
+    /// events with deleted set.
+    pub fn from_log(log: &EventLog, snapshot: Option<&str>, success: bool, end: DateTime<Utc>) -> RunReport {
+        let mut steps: Vec<Step> = Vec::new();
+
+        for (i, p) in log.phases.iter().enumerate() {
+            let p_end = match log.phases.get(i + 1) {
+                Some(n) => n.start,
+                None => end,
+            };
+
+            let events = log.phase_events(i);
+            if events.is_empty() && p.name == "start" {
+                continue;
+            }
+
+            let status = if events.iter().any(|e| e.level == Level::Error) {
+                "failed"
+            }
+            else if events.iter().any(|e| e.level == Level::Warning) {
+                "warning"
+            }
+            else {
+                "ok"
+            };
+
+            steps.push(Step {
+                name: p.name.clone(),
+                status: status.to_string(),
+                started: p.start.to_rfc3339(),
+                duration_seconds: seconds(p.start, p_end),
+            });
+        }
+
+        let deleted: Vec<Deleted> = log.events.iter()
+            .filter_map(|e| e.deleted.as_ref().map(|s| Deleted { host: e.host, snapshot: s.clone() }))
+            .collect();
+
+        let start = log.phases[0].start;
+
+        RunReport {
+            timestamp: start.to_rfc3339(),
+            session: log.session.clone(),
+            snapshot: snapshot.map(|s| s.to_string()),
+            success: success,
+            duration_seconds: seconds(start, end),
+            steps: steps,
+            deleted: deleted,
+            errors: log.events.iter().filter(|e| e.level == Level::Error)
+                       .map(|e| format!("{}: {}", ::host_label(&e.host), e.message)).collect(),
+            mail: log.events.iter().filter(|e| e.phase == "mail").map(|e| e.message.clone()).last(),
+        }
+    }
+
+    /// Report as one JSON line
+    pub fn to_json(&self) -> Result<String, String> {
+        match serde_json::to_string(self) {
+            Ok(j) => Ok(format!("{}\n", j)),
+            Err(e) => Err(format!("Error creating run report: {}", e)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use event::Event;
+
+    #[test]
+    fn run_report() {
+        let mut log = EventLog::new("vol root@slave::svol");
+        log.set_phase("snapshot");
+        log.info(HostType::Master, "Creating snapshot: snap3 on volume: vol");
+        log.set_phase("remove");
+        log.extend(vec![Event::deleted(HostType::Slave, "snap1")]);
+        log.extend(Event::lines(Level::Info, HostType::Slave, "Deleting snap will erase all the information about the snap. Do you still want to continue? (y/n) snapshot delete: snap1: snap removed successfully"));
+        log.error(HostType::Master, "Error deleting snapshot: snap2");
+        log.info(HostType::Master, "snapshot delete: snap2: snap removed successfully");
+        log.set_phase("resume");
+        log.info(HostType::Master, "Resuming geo-replication");
+        log.set_phase("remove");
+        log.info(HostType::Master, "Removing old snapshots");
+        log.set_phase("mail");
+        log.info(HostType::Master, "Email sent OK");
+
+        let end = log.phases[5].start + Duration::seconds(2);
+        let report = RunReport::from_log(&log, Some("snap3"), false, end);
+
+        let steps: Vec<(&str, &str)> = report.steps.iter().map(|s| (s.name.as_str(), s.status.as_str())).collect();
+        assert_eq!(steps, vec![("snapshot", "ok"), ("remove", "failed"), ("resume", "ok"), ("remove", "ok"), ("mail", "ok")]);
+        assert!(report.steps[4].duration_seconds >= 2.0);
+        assert_eq!(report.deleted, vec![Deleted { host: HostType::Slave, snapshot: String::from("snap1") }]);
+        assert_eq!(report.errors, vec![String::from("Master: Error deleting snapshot: snap2")]);
+        assert_eq!(report.mail, Some(String::from("Email sent OK")));
+
+        let json = report.to_json().unwrap();
+        assert!(json.contains("\"snapshot\":\"snap3\",\"success\":false"));
+        assert_eq!(json.lines().count(), 1);
+    }
+}
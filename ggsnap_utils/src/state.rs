@@ -0,0 +1,119 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use chrono::prelude::*;
+use toml;
+
+/// Times of last runs in daemon mode, stored
+/// in state file so missed runs can be caught
+/// up after downtime. Times are RFC 3339.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct DaemonState {
+    pub last_snapshot: Option<String>,
+    pub last_prune: Option<String>,
+}
+
+impl DaemonState {
+    /// Parses time of last run
+    pub fn last_run(time: &Option<String>) -> Option<DateTime<Utc>> {
+        match *time {
+            Some(ref t) => DateTime::parse_from_rfc3339(t).ok().map(|t| t.with_timezone(&Utc)),
+            None => None,
+        }
+    }
+
+    /// Formats time of run for state file
+    pub fn run_time(time: DateTime<Utc>) -> Option<String> {
+        Some(time.to_rfc3339())
+    }
+}
+
+/// Path to state file, a relative path
+/// is relative to the binary.
+pub fn state_file_path(state_file: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(state_file);
+
+    if path.is_relative() {
+        let mut exe_path = match std::env::current_exe() {
+            Ok(p)  => p,
+            Err(e) => return Err(format!("Error getting exe path, can not find state file.\nError: {}", e)),
+        };
+
+        exe_path.pop();
+        exe_path.push(path);
+        Ok(exe_path)
+    }
+    else {
+        Ok(path)
+    }
+}
+
+/// Reads daemon state, if state file
+/// does not exist an empty state is returned.
+pub fn read_daemon_state(state_file: &str) -> Result<DaemonState, String> {
+    let path = match state_file_path(state_file) {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+    let mut content = String::new();
+
+    if !path.exists() {
+        return Ok(DaemonState::default());
+    }
+
+    match File::open(&path) {
+        Ok(mut f) => {
+            if let Err(e) = f.read_to_string(&mut content) {
+                return Err(format!("Error: Can not read state file: {}\n{}", path.display(), e));
+            }
+        },
+        Err(e) => return Err(format!("Error: Can not open state file: {}\n{}", path.display(), e)),
+    }
+
+    match toml::from_str(&content) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(format!("Error parse state file: {}\n{}", path.display(), e)),
+    }
+}
+
+/// Writes daemon state to state file
+pub fn write_daemon_state(state_file: &str, state: &DaemonState) -> Result<(), String> {
+    let path = match state_file_path(state_file) {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+
+    let content = match toml::to_string(state) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Error: Can not serialize state: {}", e)),
+    };
+
+    match File::create(&path) {
+        Ok(mut f) => match f.write_all(content.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Error writing to state file: {}\nError: {}", path.display(), e)),
+        },
+        Err(e) => Err(format!("Error opening state file: {}\nError: {}", path.display(), e)),
+    }
+}
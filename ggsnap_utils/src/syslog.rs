@@ -0,0 +1,149 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use chrono::prelude::*;
+use event::Level;
+use Config;
+
+/// Path to local syslog socket
+static SYSLOG_SOCKET: &'static str = "/dev/log";
+
+pub const DEFAULT_FACILITY: &'static str = "daemon";
+pub const DEFAULT_IDENT: &'static str = "ggsnap";
+
+/// Syslog severities used by ggsnap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Err = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+impl Severity {
+    pub fn from_level(level: &Level) -> Severity {
+        match *level {
+            Level::Error => Severity::Err,
+            Level::Warning => Severity::Warning,
+            Level::Info => Severity::Info,
+        }
+    }
+}
+
+/// Facility code from name, ex: daemon, user, local0
+pub fn facility_code(name: &str) -> Option<u8> {
+    let code = match name {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        "local0" => 16,
+        "local1" => 17,
+        "local2" => 18,
+        "local3" => 19,
+        "local4" => 20,
+        "local5" => 21,
+        "local6" => 22,
+        "local7" => 23,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// Formats message as a BSD syslog line as sent
+/// by syslog(3) to the local socket.
+fn format_message(facility: u8, severity: Severity, time: &DateTime<Local>, ident: &str, pid: u32, msg: &str) -> String {
+    format!("<{}>{} {}[{}]: {}", (facility as u32) * 8 + severity as u32,
+            time.format("%b %e %H:%M:%S"), ident, pid, msg)
+}
+
+/// Connection to local syslog
+pub struct Syslog {
+    sock: UnixDatagram,
+    facility: u8,
+    ident: String,
+}
+
+impl Syslog {
+    /// Connects to /dev/log with facility
+    /// and ident from config file.
+    pub fn connect(config: &Config) -> Result<Syslog, String> {
+        let name = config.general.syslog_facility.clone().unwrap_or(String::from(DEFAULT_FACILITY));
+        let facility = match facility_code(&name) {
+            Some(f) => f,
+            None => return Err(format!("Error unknown syslog facility: {}", name)),
+        };
+
+        let sock = match UnixDatagram::unbound() {
+            Ok(s) => s,
+            Err(e) => return Err(format!("Error creating syslog socket: {}", e)),
+        };
+
+        if let Err(e) = sock.connect(SYSLOG_SOCKET) {
+            return Err(format!("Error connecting to syslog: {}: {}", SYSLOG_SOCKET, e));
+        }
+
+        Ok(Syslog {
+            sock: sock,
+            facility: facility,
+            ident: config.general.syslog_ident.clone().unwrap_or(String::from(DEFAULT_IDENT)),
+        })
+    }
+
+    pub fn send(&self, severity: Severity, msg: &str) -> Result<(), String> {
+        let line = format_message(self.facility, severity, &Local::now(), &self.ident, process::id(), msg);
+
+        match self.sock.send(line.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Error sending to syslog: {}: {}", SYSLOG_SOCKET, e)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syslog_message() {
+        assert_eq!(facility_code("daemon"), Some(3));
+        assert_eq!(facility_code("local7"), Some(23));
+        assert_eq!(facility_code("nothing"), None);
+
+        let time = Local.ymd(2018, 2, 6).and_hms(8, 4, 5);
+        assert_eq!(format_message(3, Severity::Err, &time, "ggsnap", 42, "Master: Error creating snapshot"),
+                   "<27>Feb  6 08:04:05 ggsnap[42]: Master: Error creating snapshot");
+        assert_eq!(format_message(16, Severity::from_level(&Level::Info), &time, "gg", 1, "x"),
+                   "<134>Feb  6 08:04:05 gg[1]: x");
+    }
+}
@@ -0,0 +1,202 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use std::env;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixDatagram;
+use std::{ thread, time };
+use libc;
+use event::Level;
+
+/// Path to journald native protocol socket
+static JOURNAL_SOCKET: &'static str = "/run/systemd/journal/socket";
+
+/// Sends a message to systemd over NOTIFY_SOCKET,
+/// returns false if not started by systemd or
+/// message could not be sent.
+pub fn notify(msg: &str) -> bool {
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+
+    let sock = match UnixDatagram::unbound() {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    let bytes = path.as_bytes();
+
+    if bytes.len() == 0 || bytes.len() >= addr.sun_path.len() {
+        return false;
+    }
+
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    for (i, b) in bytes.iter().enumerate() {
+        addr.sun_path[i] = *b as libc::c_char;
+    }
+
+    // Abstract socket namespace
+    if bytes[0] == b'@' {
+        addr.sun_path[0] = 0;
+    }
+
+    let len = mem::size_of::<libc::sa_family_t>() + bytes.len();
+    let res = unsafe {
+        libc::sendto(sock.as_raw_fd(), msg.as_ptr() as *const libc::c_void, msg.len(), libc::MSG_NOSIGNAL,
+                     &addr as *const libc::sockaddr_un as *const libc::sockaddr, len as libc::socklen_t)
+    };
+
+    res >= 0
+}
+
+/// Daemon is started and ready
+pub fn ready() {
+    notify("READY=1");
+}
+
+/// Daemon is reloading config
+pub fn reloading() {
+    notify("RELOADING=1");
+}
+
+/// Daemon is stopping
+pub fn stopping() {
+    notify("STOPPING=1");
+}
+
+/// Status text shown by systemctl status
+pub fn status(text: &str) {
+    notify(&format!("STATUS={}", text));
+}
+
+/// True if systemd watchdog is enabled for this process
+pub fn watchdog_enabled() -> bool {
+    if env::var("WATCHDOG_USEC").is_err() {
+        return false;
+    }
+
+    match env::var("WATCHDOG_PID") {
+        Ok(p) => p == std::process::id().to_string(),
+        Err(_) => true,
+    }
+}
+
+/// Pings systemd watchdog if enabled
+pub fn watchdog() {
+    if watchdog_enabled() {
+        notify("WATCHDOG=1");
+    }
+}
+
+/// Sleeps seconds and pings watchdog every second
+/// so long delays do not trigger the watchdog.
+pub fn sleep(seconds: u64) {
+    for _ in 0..seconds {
+        watchdog();
+        thread::sleep(time::Duration::from_secs(1));
+    }
+    watchdog();
+}
+
+/// Journal priority for event level
+pub fn priority(level: &Level) -> &'static str {
+    match *level {
+        Level::Error => "3",
+        Level::Warning => "4",
+        Level::Info => "6",
+    }
+}
+
+/// Encodes fields with the journald native protocol,
+/// values with newlines are sent length prefixed.
+fn journal_entry(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut entry: Vec<u8> = Vec::new();
+
+    for &(k, v) in fields {
+        entry.extend_from_slice(k.as_bytes());
+        if v.contains('\n') {
+            entry.push(b'\n');
+            let len = v.len() as u64;
+            for i in 0..8 {
+                entry.push((len >> (i * 8)) as u8);
+            }
+        }
+        else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(v.as_bytes());
+        entry.push(b'\n');
+    }
+
+    entry
+}
+
+/// Sends one entry to journald, field names must be
+/// upper case, ex: MESSAGE, PRIORITY, GGSNAP_SESSION.
+/// SYSLOG_IDENTIFIER is set to ggsnap.
+pub fn journal_send(fields: &[(&str, &str)]) -> Result<(), String> {
+    let mut all: Vec<(&str, &str)> = vec![("SYSLOG_IDENTIFIER", "ggsnap")];
+    all.extend_from_slice(fields);
+
+    let sock = match UnixDatagram::unbound() {
+        Ok(s) => s,
+        Err(e) => return Err(format!("Error creating journal socket: {}", e)),
+    };
+
+    match sock.send_to(&journal_entry(&all), JOURNAL_SOCKET) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error sending to journal: {}: {}", JOURNAL_SOCKET, e)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notify_socket() {
+        let path = env::temp_dir().join(format!("ggsnap_notify_{}", std::process::id()));
+        let sock = UnixDatagram::bind(&path).unwrap();
+
+        env::set_var("NOTIFY_SOCKET", &path);
+        assert!(notify("READY=1"));
+        env::remove_var("NOTIFY_SOCKET");
+        assert!(!notify("READY=1"));
+
+        let mut buf = [0; 64];
+        let n = sock.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn journal_encoding() {
+        assert_eq!(journal_entry(&[("MESSAGE", "snapshot created"), ("PRIORITY", "6")]),
+                   b"MESSAGE=snapshot created\nPRIORITY=6\n".to_vec());
+        assert_eq!(journal_entry(&[("MESSAGE", "a\nb")]),
+                   b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n".to_vec());
+        assert_eq!(priority(&Level::Warning), "4");
+    }
+}
@@ -0,0 +1,161 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+use chrono::prelude::*;
+use chrono::Duration;
+use { parse_snapshot_times, Config, HostType };
+
+/// Retention tiers from config settings
+/// number_days_every_day, number_weeks_with_one
+/// and number_months_total
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tier {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Tier {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Tier::Daily => "daily",
+            Tier::Weekly => "weekly",
+            Tier::Monthly => "monthly",
+        }
+    }
+}
+
+/// A day, week or month where one snapshot is kept,
+/// start and end are included. Snapshots are sorted
+/// newest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Period {
+    pub tier: Tier,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub snapshots: Vec<String>,
+}
+
+fn period(tier: Tier, start: NaiveDate, end: NaiveDate) -> Period {
+    Period { tier: tier, start: start, end: end, snapshots: Vec::new() }
+}
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap()
+}
+
+fn last_of_previous_month(date: NaiveDate) -> NaiveDate {
+    first_of_month(date).pred_opt().unwrap()
+}
+
+/// Oldest day kept, today number_months_total months ago
+fn oldest_kept(config: &Config, today: NaiveDate) -> NaiveDate {
+    let months = today.year() * 12 + today.month0() as i32 - config.snapshot.number_months_total as i32;
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+    let mut day = today.day();
+
+    loop {
+        if let Some(d) = NaiveDate::from_ymd_opt(year, month, day) {
+            return d;
+        }
+        day -= 1;
+    }
+}
+
+/// Periods of all tiers, newest first, laid out the same
+/// way as when old snapshots are removed: days from today,
+/// weeks after days and months after weeks until
+/// number_months_total months ago.
+pub fn periods(config: &Config, today: NaiveDate) -> Vec<Period> {
+    let mut periods: Vec<Period> = Vec::new();
+    let days = config.snapshot.number_days_every_day as i64;
+    let weeks = config.snapshot.number_weeks_with_one as i64;
+
+    for d in 0..days {
+        let day = today - Duration::days(d);
+        periods.push(period(Tier::Daily, day, day));
+    }
+
+    let week_end = today - Duration::days(days);
+    for w in 0..weeks {
+        let end = week_end - Duration::weeks(w);
+        periods.push(period(Tier::Weekly, end - Duration::days(6), end));
+    }
+
+    let oldest = oldest_kept(config, today);
+    let mut end = week_end - Duration::weeks(weeks);
+
+    while end >= oldest && config.snapshot.number_months_total > 0 {
+        let start = if first_of_month(end) < oldest { oldest } else { first_of_month(end) };
+        periods.push(period(Tier::Monthly, start, end));
+        end = last_of_previous_month(end);
+    }
+
+    periods
+}
+
+/// Adds snapshots with time to the periods they belong to
+pub fn assign(periods: &mut Vec<Period>, snap_times: &[(String, NaiveDateTime)]) {
+    for p in periods.iter_mut() {
+        let mut found: Vec<&(String, NaiveDateTime)> = snap_times.iter()
+            .filter(|&&(_, ref t)| t.date() >= p.start && t.date() <= p.end).collect();
+        found.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+        p.snapshots = found.iter().map(|&&(ref s, _)| s.clone()).collect();
+    }
+}
+
+/// Periods of all tiers with the snapshots created by
+/// ggsnap on master or slave, days in config timezone.
+pub fn snapshot_periods(config: &Config, snaps: &Vec<String>, host_type: &HostType, today: NaiveDate) -> Vec<Period> {
+    let mut p = periods(config, today);
+    assign(&mut p, &parse_snapshot_times(config, snaps, host_type));
+    p
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tier_periods() {
+        let mut config = Config::default_config();
+        config.snapshot.number_days_every_day = 2;
+        config.snapshot.number_weeks_with_one = 1;
+        config.snapshot.number_months_total = 2;
+
+        let today = NaiveDate::from_ymd_opt(2018, 3, 31).unwrap();
+        let mut p = periods(&config, today);
+        let ranges: Vec<(&str, String, String)> = p.iter().map(|p| (p.tier.name(), p.start.to_string(), p.end.to_string())).collect();
+        assert_eq!(ranges, vec![("daily", String::from("2018-03-31"), String::from("2018-03-31")),
+                                ("daily", String::from("2018-03-30"), String::from("2018-03-30")),
+                                ("weekly", String::from("2018-03-23"), String::from("2018-03-29")),
+                                ("monthly", String::from("2018-03-01"), String::from("2018-03-22")),
+                                ("monthly", String::from("2018-02-01"), String::from("2018-02-28")),
+                                ("monthly", String::from("2018-01-31"), String::from("2018-01-31"))]);
+
+        let t = |d: u32, h: u32| NaiveDate::from_ymd_opt(2018, 3, d).unwrap().and_hms_opt(h, 0, 0).unwrap();
+        assign(&mut p, &[(String::from("a"), t(31, 1)), (String::from("b"), t(31, 2)), (String::from("c"), t(25, 0))]);
+        assert_eq!(p[0].snapshots, vec![String::from("b"), String::from("a")]);
+        assert!(p[1].snapshots.is_empty());
+        assert_eq!(p[2].snapshots, vec![String::from("c")]);
+    }
+}
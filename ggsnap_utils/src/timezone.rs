@@ -0,0 +1,119 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+use chrono::prelude::*;
+use chrono_tz::Tz;
+use Config;
+
+/// Time zone from config setting timezone.
+/// Used for {zone:FORMAT} in snapshot name templates
+/// and to decide which day a snapshot belongs to
+/// when old snapshots are removed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SnapTimeZone {
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+impl SnapTimeZone {
+    /// Valid values are: local, UTC or
+    /// an IANA time zone, ex: Europe/Stockholm
+    pub fn parse(zone: &str) -> Result<SnapTimeZone, String> {
+        if zone.to_lowercase() == "local" {
+            Ok(SnapTimeZone::Local)
+        }
+        else if zone.to_lowercase() == "utc" {
+            Ok(SnapTimeZone::Utc)
+        }
+        else {
+            match zone.parse::<Tz>() {
+                Ok(tz) => Ok(SnapTimeZone::Named(tz)),
+                Err(_) => Err(format!("Error in parameter: timezone, unknown time zone: {}", zone)),
+            }
+        }
+    }
+
+    /// Wall clock time in time zone
+    pub fn naive_from_utc(&self, time: DateTime<Utc>) -> NaiveDateTime {
+        match *self {
+            SnapTimeZone::Local => time.with_timezone(&Local).naive_local(),
+            SnapTimeZone::Utc => time.naive_utc(),
+            SnapTimeZone::Named(tz) => time.with_timezone(&tz).naive_local(),
+        }
+    }
+
+    /// Converts wall clock time in time zone to UTC,
+    /// if time is ambiguous the earliest is used and
+    /// None is returned for times that does not exist.
+    pub fn naive_to_utc(&self, time: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        match *self {
+            SnapTimeZone::Local => Local.from_local_datetime(time).earliest().map(|t| t.with_timezone(&Utc)),
+            SnapTimeZone::Utc => Some(DateTime::<Utc>::from_utc(*time, Utc)),
+            SnapTimeZone::Named(tz) => tz.from_local_datetime(time).earliest().map(|t| t.with_timezone(&Utc)),
+        }
+    }
+
+    /// Current date in time zone
+    pub fn today(&self) -> NaiveDate {
+        self.naive_from_utc(Utc::now()).date()
+    }
+}
+
+/// Time zone from config setting timezone,
+/// setting is checked when config file is read.
+pub fn config_timezone(config: &Config) -> SnapTimeZone {
+    match config.snapshot.timezone {
+        Some(ref z) => SnapTimeZone::parse(z).expect("Invalid timezone"),
+        None => SnapTimeZone::Local,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_zone() {
+        let tz = SnapTimeZone::parse("Europe/Stockholm").unwrap();
+        let summer = Utc.ymd(2018, 7, 1).and_hms(22, 30, 0);
+        let winter = Utc.ymd(2018, 1, 1).and_hms(22, 30, 0);
+
+        assert_eq!(tz.naive_from_utc(summer), NaiveDate::from_ymd(2018, 7, 2).and_hms(0, 30, 0));
+        assert_eq!(tz.naive_from_utc(winter), NaiveDate::from_ymd(2018, 1, 1).and_hms(23, 30, 0));
+        assert_eq!(tz.naive_to_utc(&tz.naive_from_utc(summer)), Some(summer));
+
+        // Clocks are turned forward 2018-03-25 02:00 and back 2018-10-28 03:00
+        assert_eq!(tz.naive_to_utc(&NaiveDate::from_ymd(2018, 3, 25).and_hms(2, 30, 0)), None);
+        assert_eq!(tz.naive_to_utc(&NaiveDate::from_ymd(2018, 10, 28).and_hms(2, 30, 0)),
+                   Some(Utc.ymd(2018, 10, 28).and_hms(0, 30, 0)));
+    }
+
+    #[test]
+    fn parse_zone() {
+        assert_eq!(SnapTimeZone::parse("local"), Ok(SnapTimeZone::Local));
+        assert_eq!(SnapTimeZone::parse("UTC"), Ok(SnapTimeZone::Utc));
+        assert!(SnapTimeZone::parse("Europe/Nowhere").is_err());
+
+        let t = NaiveDate::from_ymd(2018, 2, 16).and_hms(12, 4, 38);
+        assert_eq!(SnapTimeZone::Utc.naive_from_utc(SnapTimeZone::Utc.naive_to_utc(&t).unwrap()), t);
+    }
+}
@@ -0,0 +1,219 @@
+/////////////////////////////////////////////////////////////////////////////////
+//                                                                             //
+//    ggsnap_utils, Common library for ggsnap and ggsnap_slave.                //
+//    Copyright (C) 2018  Marcus Pedersén marcus.pedersen@slu.se               //
+//                                                                             //
+//    This program is free software: you can redistribute it and/or modify     //
+//    it under the terms of the GNU General Public License as published by     //
+//    the Free Software Foundation, either version 3 of the License, or        //
+//    (at your option) any later version.                                      //
+//                                                                             //
+//    This program is distributed in the hope that it will be useful,          //
+//    but WITHOUT ANY WARRANTY; without even the implied warranty of           //
+//    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the            //
+//    GNU General Public License for more details.                             //
+//                                                                             //
+//    You should have received a copy of the GNU General Public License        //
+//    along with this program.  If not, see <http://www.gnu.org/licenses/>.    //
+//                                                                             //
+/////////////////////////////////////////////////////////////////////////////////
+
+
+
+use serde_json;
+use mail::run_date;
+use report::RunReport;
+
+/// Default timeout in seconds of a webhook request
+pub static DEFAULT_WEBHOOK_TIMEOUT: u64 = 10;
+
+/// Struct that holds information about sub section [notify_webhook]
+/// in config file
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct NotifyWebhook {
+    pub urls: Vec<String>,
+    pub on_success: Option<bool>,
+    pub on_failure: Option<bool>,
+    pub format: Option<String>,
+    pub template: Option<String>,
+    pub content_type: Option<String>,
+    pub timeout: Option<u64>,
+}
+
+impl NotifyWebhook {
+    /// True if webhooks are called for result of run
+    pub fn notifies(&self, success: bool) -> bool {
+        if success {
+            self.on_success.unwrap_or(true)
+        }
+        else {
+            self.on_failure.unwrap_or(true)
+        }
+    }
+}
+
+/// Parts of a http or https URL
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub https: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    /// Parses URL as scheme://host[:port][/path]
+    pub fn parse(url: &str) -> Result<Url, String> {
+        let (https, rest) = if url.starts_with("https://") {
+            (true, &url[8..])
+        }
+        else if url.starts_with("http://") {
+            (false, &url[7..])
+        }
+        else {
+            return Err(format!("Error in webhook URL: {}, must start with http:// or https://", url));
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority.rfind(':') {
+            Some(i) => match authority[i + 1..].parse::<u16>() {
+                Ok(p) => (&authority[..i], p),
+                Err(_) => return Err(format!("Error in webhook URL: {}, invalid port", url)),
+            },
+            None => (authority, if https { 443 } else { 80 }),
+        };
+
+        if host.is_empty() || host.contains('@') {
+            return Err(format!("Error in webhook URL: {}, invalid host", url));
+        }
+
+        Ok(Url { https: https, host: host.to_string(), port: port, path: path.to_string() })
+    }
+
+    /// Value of Host header, port is only
+    /// added if it is not default of scheme
+    pub fn host_header(&self) -> String {
+        if self.port == if self.https { 443 } else { 80 } {
+            self.host.clone()
+        }
+        else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Checks format, template and URLs in [notify_webhook]
+pub fn check_webhook(webhook: &NotifyWebhook) -> Result<(), String> {
+    match webhook.format.as_ref().map(|f| f.as_str()) {
+        None | Some("json") => (),
+        Some("template") => if webhook.template.as_ref().map(|t| t.is_empty()).unwrap_or(true) {
+            return Err(String::from("Error in parameter: notify_webhook.template, must be set when format is template"));
+        },
+        Some(_) => return Err(String::from("Error in parameter: notify_webhook.format, valid values are: json, template")),
+    }
+
+    for u in webhook.urls.iter() {
+        if let Err(e) = Url::parse(u) {
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Value as content of a JSON string
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or(String::from("\"\""));
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Fills in template with values from report, values
+/// are escaped to be used inside JSON strings.
+/// Fields: {status}, {session}, {snapshot}, {date},
+/// {duration}, {deleted} (number of snapshots) and
+/// {errors} (separated by ; ).
+pub fn render(template: &str, report: &RunReport) -> String {
+    let date = run_date(&report.timestamp);
+    let errors = if report.errors.is_empty() {
+        String::from("None")
+    }
+    else {
+        report.errors.join("; ")
+    };
+
+    template.replace("{status}", if report.success { "OK" } else { "Error" })
+            .replace("{session}", &json_escape(&report.session))
+            .replace("{snapshot}", &json_escape(&report.snapshot.clone().unwrap_or(String::from("None"))))
+            .replace("{date}", &date)
+            .replace("{duration}", &format!("{:.1}", report.duration_seconds))
+            .replace("{deleted}", &report.deleted.len().to_string())
+            .replace("{errors}", &json_escape(&errors))
+}
+
+/// Body posted to webhooks, the run report as JSON
+/// or the rendered template.
+pub fn payload(webhook: &NotifyWebhook, report: &RunReport) -> Result<String, String> {
+    match webhook.format.as_ref().map(|f| f.as_str()) {
+        Some("template") => Ok(render(&webhook.template.clone().unwrap_or(String::new()), report)),
+        _ => match serde_json::to_string(report) {
+            Ok(j) => Ok(j),
+            Err(e) => Err(format!("Error creating webhook payload: {}", e)),
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use event::EventLog;
+    use HostType;
+
+    #[test]
+    fn webhook_payload() {
+        assert_eq!(Url::parse("https://hooks.example.com/services/T0/B0").unwrap(),
+                   Url { https: true, host: String::from("hooks.example.com"), port: 443, path: String::from("/services/T0/B0") });
+        assert_eq!(Url::parse("http://127.0.0.1:8065").unwrap(),
+                   Url { https: false, host: String::from("127.0.0.1"), port: 8065, path: String::from("/") });
+        assert_eq!(Url::parse("http://127.0.0.1:8065").unwrap().host_header(), "127.0.0.1:8065");
+        assert_eq!(Url::parse("https://hooks.example.com:443/").unwrap().host_header(), "hooks.example.com");
+        assert_eq!(Url::parse("http://hooks.example.com:443/").unwrap().host_header(), "hooks.example.com:443");
+        assert!(Url::parse("ftp://example.com").is_err());
+        assert!(Url::parse("http://example.com:port/").is_err());
+
+        let mut webhook = NotifyWebhook {
+            urls: vec![String::from("https://hooks.example.com/services/T0/B0")],
+            on_success: Some(false),
+            on_failure: None,
+            format: Some(String::from("template")),
+            template: None,
+            content_type: None,
+            timeout: None,
+        };
+        assert!(check_webhook(&webhook).is_err());
+        assert!(!webhook.notifies(true));
+        assert!(webhook.notifies(false));
+
+        let mut log = EventLog::new("vol root@slave::svol");
+        log.set_phase("resume");
+        log.error(HostType::Master, "Error resuming \"geo-replication\"");
+        let report = RunReport::from_log(&log, None, false, Utc::now());
+
+        webhook.template = Some(String::from("{\"text\": \"ggsnap {status}: {session}, snapshot {snapshot}, errors: {errors}\"}"));
+        assert!(check_webhook(&webhook).is_ok());
+        let body = payload(&webhook, &report).unwrap();
+        assert_eq!(body, "{\"text\": \"ggsnap Error: vol root@slave::svol, snapshot None, errors: Master: Error resuming \\\"geo-replication\\\"\"}");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["text"], "ggsnap Error: vol root@slave::svol, snapshot None, errors: Master: Error resuming \"geo-replication\"");
+
+        webhook.format = None;
+        let json: serde_json::Value = serde_json::from_str(&payload(&webhook, &report).unwrap()).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["session"], "vol root@slave::svol");
+    }
+}